@@ -0,0 +1,184 @@
+//! Kernel command line.
+//!
+//! The bootloader passes a command line in the device tree's `/chosen/bootargs`
+//! (`-append` in QEMU). It starts with any number of kernel parameters, each of
+//! the form `name=value`, where `name` is listed in [`PARAMS`]:
+//!
+//! ```text
+//! sched=fcfs user_pool_pages=512 args-many a b c
+//! ```
+//!
+//! Parsing stops at the first word that is not a registered parameter, or right
+//! after a `--`. Everything from there on is left untouched for the init program
+//! or the test being run, see [`args`].
+//!
+//! The command line is parsed before memory management is up, so it is copied
+//! to a static buffer instead of the heap. This also keeps it valid after the
+//! device tree is reclaimed.
+
+use core::str;
+
+use crate::sbi::{console, timer};
+use crate::sync::OnceCell;
+use crate::{fs, mem, thread};
+use crate::{OsError, Result};
+
+/// Maximum length of the command line, longer ones are truncated.
+const CMDLINE_SIZE: usize = 1024;
+
+/// Maximum number of parameters on the command line.
+const MAX_PARAMS: usize = 16;
+
+/// A kernel parameter.
+pub struct Param {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// Validates the value and applies it. The value stays available through [`param`].
+    pub set: fn(&'static str) -> Result<()>,
+}
+
+/// All kernel parameters that can be given on the command line.
+pub static PARAMS: [Param; 9] = [
+    Param {
+        name: "init",
+        help: "program to run at boot, instead of the kernel shell",
+        set: |_| Ok(()),
+    },
+    Param {
+        name: "loglevel",
+        help: "console verbosity: error, warn or info",
+        set: console::set_loglevel,
+    },
+    Param {
+        name: "sched",
+        help: "thread scheduler: fcfs or priority",
+        set: thread::scheduler::set_policy,
+    },
+    Param {
+        name: "replace",
+        help: "page replacement: clock, second-chance, aging or fifo",
+        set: mem::replace::set_policy,
+    },
+    Param {
+        name: "swap",
+        help: "swap file on disk, or `off` to disable swapping",
+        set: fs::disk::Swap::set_file,
+    },
+    Param {
+        name: "zswap_pages",
+        help: "number of kernel pages for compressed swap, or 0 to disable it",
+        set: mem::zswap::set_pool_pages,
+    },
+    Param {
+        name: "ksm_interval",
+        help: "ticks between scans for identical pages to merge, or 0 to disable merging",
+        set: mem::frametable::ksm::set_interval,
+    },
+    Param {
+        name: "user_pool_pages",
+        help: "number of physical pages reserved for user memory",
+        set: mem::palloc::set_user_pool_pages,
+    },
+    Param {
+        name: "ticks_per_sec",
+        help: "frequency of timer interrupts",
+        set: timer::set_ticks_per_sec,
+    },
+];
+
+struct Cmdline {
+    /// The `(name, value)` pairs of all parameters found.
+    params: [(&'static str, &'static str); MAX_PARAMS],
+    nparams: usize,
+    /// The rest of the command line.
+    args: &'static str,
+}
+
+static CMDLINE: OnceCell<Cmdline> = OnceCell::new();
+
+/// Copy the command line `bootargs` and apply the kernel parameters in it.
+///
+/// Invalid parameters are reported and ignored.
+pub fn init(bootargs: &str) {
+    CMDLINE.init(|| {
+        static mut BUF: [u8; CMDLINE_SIZE] = [0; CMDLINE_SIZE];
+
+        let mut len = bootargs.len().min(CMDLINE_SIZE);
+        while !bootargs.is_char_boundary(len) {
+            len -= 1;
+        }
+        let cmdline: &'static str = unsafe {
+            BUF[..len].copy_from_slice(&bootargs.as_bytes()[..len]);
+            str::from_utf8_unchecked(&BUF[..len])
+        };
+
+        let mut params = [("", ""); MAX_PARAMS];
+        let mut nparams = 0;
+        let mut rest = cmdline.trim_start_matches(' ');
+        while let Some(word) = rest.split(' ').next().filter(|w| !w.is_empty()) {
+            if word == "--" {
+                rest = &rest[word.len()..];
+                break;
+            }
+
+            let param = word.split_once('=').and_then(|(name, value)| {
+                PARAMS.iter().find(|p| p.name == name).map(|p| (p, value))
+            });
+            let (param, value) = match param {
+                Some(param) => param,
+                None => break,
+            };
+
+            if nparams == MAX_PARAMS {
+                kwarn!("cmdline: too many parameters, ignoring {}", word);
+            } else if let Err(e) = (param.set)(value) {
+                kwarn!(
+                    "cmdline: invalid {}={}: {:?} ({})",
+                    param.name,
+                    value,
+                    e,
+                    param.help
+                );
+            } else {
+                params[nparams] = (param.name, value);
+                nparams += 1;
+            }
+
+            rest = rest[word.len()..].trim_start_matches(' ');
+        }
+
+        Cmdline {
+            params,
+            nparams,
+            args: rest.strip_prefix(' ').unwrap_or(rest),
+        }
+    });
+}
+
+/// The value of kernel parameter `name`, if given. The last one wins.
+pub fn param(name: &str) -> Option<&'static str> {
+    let cmdline = CMDLINE.get();
+    cmdline.params[..cmdline.nparams]
+        .iter()
+        .rev()
+        .find(|(n, _)| *n == name)
+        .map(|(_, value)| *value)
+}
+
+/// The part of the command line after the kernel parameters.
+pub fn args() -> &'static str {
+    CMDLINE.get().args
+}
+
+/// Parse a positive number, for parameters counting something.
+pub fn parse_count(value: &str) -> Result<usize> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(OsError::InvalidArgument),
+        Ok(n) => Ok(n),
+    }
+}
+
+/// Parse a number, for parameters where 0 turns something off.
+pub fn parse_number(value: &str) -> Result<usize> {
+    value.parse().map_err(|_| OsError::InvalidArgument)
+}
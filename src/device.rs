@@ -1,2 +1,3 @@
 pub mod plic;
+pub mod tty;
 pub mod virtio;
@@ -7,12 +7,10 @@
 //! For more information, see <https://github.com/riscv/riscv-plic-spec/blob/master/riscv-plic.adoc>.
 //!
 
-use crate::mem::PLIC_BASE;
+use crate::device::virtio;
+use crate::mem::plic_base;
 use crate::sync::OnceCell;
 
-/// Hard-coded Virtio device 0 interrupt identifier (ID).
-pub const VIRTIO0_ID: usize = 1;
-
 // Hart ID.
 static HART_ID: OnceCell<usize> = OnceCell::new();
 
@@ -23,12 +21,12 @@ pub fn init(hart_id: usize) {
         // Set this hart's S-mode priority threshold.
         write_threshold(0);
 
-        // Set interrupt priority of Virtio device 0.
+        // Set interrupt priority of the virtio block device.
         // 0 means no interrupt. Any positive value is OK.
-        write_priority(VIRTIO0_ID, 1);
+        write_priority(virtio::irq(), 1);
 
-        // Enable this hart to receive interrupts from Virtio device 0.
-        set_enable(VIRTIO0_ID);
+        // Enable this hart to receive interrupts from the virtio block device.
+        set_enable(virtio::irq());
     }
 }
 
@@ -95,20 +93,20 @@ fn hart_id() -> usize {
 // Address calculation helper functions.
 // See "Memory Map" section in the spec for more information.
 fn get_priority_ptr(id: usize) -> *mut u32 {
-    (PLIC_BASE + 0x4 * id) as _
+    (plic_base() + 0x4 * id) as _
 }
 fn get_pending_ptr(id: usize) -> *const u32 {
-    (PLIC_BASE + 0x1000 + 0x4 * (id / 32)) as _
+    (plic_base() + 0x1000 + 0x4 * (id / 32)) as _
 }
 fn get_enable_ptr(hart_id: usize, id: usize) -> *mut u32 {
-    (PLIC_BASE + 0x2000 + 0x80 * (2 * hart_id + 1) + 0x4 * (id / 32)) as _
+    (plic_base() + 0x2000 + 0x80 * (2 * hart_id + 1) + 0x4 * (id / 32)) as _
 }
 fn get_threshold_ptr(hart_id: usize) -> *mut u32 {
-    (PLIC_BASE + 0x200000 + 0x1000 * (2 * hart_id + 1)) as _
+    (plic_base() + 0x200000 + 0x1000 * (2 * hart_id + 1)) as _
 }
 fn get_claim_ptr(hart_id: usize) -> *const u32 {
     get_completion_ptr(hart_id)
 }
 fn get_completion_ptr(hart_id: usize) -> *mut u32 {
-    (PLIC_BASE + 0x200000 + 0x1000 * (2 * hart_id + 1) + 0x4) as _
+    (plic_base() + 0x200000 + 0x1000 * (2 * hart_id + 1) + 0x4) as _
 }
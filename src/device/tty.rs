@@ -0,0 +1,257 @@
+//! Console TTY and its line discipline.
+//!
+//! All console input goes through [`Tty`]. A kernel thread polls the SBI
+//! console every tick, so that `^C` is seen even when nobody reads, and cooks
+//! raw bytes according to the current [`TtyMode`]:
+//!
+//! - In canonical mode (`ICANON`), input is collected line by line. Erase
+//!   (`^H`, `DEL`) and kill (`^U`) edit the pending line, and a read returns
+//!   only after a whole line has been typed. `^D` on an empty line is EOF.
+//! - In raw mode, every byte is handed to the reader as soon as it arrives.
+//!
+//! With `ECHO` set, typed characters are written back to the console. With
+//! `ISIG` set, `^C` discards pending input and sends `SIGINT` to the foreground
+//! process.
+//!
+//! Output is binary-safe: bytes are passed to the console untouched.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::sbi::{console::stdout, console_getchar, console_putchar};
+use crate::sync::{Condvar, Intr, Lazy, Mutex};
+use crate::thread;
+use crate::userproc::{self, signal};
+use crate::{OsError, Result};
+
+/// `^C`
+const VINTR: u8 = 0x03;
+/// `^D`
+const VEOF: u8 = 0x04;
+/// `^H`
+const VERASE: u8 = 0x08;
+/// `^U`
+const VKILL: u8 = 0x15;
+/// `DEL`, sent by most terminals for backspace.
+const VERASE2: u8 = 0x7f;
+
+/// Get the tty mode, written to the user pointer in `arg`.
+pub const TCGETMODE: usize = 1;
+/// Set the tty mode to the flags in `arg`.
+pub const TCSETMODE: usize = 2;
+
+bitflags::bitflags! {
+    pub struct TtyMode: usize {
+        /// Canonical (line-buffered) input with line editing.
+        const ICANON = 0b001;
+        /// Echo input characters.
+        const ECHO = 0b010;
+        /// Turn `^C` into a `SIGINT` to the foreground process.
+        const ISIG = 0b100;
+    }
+}
+
+impl Default for TtyMode {
+    fn default() -> Self {
+        Self::ICANON | Self::ECHO | Self::ISIG
+    }
+}
+
+struct TtyInner {
+    mode: TtyMode,
+    /// The line being edited (canonical mode only).
+    line: Vec<u8>,
+    /// Bytes ready to be consumed by readers.
+    ready: VecDeque<u8>,
+    /// A pending end-of-file, consumed by the next read.
+    eof: bool,
+    /// Pid of the foreground process, which receives `^C`.
+    foreground: Option<isize>,
+    /// Number of `^C` typed so far.
+    interrupts: usize,
+}
+
+/// The one and only console tty, and readers waiting for input.
+pub struct Tty(Mutex<TtyInner, Intr>, Condvar);
+
+impl Tty {
+    pub fn get() -> &'static Self {
+        static TTY: Lazy<Tty> = Lazy::new(|| {
+            Tty(
+                Mutex::new(TtyInner {
+                    mode: TtyMode::default(),
+                    line: Vec::new(),
+                    ready: VecDeque::new(),
+                    eof: false,
+                    foreground: None,
+                    interrupts: 0,
+                }),
+                Condvar::new(),
+            )
+        });
+        &TTY
+    }
+
+    /// Starts the thread feeding console input to the line discipline.
+    pub fn start() {
+        thread::spawn("tty", || loop {
+            while let Some(c) = Self::poll() {
+                Self::get().input(c);
+            }
+            thread::sleep(1);
+        });
+    }
+
+    pub fn mode(&self) -> TtyMode {
+        self.0.lock().mode
+    }
+
+    /// Switch between canonical and raw mode, or toggle echo and signals.
+    ///
+    /// Leaving canonical mode hands the partially edited line to readers.
+    pub fn set_mode(&self, mode: TtyMode) {
+        let mut tty = self.0.lock();
+        if !mode.contains(TtyMode::ICANON) {
+            let line = core::mem::take(&mut tty.line);
+            tty.ready.extend(line);
+            self.1.notify_all();
+        }
+        tty.mode = mode;
+    }
+
+    pub fn foreground(&self) -> Option<isize> {
+        self.0.lock().foreground
+    }
+
+    pub fn set_foreground(&self, pid: Option<isize>) {
+        self.0.lock().foreground = pid;
+    }
+
+    /// Read from the console into `buf`, blocking until some input is ready.
+    ///
+    /// ## Return
+    /// - `Ok(0)`: end of file (`^D` on an empty line).
+    /// - `Ok(n)`: `n` bytes were read. In canonical mode, at most one line.
+    /// - `Err(Interrupted)`: `^C` was typed while the current process is in the
+    ///   foreground, or the current thread is interrupted, see [`thread::interrupt`].
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let pid = userproc::current().pid();
+        let mut tty = self.0.lock();
+        let interrupts = tty.interrupts;
+        loop {
+            if tty.interrupts != interrupts && tty.foreground == Some(pid) {
+                return Err(OsError::Interrupted);
+            }
+            if tty.eof {
+                tty.eof = false;
+                return Ok(0);
+            }
+            if !tty.ready.is_empty() {
+                let mut cnt = 0;
+                while cnt < buf.len() {
+                    match tty.ready.pop_front() {
+                        Some(c) => {
+                            buf[cnt] = c;
+                            cnt += 1;
+                            if c == b'\n' && tty.mode.contains(TtyMode::ICANON) {
+                                break;
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                return Ok(cnt);
+            }
+            self.1.wait_interruptible(&mut tty)?;
+        }
+    }
+
+    /// Write `buf` to the console as is.
+    pub fn write(&self, buf: &[u8]) -> usize {
+        let _lock = stdout().lock();
+        buf.iter().for_each(|&c| console_putchar(c as usize));
+        buf.len()
+    }
+
+    /// Poll the SBI console once.
+    fn poll() -> Option<u8> {
+        match console_getchar() as isize {
+            c if c <= 0 => None,
+            c => Some(c as u8),
+        }
+    }
+
+    /// Feed one byte into the line discipline, waking up readers.
+    ///
+    /// Echoes are written, and `SIGINT` sent, once the tty is unlocked.
+    pub fn input(&self, c: u8) {
+        let mut echo = Vec::new();
+        let mut tty = self.0.lock();
+
+        if tty.mode.contains(TtyMode::ISIG) && c == VINTR {
+            echo.extend_from_slice(b"^C\n");
+            tty.line.clear();
+            tty.ready.clear();
+            tty.interrupts += 1;
+            self.1.notify_all();
+            let (mode, foreground) = (tty.mode, tty.foreground);
+            drop(tty);
+            self.echo(mode, &echo);
+            if let Some(pid) = foreground {
+                let _ = signal::kill(pid, signal::SIGINT);
+            }
+            return;
+        }
+
+        if !tty.mode.contains(TtyMode::ICANON) {
+            echo.push(c);
+            tty.ready.push_back(c);
+        } else {
+            match c {
+                VEOF => {
+                    if tty.line.is_empty() {
+                        tty.eof = true;
+                    } else {
+                        let line = core::mem::take(&mut tty.line);
+                        tty.ready.extend(line);
+                    }
+                }
+                VERASE | VERASE2 => {
+                    if tty.line.pop().is_some() {
+                        echo.extend_from_slice(b"\x08 \x08");
+                    }
+                }
+                VKILL => {
+                    let n = tty.line.len();
+                    tty.line.clear();
+                    (0..n).for_each(|_| echo.extend_from_slice(b"\x08 \x08"));
+                }
+                b'\r' | b'\n' => {
+                    echo.push(b'\n');
+                    let line = core::mem::take(&mut tty.line);
+                    tty.ready.extend(line);
+                    tty.ready.push_back(b'\n');
+                }
+                _ => {
+                    echo.push(c);
+                    tty.line.push(c);
+                }
+            }
+        }
+
+        self.1.notify_all();
+        let mode = tty.mode;
+        drop(tty);
+        self.echo(mode, &echo);
+    }
+
+    fn echo(&self, mode: TtyMode, s: &[u8]) {
+        if mode.contains(TtyMode::ECHO) {
+            self.write(s);
+        }
+    }
+}
@@ -7,8 +7,9 @@
 use alloc::boxed::Box;
 use core::{arch, ptr};
 
-use crate::mem::{MMIO_BASE, VM_OFFSET};
-use crate::sync::{Lazy, Mutex, Semaphore};
+use crate::mem::VM_OFFSET;
+use crate::platform;
+use crate::sync::{Lazy, Mutex, OnceCell, Semaphore};
 
 /* -------------------------------------------------------------------------- */
 /*                                  INTERFACE                                 */
@@ -20,29 +21,34 @@ pub const SECTOR_SIZE: usize = 512;
 /* -------------------------------------------------------------------------- */
 /*                                    MMIO                                    */
 /* -------------------------------------------------------------------------- */
-// A subset of MMIO Virtio Device Registers.
+// A subset of MMIO Virtio Device Registers, as offsets from the transport base.
 // RO = Read Only, WO = Write Only, RW = Read Write.
 // See section 4.2.2 in the spec for more information.
-const MAGIC_VALUE: *const u32 = (MMIO_BASE + 0x0) as _; // RO
-const VERSION: *const u32 = (MMIO_BASE + 0x4) as _; // RO
-const DEVICE_ID: *const u32 = (MMIO_BASE + 0x8) as _; // RO
-const DEVICE_FEATURES: *const u32 = (MMIO_BASE + 0x10) as _; // RO
-const DRIVER_FEATURES: *mut u32 = (MMIO_BASE + 0x20) as _; // WO
-const QUEUE_SEL: *mut u32 = (MMIO_BASE + 0x30) as _; // WO
-const QUEUE_NUM_MAX: *const u32 = (MMIO_BASE + 0x34) as _; // RO
-const QUEUE_NUM: *mut u32 = (MMIO_BASE + 0x38) as _; // WO
-const QUEUE_READY: *mut u32 = (MMIO_BASE + 0x44) as _; // RW
-const QUEUE_NOTIFY: *mut u32 = (MMIO_BASE + 0x50) as _; // WO
-const INTERRUPT_STATUS: *const u32 = (MMIO_BASE + 0x60) as _; // RO
-const INTERRUPT_ACK: *mut u32 = (MMIO_BASE + 0x64) as _; // WO
-const STATUS: *mut u32 = (MMIO_BASE + 0x70) as _; // RW
-const QUEUE_DESC_LOW: *mut u32 = (MMIO_BASE + 0x80) as _; // WO
-const QUEUE_DESC_HIGH: *mut u32 = (MMIO_BASE + 0x84) as _; // WO
-const QUEUE_DRIVER_LOW: *mut u32 = (MMIO_BASE + 0x90) as _; // WO
-const QUEUE_DRIVER_HIGH: *mut u32 = (MMIO_BASE + 0x94) as _; // WO
-const QUEUE_DEVICE_LOW: *mut u32 = (MMIO_BASE + 0xa0) as _; // WO
-const QUEUE_DEVICE_HIGH: *mut u32 = (MMIO_BASE + 0xa4) as _; // WO
-const CONFIG: *mut u8 = (MMIO_BASE + 0x100) as _; // RW
+const MAGIC_VALUE: usize = 0x0; // RO
+const VERSION: usize = 0x4; // RO
+const DEVICE_ID: usize = 0x8; // RO
+const DEVICE_FEATURES: usize = 0x10; // RO
+const DRIVER_FEATURES: usize = 0x20; // WO
+const QUEUE_SEL: usize = 0x30; // WO
+const QUEUE_NUM_MAX: usize = 0x34; // RO
+const QUEUE_NUM: usize = 0x38; // WO
+const QUEUE_READY: usize = 0x44; // RW
+const QUEUE_NOTIFY: usize = 0x50; // WO
+const INTERRUPT_STATUS: usize = 0x60; // RO
+const INTERRUPT_ACK: usize = 0x64; // WO
+const STATUS: usize = 0x70; // RW
+const QUEUE_DESC_LOW: usize = 0x80; // WO
+const QUEUE_DESC_HIGH: usize = 0x84; // WO
+const QUEUE_DRIVER_LOW: usize = 0x90; // WO
+const QUEUE_DRIVER_HIGH: usize = 0x94; // WO
+const QUEUE_DEVICE_LOW: usize = 0xa0; // WO
+const QUEUE_DEVICE_HIGH: usize = 0xa4; // WO
+const CONFIG: usize = 0x100; // RW
+
+// Pointer to the register at `offset` of the probed block device.
+fn reg<T>(offset: usize) -> *mut T {
+    (probe().base + offset) as _
+}
 
 // A subset of status fields.
 // See section 2.1 in the spec for more information.
@@ -55,6 +61,46 @@ bitflags::bitflags! {
     }
 }
 
+/* -------------------------------------------------------------------------- */
+/*                                   PROBING                                  */
+/* -------------------------------------------------------------------------- */
+
+// Device ID of a block device. See section 5 in the spec for more information.
+const BLOCK_DEVICE_ID: u32 = 0x2;
+const MAGIC: u32 = 0x74726976;
+
+// The virtio transport that hosts the block device.
+struct Transport {
+    base: usize, // Kernel virtual address of the registers.
+    irq: usize,  // Interrupt source ID at the PLIC.
+}
+
+// Find the block device among the virtio transports in the device tree.
+// Empty transports report a device ID of 0.
+fn probe() -> &'static Transport {
+    static TRANSPORT: OnceCell<Transport> = OnceCell::new();
+
+    TRANSPORT.get_or_init(|| {
+        platform::get()
+            .virtio()
+            .iter()
+            .map(|mmio| Transport {
+                base: mmio.reg.base + VM_OFFSET,
+                irq: mmio.irq,
+            })
+            .find(|t| unsafe {
+                ((t.base + MAGIC_VALUE) as *const u32).read_volatile() == MAGIC
+                    && ((t.base + DEVICE_ID) as *const u32).read_volatile() == BLOCK_DEVICE_ID
+            })
+            .expect("No virtio block device")
+    })
+}
+
+/// Interrupt source ID of the virtio block device.
+pub fn irq() -> usize {
+    probe().irq
+}
+
 /* -------------------------------------------------------------------------- */
 /*                                  VIRTQUEUE                                 */
 /* -------------------------------------------------------------------------- */
@@ -132,60 +178,60 @@ impl Virtio {
         unsafe {
             // Start device initialization.
             // See section 4.2.3.1 in the spec for more information.
-            let magic = MAGIC_VALUE.read_volatile();
-            assert_eq!(magic, 0x74726976);
-            let version = VERSION.read_volatile();
+            let magic = reg::<u32>(MAGIC_VALUE).read_volatile();
+            assert_eq!(magic, MAGIC);
+            let version = reg::<u32>(VERSION).read_volatile();
             assert_eq!(version, 0x2);
 
             // We only support Virtio Block Device.
             // See section 5.2 in the spec for more information.
-            let device_id = DEVICE_ID.read_volatile();
-            assert_eq!(device_id, 0x2);
+            let device_id = reg::<u32>(DEVICE_ID).read_volatile();
+            assert_eq!(device_id, BLOCK_DEVICE_ID);
 
             // Reset the device.
             let mut status = Status { bits: 0 };
-            STATUS.write_volatile(status.bits());
+            reg::<u32>(STATUS).write_volatile(status.bits());
 
             // Set the ACKNOWLEDGE status bit.
             status |= Status::ACKNOWLEDGE;
-            STATUS.write_volatile(status.bits());
+            reg::<u32>(STATUS).write_volatile(status.bits());
 
             // Set the DRIVER status bit.
             status |= Status::DRIVER;
-            STATUS.write_volatile(status.bits());
+            reg::<u32>(STATUS).write_volatile(status.bits());
 
             // Negotiate features. We don't support any feature.
-            _ = DEVICE_FEATURES.read_volatile();
-            DRIVER_FEATURES.write_volatile(0);
+            _ = reg::<u32>(DEVICE_FEATURES).read_volatile();
+            reg::<u32>(DRIVER_FEATURES).write_volatile(0);
 
             // Finish feature negotiation.
             status |= Status::FEATURES_OK;
-            STATUS.write_volatile(status.bits());
+            reg::<u32>(STATUS).write_volatile(status.bits());
 
             // Ensure the FEATURES_OK status bit is still set.
             status = Status {
-                bits: STATUS.read_volatile(),
+                bits: reg::<u32>(STATUS).read_volatile(),
             };
             assert!(status.contains(Status::FEATURES_OK));
 
             // Get capacity of the disk.
-            let capacity = (CONFIG as *mut u64).read_volatile();
+            let capacity = reg::<u64>(CONFIG).read_volatile();
             self.capacity = capacity;
 
             #[cfg(feature = "debug")]
             kprintln!("Disk capacity: {} * {}B", capacity, SECTOR_SIZE);
 
             // Select queue 0. We only use queue 0.
-            QUEUE_SEL.write_volatile(0);
+            reg::<u32>(QUEUE_SEL).write_volatile(0);
 
             // Ensure the queue is not already in use.
-            let ready = QUEUE_READY.read_volatile();
+            let ready = reg::<u32>(QUEUE_READY).read_volatile();
             assert_eq!(ready, 0);
 
             // Negotiate queue size.
-            let max_size = QUEUE_NUM_MAX.read_volatile();
+            let max_size = reg::<u32>(QUEUE_NUM_MAX).read_volatile();
             assert!(QUEUE_SIZE <= max_size as _);
-            QUEUE_NUM.write_volatile(QUEUE_SIZE as _);
+            reg::<u32>(QUEUE_NUM).write_volatile(QUEUE_SIZE as _);
 
             // Allocate and zero the queues.
             self.desc_table = Box::into_raw(Box::default());
@@ -196,19 +242,23 @@ impl Virtio {
             self.used.write(Default::default());
 
             // Tell physical addresses of the queues to the device.
-            QUEUE_DESC_LOW.write_volatile((self.desc_table as usize - VM_OFFSET) as u32);
-            QUEUE_DESC_HIGH.write_volatile((self.desc_table as usize - VM_OFFSET >> 32) as u32);
-            QUEUE_DRIVER_LOW.write_volatile((self.avail as usize - VM_OFFSET) as u32);
-            QUEUE_DRIVER_HIGH.write_volatile((self.avail as usize - VM_OFFSET >> 32) as u32);
-            QUEUE_DEVICE_LOW.write_volatile((self.used as usize - VM_OFFSET) as u32);
-            QUEUE_DEVICE_HIGH.write_volatile((self.used as usize - VM_OFFSET >> 32) as u32);
+            reg::<u32>(QUEUE_DESC_LOW)
+                .write_volatile((self.desc_table as usize - VM_OFFSET) as u32);
+            reg::<u32>(QUEUE_DESC_HIGH)
+                .write_volatile((self.desc_table as usize - VM_OFFSET >> 32) as u32);
+            reg::<u32>(QUEUE_DRIVER_LOW).write_volatile((self.avail as usize - VM_OFFSET) as u32);
+            reg::<u32>(QUEUE_DRIVER_HIGH)
+                .write_volatile((self.avail as usize - VM_OFFSET >> 32) as u32);
+            reg::<u32>(QUEUE_DEVICE_LOW).write_volatile((self.used as usize - VM_OFFSET) as u32);
+            reg::<u32>(QUEUE_DEVICE_HIGH)
+                .write_volatile((self.used as usize - VM_OFFSET >> 32) as u32);
 
             // The queue is ready after this.
-            QUEUE_READY.write_volatile(0x1);
+            reg::<u32>(QUEUE_READY).write_volatile(0x1);
 
             // The device is live after this.
             status |= Status::DRIVER_OK;
-            STATUS.write_volatile(status.bits());
+            reg::<u32>(STATUS).write_volatile(status.bits());
         }
     }
 
@@ -318,7 +368,7 @@ impl Virtio {
             );
 
             // Tell the device we've done with the interrupt.
-            INTERRUPT_ACK.write_volatile(1);
+            reg::<u32>(INTERRUPT_ACK).write_volatile(1);
         }
     }
 
@@ -356,7 +406,7 @@ impl Virtio {
                 1
             );
 
-            INTERRUPT_ACK.write_volatile(1);
+            reg::<u32>(INTERRUPT_ACK).write_volatile(1);
         }
     }
 
@@ -376,7 +426,7 @@ impl Virtio {
         arch::asm!("fence w,w");
 
         // Notify the device.
-        QUEUE_NOTIFY.write_volatile(0);
+        reg::<u32>(QUEUE_NOTIFY).write_volatile(0);
     }
 }
 
@@ -384,7 +434,7 @@ impl Virtio {
 pub fn handle_interrupt() {
     // Check interrupt status.
     // See section 4.2.3.4 in the spec for more information.
-    let status = unsafe { INTERRUPT_STATUS.read_volatile() };
+    let status = unsafe { reg::<u32>(INTERRUPT_STATUS).read_volatile() };
     assert_eq!(status, 1);
 
     // Wake up the waiting thread.
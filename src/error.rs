@@ -20,4 +20,12 @@ pub enum OsError {
     FileNotOpened = -13,
     StackOverflow = -14,
     BadMapid = -15,
+    Interrupted = -16,
+    NotTty = -17,
+    InvalidArgument = -18,
+    NoSuchProcess = -19,
+    BrokenPipe = -20,
+    WouldBlock = -21,
+    TooManyLevels = -22,
+    OutOfMemory = -23,
 }
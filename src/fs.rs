@@ -55,9 +55,15 @@ pub trait Vnode: Sync + Send {
     fn write_at(&self, buf: &[u8], off: usize) -> Result<usize>;
     fn deny_write(&self);
     fn allow_write(&self);
+    /// Bypass the page cache from now on, e.g. for the swap file, whose pages
+    /// are cached by the processes using them already.
+    fn set_uncached(&self);
 
     fn inum(&self) -> usize;
     fn len(&self) -> usize;
+    /// The frame holding the page at `off`, which is page aligned, held for
+    /// the caller, see [`PageCache`](crate::mem::pagecache::PageCache).
+    fn page(&self, off: usize) -> Result<usize>;
     fn resize(&self, size: usize) -> Result<()>;
     fn close(&self);
 }
@@ -94,6 +100,11 @@ impl File {
     pub fn inum(&self) -> usize {
         self.vnode.inum()
     }
+
+    /// The frame holding the page at `offset`, see [`Vnode::page`].
+    pub fn page(&self, offset: usize) -> Result<usize> {
+        self.vnode.page(offset)
+    }
 }
 
 impl Read for File {
@@ -143,6 +154,10 @@ impl File {
     pub fn close(&self) {
         self.vnode.close();
     }
+
+    pub fn set_uncached(&self) {
+        self.vnode.set_uncached();
+    }
 }
 
 impl Drop for File {
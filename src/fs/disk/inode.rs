@@ -8,7 +8,9 @@ use core::{cmp, mem};
 use super::{bytes_to_sectors, Inum, DISKFS};
 use crate::device::virtio::{Virtio, SECTOR_SIZE};
 use crate::fs::Vnode;
-use crate::mem::{Translate, PG_MASK, PG_SIZE};
+use crate::mem::frametable::FrameTable;
+use crate::mem::pagecache::PageCache;
+use crate::mem::{PhysAddr, Translate, PG_MASK, PG_SIZE};
 use crate::sync::Mutex;
 use crate::{OsError, Result};
 
@@ -50,6 +52,8 @@ struct InodeDesc {
     shrink_len: u32,
     /// Deny write to a running file.
     deny_write: u32,
+    /// Bypass the page cache.
+    uncached: bool,
 }
 
 impl InodeDesc {
@@ -59,6 +63,7 @@ impl InodeDesc {
             removed: false,
             deny_write: 0,
             shrink_len,
+            uncached: false,
         }
     }
 }
@@ -189,26 +194,12 @@ impl Inode {
             }
         }
     }
-}
 
-impl Vnode for Inode {
-    fn inum(&self) -> usize {
-        self.0.lock().0.sector as usize
-    }
-
-    fn len(&self) -> usize {
-        self.0.lock().1.inner.len as _
-    }
-
-    fn read_at(&self, buf: &mut [u8], mut off: usize) -> Result<usize> {
+    /// Reads from the disk at `off` into `buf`, past the page cache.
+    fn read_direct(data: &DiskInode, buf: &mut [u8], mut off: usize) -> Result<usize> {
         let mut bytes_read = 0;
         let mut buf_left = buf.len(); // Bytes left in `buf`.
 
-        // We must acquire lock during the whole process
-        // to avoid being resized by other threads.
-        let guard = self.0.lock();
-        let (_, data) = &*guard;
-
         let start = data.inner.start;
         let len = data.inner.len as usize;
 
@@ -253,6 +244,65 @@ impl Vnode for Inode {
         Ok(bytes_read)
     }
 
+    /// Reads the page at `off`, which is page aligned, from the disk. Bytes past
+    /// the end of the file are zero.
+    fn read_page(data: &DiskInode, off: usize, page: &mut [u8; PG_SIZE]) {
+        let len = data.inner.len as usize;
+        for (i, sector_buf) in page.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            let pos = off + i * SECTOR_SIZE;
+            if pos >= len {
+                sector_buf.fill(0);
+                continue;
+            }
+            let sector = data.inner.start as usize + pos / SECTOR_SIZE;
+            Virtio::read_sector(sector as _, sector_buf.try_into().unwrap());
+            if len < pos + SECTOR_SIZE {
+                sector_buf[len - pos..].fill(0);
+            }
+        }
+    }
+}
+
+impl Vnode for Inode {
+    fn inum(&self) -> usize {
+        self.0.lock().0.sector as usize
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().1.inner.len as _
+    }
+
+    fn read_at(&self, buf: &mut [u8], off: usize) -> Result<usize> {
+        let end = {
+            let guard = self.0.lock();
+            let (desc, data) = &*guard;
+            if desc.uncached {
+                // We must acquire lock during the whole process
+                // to avoid being resized by other threads.
+                return Self::read_direct(data, buf, off);
+            }
+            cmp::min(data.inner.len as usize, off + buf.len())
+        };
+
+        // Pages are copied without the lock held, as touching `buf` may fault
+        // and write pages of this very file back.
+        let mut pos = off;
+        while pos < end {
+            // Read from the cached page at `page_off`.
+            let page_off = pos & !PG_MASK;
+            let chunk_size = cmp::min(page_off + PG_SIZE, end) - pos;
+            let frame = self.page(page_off)?;
+            let page = unsafe { &*(PhysAddr::from_pa(frame).into_va() as *const [u8; PG_SIZE]) };
+            buf[pos - off..pos - off + chunk_size]
+                .copy_from_slice(&page[pos - page_off..pos - page_off + chunk_size]);
+            FrameTable::put(frame);
+
+            pos += chunk_size;
+        }
+
+        Ok(pos.saturating_sub(off))
+    }
+
     fn write_at(&self, buf: &[u8], mut off: usize) -> Result<usize> {
         if self.0.lock().0.deny_write > 0 {
             return Err(OsError::InvalidFileMode);
@@ -313,9 +363,43 @@ impl Vnode for Inode {
             bytes_written += chunk_size;
         }
 
+        if desc.uncached {
+            return Ok(bytes_written);
+        }
+
+        // Keep cached pages up to date.
+        let start = off - bytes_written;
+        let mut pos = start;
+        while pos < off {
+            let page_off = pos & !PG_MASK;
+            let chunk_size = cmp::min(page_off + PG_SIZE, off) - pos;
+            if let Some(frame) = PageCache::find(desc.sector as usize, page_off) {
+                let page =
+                    unsafe { &mut *(PhysAddr::from_pa(frame).into_va() as *mut [u8; PG_SIZE]) };
+                page[pos - page_off..pos - page_off + chunk_size]
+                    .copy_from_slice(&buf[pos - start..pos - start + chunk_size]);
+                FrameTable::put(frame);
+            }
+            pos += chunk_size;
+        }
+
         Ok(bytes_written)
     }
 
+    fn page(&self, off: usize) -> Result<usize> {
+        let inum = self.inum();
+        if let Some(frame) = PageCache::find(inum, off) {
+            return Ok(frame);
+        }
+        // Allocated before locking, as making room may write pages of this
+        // very file back.
+        let buf = FrameTable::alloc_frame()?;
+        let guard = self.0.lock();
+        let (_, data) = &*guard;
+        Self::read_page(data, off, unsafe { &mut *(buf as *mut [u8; PG_SIZE]) });
+        Ok(PageCache::insert(inum, off, buf))
+    }
+
     fn resize(&self, newlen: usize) -> Result<()> {
         let mut guard = self.0.lock();
         let (desc, data) = &mut *guard;
@@ -355,10 +439,15 @@ impl Vnode for Inode {
     fn allow_write(&self) {
         self.0.lock().0.deny_write -= 1;
     }
+
+    fn set_uncached(&self) {
+        self.0.lock().0.uncached = true;
+    }
 }
 
 impl Drop for Inode {
     fn drop(&mut self) {
+        PageCache::drop_inode(self.inum());
         self.close()
     }
 }
@@ -7,20 +7,43 @@ use crate::fs::{File, FileSys};
 use crate::io::Seek;
 use crate::mem::PG_SIZE;
 use crate::sync::{Lazy, Mutex, MutexGuard, Primitive};
+use crate::Result;
 
 pub struct Swap;
 
-static SWAPFILE: Lazy<Mutex<File>> = Lazy::new(|| {
-    Mutex::new(
-        DISKFS
-            .open(".glbswap".into())
-            .expect("swap file \".glbswap\" should exist"),
-    )
+/// Swap file used unless the `swap=` kernel parameter says otherwise.
+const DEFAULT_SWAPFILE: &str = ".glbswap";
+
+static SWAPFILE: Lazy<Option<Mutex<File>>> = Lazy::new(|| {
+    let name = crate::cmdline::param("swap").unwrap_or(DEFAULT_SWAPFILE);
+    if name == "off" {
+        return None;
+    }
+    let file = DISKFS
+        .open(name.into())
+        .unwrap_or_else(|_| panic!("swap file {:?} should exist", name));
+    file.set_uncached();
+    Some(Mutex::new(file))
 });
 
 impl Swap {
+    /// Check the value of the `swap=` kernel parameter. The file is opened on first use.
+    pub fn set_file(name: &str) -> Result<()> {
+        match name {
+            "" => Err(crate::OsError::InvalidArgument),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether swapping is enabled
+    pub fn enabled() -> bool {
+        SWAPFILE.is_some()
+    }
+
     pub fn len() -> usize {
-        SWAPFILE.lock().len().unwrap()
+        SWAPFILE
+            .as_ref()
+            .map_or(0, |file| file.lock().len().unwrap())
     }
 
     pub fn page_num() -> usize {
@@ -30,6 +53,6 @@ impl Swap {
 
     /// TODO: Design high-level interfaces, or do in lab3?
     pub fn lock() -> MutexGuard<'static, File, Primitive> {
-        SWAPFILE.lock()
+        SWAPFILE.as_ref().expect("swapping is disabled").lock()
     }
 }
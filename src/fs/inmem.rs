@@ -108,6 +108,11 @@ impl Vnode for Inode {
         unimplemented!();
     }
 
+    /// In-memory files have no pages in the page cache, so they can't be mapped.
+    fn page(&self, _off: usize) -> Result<usize> {
+        Err(OsError::InvalidArgument)
+    }
+
     fn close(&self) {
         unimplemented!();
     }
@@ -115,4 +120,5 @@ impl Vnode for Inode {
     // TODO: Impl deny write for mem file.
     fn deny_write(&self) {}
     fn allow_write(&self) {}
+    fn set_uncached(&self) {}
 }
@@ -10,11 +10,13 @@ extern crate riscv;
 #[macro_use]
 pub mod sbi;
 pub mod boot;
+pub mod cmdline;
 pub mod device;
 pub mod error;
 pub mod fs;
 pub mod io;
 pub mod mem;
+pub mod platform;
 pub mod sync;
 pub mod thread;
 pub mod trap;
@@ -30,17 +32,15 @@ mod my_test;
 
 pub use error::OsError;
 
-use core::{ptr, slice, str};
-use fdt::{standard_nodes::MemoryRegion, Fdt};
+use alloc::string::String;
+use core::ptr;
 use riscv::register;
 
 use fs::{disk::DISKFS, FileSys};
-use mem::PhysAddr;
 
 extern "C" {
     fn sbss();
     fn ebss();
-    fn ekernel();
     fn bootstack();
 }
 
@@ -57,42 +57,30 @@ pub extern "C" fn main(hart_id: usize, dtb: usize) -> ! {
     // Flush BSS since they are not loaded and the corresponding memory may be random
     unsafe { ptr::write_bytes(sbss as *mut u8, 0, ebss as usize - sbss as usize) };
 
-    // Parse the device tree.
-    let devtree = unsafe { Fdt::from_ptr(dtb as *const u8).unwrap() };
-    // Get the start point and length of physical memory
-    let (pm_base, pm_len) = {
-        let memory = devtree.memory();
-        let mut regions = memory.regions();
-        let MemoryRegion {
-            starting_address,
-            size,
-        } = regions.next().expect("No memory info.");
-        assert_eq!(regions.next(), None, "Unknown memory region");
-        (
-            starting_address as usize,
-            size.expect("Unknown physical memory length"),
-        )
-    };
-    assert_eq!(pm_base, mem::PM_BASE, "Error constant mem::PM_BASE.");
-    // Get the boot arguments.
-    let _bootargs: &'static str = unsafe {
-        let (vm, len) = {
-            let bootargs = devtree.chosen().bootargs().unwrap();
-            let len = bootargs.len();
-            (PhysAddr::from_pa(bootargs.as_ptr() as usize).into_va(), len)
-        };
-        str::from_utf8(slice::from_raw_parts(vm as *const u8, len)).unwrap()
-    };
-
-    // Initialize memory management.
-    let ram_base = ekernel as usize;
-    let ram_tail = dtb + mem::VM_OFFSET; // Current we do not reuse dtb area.
-    mem::init(ram_base, ram_tail, pm_len);
+    // Parse the device tree and the kernel command line in it.
+    platform::init(dtb);
+    let platform = platform::get();
+    sbi::timer::set_clock_per_sec(platform.timebase_frequency);
+    cmdline::init(
+        platform::devtree(dtb)
+            .chosen()
+            .bootargs()
+            .unwrap_or_default(),
+    );
+
+    // Initialize memory management. Nothing refers to the device tree afterwards.
+    mem::init(platform);
+    unsafe { mem::reclaim(platform.dtb) };
 
     #[cfg(feature = "debug")]
     {
-        kprintln!("RAM: 0x{:x} - 0x{:x}", ram_base, ram_tail);
-        kprintln!("BOOTARGS: {:?}", _bootargs);
+        platform.memory.iter().for_each(|r| {
+            kprintln!("RAM: 0x{:x} - 0x{:x}", r.base, r.end());
+        });
+        platform.reserved.iter().for_each(|r| {
+            kprintln!("Reserved: 0x{:x} - 0x{:x}", r.base, r.end());
+        });
+        kprintln!("BOOTARGS: {:?}", cmdline::args());
     }
 
     trap::set_strap_entry();
@@ -109,35 +97,72 @@ pub extern "C" fn main(hart_id: usize, dtb: usize) -> ! {
     // Init timer & external interrupt
     sbi::interrupt::init();
 
+    // Feed console input to the tty in the background. Scheduler tests expect
+    // to have the CPU to themselves.
+    #[cfg(not(feature = "test-schedule"))]
+    device::tty::Tty::start();
+
+    // Merge identical user pages in the background.
+    mem::frametable::ksm::Ksm::start();
+
     #[cfg(feature = "test")]
     {
         use alloc::sync::Arc;
         let sema = Arc::new(sync::Semaphore::new(0));
         let sema2 = sema.clone();
-        thread::spawn("test", move || crate::test::main(sema2, _bootargs));
+        thread::spawn("test", move || crate::test::main(sema2, cmdline::args()));
         sema.down();
     }
 
+    // Run the init program, if there is one, with the rest of the command line as arguments.
+    let init = cmdline::param("init");
+    if let Some(path) = init {
+        let argv = core::iter::once(path)
+            .chain(cmdline::args().split(' ').filter(|arg| !arg.is_empty()))
+            .map(String::from)
+            .collect();
+        match DISKFS.open(path.into()) {
+            Ok(file) => {
+                let status = userproc::wait(userproc::execute(file, argv));
+                kprintln!("init exited with status {:?}", status);
+            }
+            Err(e) => kprintln!("init: cannot open {}: {:?}", path, e),
+        }
+    }
+
     #[cfg(feature = "shell")]
-    {
+    if init.is_none() {
         // TODO: Lab 0
         use alloc::string::String;
-        // function for reading a line from console
+        use device::tty::Tty;
+        // function for reading a line from console, `None` on end of file
         fn getline() -> Option<String> {
-            let mut buf = String::new();
+            let mut buf = [0u8; 256];
+            let mut line = alloc::vec::Vec::new();
             loop {
-                let c = crate::sbi::console_getchar();
-                match c {
-                    10 => break,                              // end of line
-                    _ => buf.push(char::from_u32(c as u32)?), // other characters
+                match Tty::get().read(&mut buf) {
+                    Ok(0) if line.is_empty() => return None,
+                    Ok(0) => break,
+                    Ok(n) => {
+                        line.extend_from_slice(&buf[..n]);
+                        if line.last() == Some(&b'\n') {
+                            line.pop();
+                            break;
+                        }
+                    }
+                    // `^C` throws the current line away.
+                    Err(_) => line.clear(),
                 }
             }
-            Some(buf)
+            Some(String::from_utf8_lossy(&line).into_owned())
         }
         // Enter the shell loop
         loop {
             kprint!("PKUOS> ");
-            let cmd = getline().expect("Invalid input");
+            let cmd = match getline() {
+                Some(cmd) => cmd,
+                None => break,
+            };
             match cmd.as_str() {
                 "exit" => break,
                 "whoami" => kprint!("2200013172\n"),
@@ -151,6 +176,8 @@ pub extern "C" fn main(hart_id: usize, dtb: usize) -> ! {
         my_test::user::main("page-merge-mm");
     }
 
+    mem::zswap::ZSwap::report();
+    mem::frametable::ksm::Ksm::report();
     DISKFS.unmount();
 
     kprintln!("Goodbye, World!");
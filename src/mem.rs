@@ -10,12 +10,16 @@
 pub mod frametable;
 pub mod layout;
 pub mod malloc;
+pub mod pagecache;
 pub mod pagetable;
 pub mod palloc;
+pub mod replace;
 pub mod suppagetable;
 pub mod swaptable;
 pub mod userbuf;
 mod utils;
+pub mod vmstats;
+pub mod zswap;
 
 use core::mem::size_of;
 
@@ -25,7 +29,8 @@ pub use self::pagetable::*;
 pub use self::palloc::Palloc;
 pub use self::utils::*;
 
-use self::palloc::USER_POOL_LIMIT;
+use self::palloc::user_pool_pages;
+use crate::platform::{Platform, Region, Regions};
 
 pub fn get_pte(va: usize) -> Option<Entry> {
     match crate::thread::Manager::get().current.lock().pagetable {
@@ -34,13 +39,74 @@ pub fn get_pte(va: usize) -> Option<Entry> {
     }
 }
 
-pub fn init(ram_base: usize, ram_tail: usize, pm_len: usize) {
-    let palloc_tail = ram_tail - USER_POOL_LIMIT * PG_SIZE;
+pub fn get_leaf(va: usize) -> Option<(Entry, PageSize)> {
+    match crate::thread::Manager::get().current.lock().pagetable {
+        Some(ref pt) => pt.lock().get_leaf(va).map(|(pte, size)| (*pte, size)),
+        None => KernelPgTable::get()
+            .get_leaf(va)
+            .map(|(pte, size)| (*pte, size)),
+    }
+}
+
+/// Hands all free RAM described by `platform` to the page allocators.
+///
+/// Free RAM is every memory region minus firmware reservations, the kernel
+/// image and the device tree blob. The top [`user_pool_pages`] pages go to
+/// the user pool, the rest to the kernel.
+///
+/// Only the first GiB of RAM is mapped before the kernel page table is
+/// activated, so memory above it is handed out afterwards.
+pub fn init(platform: &Platform) {
+    extern "C" {
+        fn ekernel();
+    }
+
+    let mut reserved = platform.reserved;
+    reserved.push(Region::new(PM_BASE, ekernel as usize - VM_OFFSET - PM_BASE));
+    reserved.push(platform.dtb.page_outer());
+
+    let for_each_free = |reserved: &Regions, f: &mut dyn FnMut(Region)| {
+        platform
+            .memory
+            .iter()
+            .filter(|r| r.base >= PM_BASE)
+            .for_each(|r| reserved.subtract_from(*r, |free| f(free.page_inner())));
+    };
+
+    let pool_size = user_pool_pages() * PG_SIZE;
+    let mut top = Region::default();
+    for_each_free(&reserved, &mut |free| {
+        if free.end() > top.end() {
+            top = free;
+        }
+    });
+    assert!(top.size >= pool_size, "not enough memory for the user pool");
+    let user_pool = Region::new(top.end() - pool_size, pool_size);
+    reserved.push(user_pool);
+
+    let insert = |free: Region| unsafe {
+        if free.size > 0 {
+            Palloc::init(free.base + VM_OFFSET, free.end() + VM_OFFSET)
+        }
+    };
+    let (mut low, mut high) = (Regions::new(), Regions::new());
+    low.push(Region::new(0, BOOT_MAP_END));
+    high.push(Region::new(BOOT_MAP_END, usize::MAX - BOOT_MAP_END));
+    for_each_free(&reserved, &mut |free| high.subtract_from(free, insert));
+
+    KernelPgTable::init(platform);
+
+    for_each_free(&reserved, &mut |free| low.subtract_from(free, insert));
+    unsafe { palloc::UserPool::init(user_pool.base + VM_OFFSET, user_pool.end() + VM_OFFSET) };
+}
 
-    unsafe {
-        palloc::Palloc::init(ram_base, palloc_tail);
-        palloc::UserPool::init(palloc_tail, ram_tail);
-        KernelPgTable::init(pm_len);
+/// Gives the pages of a boot-time region, such as the device tree blob, to the kernel allocator.
+///
+/// Nothing may refer to the region anymore.
+pub unsafe fn reclaim(region: Region) {
+    let region = region.page_inner();
+    if region.size > 0 {
+        Palloc::init(region.base + VM_OFFSET, region.end() + VM_OFFSET);
     }
 }
 
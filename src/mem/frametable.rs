@@ -1,155 +1,638 @@
-use alloc::{sync::Arc, vec::Vec};
+pub mod ksm;
+
+use alloc::{sync::Arc, vec, vec::Vec};
 
 use crate::{
-    fs::disk::Swap,
+    fs::File,
     io::{Seek, Write},
     sync::{Lazy, Mutex, Primitive},
     thread::{self, Thread},
+    userproc::oom,
+    Result,
 };
 
 use super::{
-    palloc::UserPool, swaptable::SwapTable, PTEFlags, PageAlign, PhysAddr, PG_SIZE, VM_OFFSET,
+    pagecache::PageCache,
+    palloc::UserPool,
+    replace::{Replace, Replacer},
+    suppagetable::SupPageEntry,
+    swaptable::SwapTable,
+    vmstats::{Event, Pages, VmStats},
+    Entry, PTEFlags, PageTable, PhysAddr, PG_SHIFT, PG_SIZE, VM_OFFSET,
 };
 
 bitflags::bitflags! {
-    pub struct FTEFlags: usize {
+    pub struct FrameFlags: usize {
         // pinned
         const P = 0b0000_0001;
 
         // used
         // const U = 0b0000_0010;
+
+        // cached, the frame belongs to the page cache
+        const C = 0b0000_0100;
+
+        // merged, pages with the same contents share the frame, see `ksm`
+        const K = 0b0000_1000;
     }
 }
 
-#[derive(Debug)]
-pub struct FrameTableEntry {
-    pub frame: usize,
+/// A mapping of a frame: page `va` in the address space of `thread`.
+#[derive(Debug, Clone)]
+pub struct Mapping {
     pub thread: Arc<Thread>,
-    pub va_and_flag: usize,
+    pub va: usize,
 }
 
-impl FrameTableEntry {
-    fn new(frame: usize, thread: Arc<Thread>, va: usize, flag: FTEFlags) -> Self {
+impl Mapping {
+    fn flag(&self) -> PTEFlags {
+        let pt = self.thread.pagetable.as_ref().unwrap().lock();
+        pt.get_pte(self.va).unwrap().flag()
+    }
+
+    /// Whether the mapping is of page `va` in the address space of `thread`.
+    fn is(&self, thread: &Thread, va: usize) -> bool {
+        self.va == va && same_space(&self.thread, thread)
+    }
+}
+
+/// Descriptor of a frame of the user pool.
+#[derive(Debug)]
+pub struct FrameDesc {
+    /// References to the frame: its mappings, plus holders such as the page
+    /// cache. The frame is free without any.
+    refs: usize,
+    flags: FrameFlags,
+    /// Every mapping of the frame, the reverse of the page tables.
+    rmap: Vec<Mapping>,
+    /// Recent accesses, for [`Aging`](crate::mem::replace::aging::Aging).
+    pub age: u8,
+    /// When the frame was mapped, after being free or evicted, for
+    /// [`Fifo`](crate::mem::replace::fifo::Fifo). Earlier is lower.
+    pub since: usize,
+}
+
+impl FrameDesc {
+    const fn free() -> Self {
         Self {
-            frame,
-            thread,
-            va_and_flag: va | flag.bits(),
+            refs: 0,
+            flags: FrameFlags::empty(),
+            rmap: Vec::new(),
+            age: 0,
+            since: 0,
         }
     }
 
     fn is_pinned(&self) -> bool {
-        self.va_and_flag & FTEFlags::P.bits() != 0
+        self.flags.contains(FrameFlags::P)
     }
 
-    fn is_used(&self) -> bool {
-        let pt = self.thread.pagetable.as_ref().unwrap().lock();
-        let pte = pt.get_pte(self.va_and_flag).unwrap();
-        pte.is_accessed()
+    fn is_cached(&self) -> bool {
+        self.flags.contains(FrameFlags::C)
+    }
+
+    /// Whether any mapping accessed the frame.
+    pub fn is_used(&self) -> bool {
+        self.rmap
+            .iter()
+            .any(|mapping| mapping.flag().contains(PTEFlags::A))
+    }
+
+    /// Whether any mapping wrote to the frame.
+    pub fn is_dirty(&self) -> bool {
+        self.rmap
+            .iter()
+            .any(|mapping| mapping.flag().contains(PTEFlags::D))
     }
 
-    fn unset_used(&self) {
-        let mut pt = self.thread.pagetable.as_ref().unwrap().lock();
-        let flag = pt.get_pte(self.va_and_flag).unwrap().flag();
-        pt.map(
-            PhysAddr::from_pa(self.frame),
-            self.va_and_flag.floor(),
-            1,
-            flag & !PTEFlags::A,
-        );
+    /// Clears the accessed bits of all mappings.
+    pub fn unset_used(&self) {
+        for mapping in &self.rmap {
+            let mut pt = mapping.thread.pagetable.as_ref().unwrap().lock();
+            let pte = *pt.get_pte(mapping.va).unwrap();
+            pt.map(pte.pa(), mapping.va, 1, pte.flag() - PTEFlags::A);
+        }
+    }
+}
+
+/// Whether two threads run in the same address space.
+fn same_space(a: &Thread, b: &Thread) -> bool {
+    match (&a.pagetable, &b.pagetable) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        _ => false,
     }
 }
 
-pub struct FrameTable(Mutex<Vec<FrameTableEntry>, Primitive>);
+struct FrameTableInner {
+    /// Descriptors of the frames of the user pool, by physical page number
+    /// from `base`.
+    frames: Vec<FrameDesc>,
+    base: usize,
+    policy: Replacer,
+    /// Stamps [`FrameDesc::since`].
+    mapped: usize,
+}
+
+impl FrameTableInner {
+    /// Index of `frame`, a physical address, in `frames`.
+    fn index(&self, frame: usize) -> usize {
+        (frame >> PG_SHIFT)
+            .checked_sub(self.base)
+            .filter(|&index| index < self.frames.len())
+            .unwrap_or_else(|| panic!("{:#x} is not a user frame", frame))
+    }
+
+    fn desc(&mut self, frame: usize) -> &mut FrameDesc {
+        let index = self.index(frame);
+        &mut self.frames[index]
+    }
+
+    fn frame(&self, index: usize) -> usize {
+        (self.base + index) << PG_SHIFT
+    }
+
+    /// Adds `mapping` of `frame`, with its reference.
+    fn add_mapping(&mut self, frame: usize, mapping: Mapping) {
+        self.mapped += 1;
+        let since = self.mapped;
+        let desc = self.desc(frame);
+        if desc.rmap.is_empty() {
+            desc.since = since;
+        }
+        desc.refs += 1;
+        account(&mapping.thread, desc.is_cached(), VmStats::add);
+        desc.rmap.push(mapping);
+    }
+
+    /// Removes the mapping of `frame` at `va` in the address space of
+    /// `thread`, leaving its reference to the caller.
+    fn remove_mapping(&mut self, frame: usize, thread: &Thread, va: usize) -> Option<Mapping> {
+        let desc = self.desc(frame);
+        let index = desc
+            .rmap
+            .iter()
+            .position(|mapping| mapping.is(thread, va))?;
+        let mapping = desc.rmap.swap_remove(index);
+        account(&mapping.thread, desc.is_cached(), VmStats::sub);
+        Some(mapping)
+    }
+
+    /// Drops a reference to `frame`, returns true if it was the last one.
+    fn release(&mut self, frame: usize) -> bool {
+        let desc = self.desc(frame);
+        assert!(desc.refs > 0, "frame is not in use");
+        desc.refs -= 1;
+        if desc.refs == 0 {
+            *desc = FrameDesc::free();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_shared(&mut self, frame: usize) -> bool {
+        self.desc(frame).refs > 1
+    }
+}
+
+/// The frame table: what every frame of the user pool is used for.
+///
+/// A frame is described by a [`FrameDesc`], found by its physical page number.
+/// Its reverse mappings lead to all page table entries mapping it, so a frame
+/// shared by forked processes or through the page cache is evicted at once
+/// from all of them. Anonymous pages with the same contents may share a
+/// frame too, see [`ksm`].
+pub struct FrameTable(Mutex<FrameTableInner, Primitive>);
 
 impl FrameTable {
     pub fn get() -> &'static Self {
-        static FRAME_TABLE: Lazy<FrameTable> = Lazy::new(|| FrameTable(Mutex::new(Vec::new())));
+        static FRAME_TABLE: Lazy<FrameTable> = Lazy::new(|| {
+            let pool = UserPool::range();
+            let frames = (pool.end - pool.start) / PG_SIZE;
+            FrameTable(Mutex::new(FrameTableInner {
+                frames: (0..frames).map(|_| FrameDesc::free()).collect(),
+                base: (pool.start - VM_OFFSET) >> PG_SHIFT,
+                policy: Replacer::default(),
+                mapped: 0,
+            }))
+        });
         &FRAME_TABLE
     }
 
     pub fn map(frame: usize, thread: Arc<Thread>, va: usize, pinned: bool) {
-        let flag = if pinned {
-            FTEFlags::P
-        } else {
-            FTEFlags::empty()
-        };
-        Self::get()
-            .0
-            .lock()
-            .push(FrameTableEntry::new(frame, thread, va, flag));
+        let mut ft = Self::get().0.lock();
+        if pinned {
+            ft.desc(frame).flags |= FrameFlags::P;
+        }
+        ft.add_mapping(frame, Mapping { thread, va });
+    }
+
+    /// Maps `frame` of the page cache, which the caller holds, at `va`.
+    pub fn map_cached(frame: usize, thread: Arc<Thread>, va: usize) {
+        let mut ft = Self::get().0.lock();
+        ft.desc(frame).flags |= FrameFlags::C;
+        ft.add_mapping(frame, Mapping { thread, va });
+    }
+
+    /// Allows a frame mapped with `pinned` set to be evicted.
+    pub fn unpin(frame: usize) {
+        Self::get().0.lock().desc(frame).flags -= FrameFlags::P;
     }
 
+    /// Takes a reference to `frame` that is not a mapping, e.g. to read it.
+    pub fn hold(frame: usize) {
+        Self::get().0.lock().desc(frame).refs += 1;
+    }
+
+    /// Drops a reference taken by [`hold`](Self::hold), freeing `frame` with
+    /// the last one.
+    pub fn put(frame: usize) {
+        if Self::get().0.lock().release(frame) {
+            unsafe { UserPool::dealloc_pages((frame + VM_OFFSET) as *mut _, 1) };
+        }
+    }
+
+    /// Takes `frame` if it has exactly `refs` references, which their holders
+    /// give up.
+    pub fn take(frame: usize, refs: usize) -> bool {
+        let mut ft = Self::get().0.lock();
+        let desc = ft.desc(frame);
+        if desc.refs != refs {
+            return false;
+        }
+        assert!(desc.rmap.is_empty(), "taking a mapped frame");
+        *desc = FrameDesc::free();
+        true
+    }
+
+    /// Unmaps all frames of the current address space, when its last thread exits.
+    /// Frames no one else maps are freed.
     pub fn cleanup() {
         let current = thread::current();
-        Self::get()
-            .0
-            .lock()
-            .retain(|entry| !Arc::ptr_eq(&entry.thread, &current));
+        let mut ft = Self::get().0.lock();
+        let mut pt = current.pagetable.as_ref().unwrap().lock();
+        pt.for_each_user_entry(|va, entry| {
+            if !entry.is_valid() {
+                return;
+            }
+            let frame = entry.pa().value();
+            if ft.remove_mapping(frame, &current, va).is_none() {
+                return;
+            }
+            // Invalidate the mapping, so that destroying the page table won't free it again.
+            *entry = Entry::new(PhysAddr::from_pa(0), PTEFlags::empty());
+            if ft.release(frame) {
+                unsafe { UserPool::dealloc_pages((frame + VM_OFFSET) as *mut _, 1) };
+            }
+        });
     }
 
-    pub fn alloc_frame() -> *mut u8 {
-        if let Some(frame) = unsafe { UserPool::alloc_pages(1) } {
-            return frame;
+    /// Unmaps the page at `va` from the current address space. Its frame, if it
+    /// is resident, is freed unless another mapping shares it.
+    pub fn unmap(va: usize) {
+        let current = thread::current();
+        let mut ft = Self::get().0.lock();
+        let mut pt = current.pagetable.as_ref().unwrap().lock();
+        let frame = pt
+            .get_pte(va)
+            .filter(|pte| pte.is_valid())
+            .map(|pte| pte.pa().value());
+        if let Some(frame) = frame {
+            if ft.remove_mapping(frame, &current, va).is_some() && ft.release(frame) {
+                unsafe { UserPool::dealloc_pages((frame + VM_OFFSET) as *mut _, 1) };
+            }
         }
 
-        let frame = Self::select_and_evict();
+        pt.map(PhysAddr::from_pa(0), va, 1, PTEFlags::empty());
+        PageTable::flush_tlb();
+    }
+
+    /// Sets the permissions of page `va` of the current address space to `flag`,
+    /// some of R, W, X and U. A page whose frame is shared stays copy-on-write
+    /// instead of becoming writable, unless it is a shared mapping. The caller
+    /// flushes the TLB.
+    pub fn protect(va: usize, flag: PTEFlags) {
+        let current = thread::current();
+        let mut ft = Self::get().0.lock();
+        let mut pt = current.pagetable.as_ref().unwrap().lock();
+        let pte = match pt.get_pte(va) {
+            Some(pte) if !pte.flag().is_empty() => *pte,
+            _ => return,
+        };
 
-        (frame + VM_OFFSET) as *mut u8
+        let keep = PTEFlags::V | PTEFlags::A | PTEFlags::D | PTEFlags::SHARED;
+        let mut new = (pte.flag() & keep) | flag;
+        if flag.contains(PTEFlags::W)
+            && !pte.is_shared()
+            && (pte.is_cow() || (pte.is_valid() && ft.is_shared(pte.pa().value())))
+        {
+            new = (new - PTEFlags::W) | PTEFlags::COW;
+        }
+        pt.map(pte.pa(), va, 1, new);
+    }
+
+    /// Shares all resident pages of `parent` with `child`, which must not be running yet.
+    ///
+    /// Writable pages become read-only copy-on-write pages in both processes,
+    /// except for shared mappings, which stay shared. Pages
+    /// not present yet are described by the supplemental page table, which is
+    /// copied too.
+    pub fn fork(parent: &Arc<Thread>, child: &Arc<Thread>) {
+        // Nothing can be evicted while the frame table is locked.
+        let mut ft = Self::get().0.lock();
+
+        let mut parent_pt = parent.pagetable.as_ref().unwrap().lock();
+        let mut child_pt = child.pagetable.as_ref().unwrap().lock();
+        parent_pt.for_each_user_entry(|va, entry| {
+            let mut flag = entry.flag();
+            if entry.is_valid() {
+                if flag.contains(PTEFlags::W) && !entry.is_shared() {
+                    flag = (flag - PTEFlags::W) | PTEFlags::COW;
+                }
+                *entry = Entry::new(entry.pa(), flag);
+                let mapping = Mapping {
+                    thread: child.clone(),
+                    va,
+                };
+                ft.add_mapping(entry.pa().value(), mapping);
+            }
+            child_pt.map(entry.pa(), va, 1, flag);
+        });
+
+        let parent_spt = parent.suppt.as_ref().unwrap().0.lock();
+        let mut child_spt = child.suppt.as_ref().unwrap().0.lock();
+        for (va, spte) in parent_spt.iter() {
+            if let SupPageEntry::InSwap(offset) = spte {
+                SwapTable::share(*offset);
+                VmStats::add(child, Pages::Swapped, 1);
+            }
+            child_spt.insert(*va, spte.clone());
+        }
+
+        // Stale writable translations of the parent must go.
+        PageTable::flush_tlb();
+    }
+
+    /// Gives the current thread its own copy of the copy-on-write page at `va`.
+    ///
+    /// `frame` is a free frame, from [`alloc_frame`](Self::alloc_frame), to copy
+    /// the page into. It is released if the page turns out not to be shared anymore.
+    pub fn copy_on_write(va: usize, frame: *mut u8) {
+        let current = thread::current();
+        let mut ft = Self::get().0.lock();
+        let mut pt = current.pagetable.as_ref().unwrap().lock();
+
+        let release = |frame: *mut u8| unsafe { UserPool::dealloc_pages(frame, 1) };
+
+        let pte = match pt.get_pte(va) {
+            Some(pte) if pte.is_valid() && pte.is_cow() => *pte,
+            // Evicted while `frame` was allocated. Fault again to swap it in.
+            _ => return release(frame),
+        };
+        let old = pte.pa().value();
+        let flag = (pte.flag() - PTEFlags::COW) | PTEFlags::W;
+
+        if !ft.is_shared(old) {
+            // The last user of the page keeps it.
+            pt.map(pte.pa(), va, 1, flag);
+            release(frame);
+        } else {
+            unsafe { (frame as *mut [u8; PG_SIZE]).copy_from((old + VM_OFFSET) as *const _, 1) };
+            let new = PhysAddr::from(frame);
+            pt.map(new, va, 1, flag);
+
+            // A copy of a cached page is the thread's own.
+            let mapping = ft
+                .remove_mapping(old, &current, va)
+                .expect("resident page is not in the frame table");
+            ft.release(old);
+            ft.add_mapping(new.value(), mapping);
+        }
+
+        PageTable::flush_tlb();
+    }
+
+    /// Allocates a frame for a user page, making room if needed. When nothing
+    /// can be evicted, a process is killed, see [`oom`].
+    ///
+    /// ## Return
+    /// - `Ok(frame)`: The kernel virtual address of the frame.
+    /// - `Err(OutOfMemory)`: The current process is killed, or nothing is left to kill.
+    pub fn alloc_frame() -> Result<*mut u8> {
+        loop {
+            if let Some(frame) = unsafe { UserPool::alloc_pages(1) } {
+                return Ok(frame);
+            }
+            if let Some(frame) = PageCache::reclaim() {
+                return Ok(frame);
+            }
+            if let Some(frame) = Self::select_and_evict() {
+                return Ok((frame + VM_OFFSET) as *mut u8);
+            }
+            oom::kill_victim()?;
+        }
     }
 
-    pub fn select_and_evict() -> usize {
-        static mut HEAD: usize = 0;
+    /// Evicts a page from all its mappings to make room, returning its frame,
+    /// if any page can be evicted.
+    ///
+    /// Where a page goes depends on what backs it:
+    /// - A clean private page of a file is dropped, to be loaded again.
+    /// - A cached page of a file, mapped shared or read-only, is written back
+    ///   if dirty, then leaves the page cache.
+    /// - Anonymous and dirty private pages go to swap, as long as there is
+    ///   room. Processes sharing the page after a fork share the swap slot.
+    pub fn select_and_evict() -> Option<usize> {
+        loop {
+            let (file, offset, frame, dirty) = match Self::evict_one()? {
+                Evicted::Freed(frame) => return Some(frame),
+                Evicted::Mapped {
+                    file,
+                    offset,
+                    frame,
+                    dirty,
+                } => (file, offset, frame, dirty),
+            };
 
-        fn write_to_swap(frame: usize) -> usize {
+            // Written without the frame table locked, as writing looks up the page cache.
+            if dirty {
+                write_back(file.clone(), offset, frame);
+            }
+            if PageCache::evict(file.inum(), offset, frame) {
+                return Some(frame);
+            }
+            // Mapped again meanwhile, try another one.
+        }
+    }
+
+    /// Unmaps a frame chosen by the replacement policy from all its mappings.
+    ///
+    /// Returns `None` if no frame can be evicted, or if the one chosen cannot
+    /// be written to swap, in which case it stays mapped.
+    fn evict_one() -> Option<Evicted> {
+        fn write_to_swap(frame: usize) -> Option<usize> {
             let page = unsafe {
                 ((frame + VM_OFFSET) as *mut [u8; PG_SIZE])
                     .as_ref()
                     .unwrap()
             };
-            let swap_offset = SwapTable::alloc();
-            {
-                let mut swap = Swap::lock();
-                swap.seek(crate::io::SeekFrom::Start(swap_offset)).unwrap();
-                swap.write(page).unwrap();
+            let swap_offset = SwapTable::alloc()?;
+            if SwapTable::write(swap_offset, page).is_err() {
+                SwapTable::dealloc(swap_offset);
+                return None;
             }
-            swap_offset
+            Some(swap_offset)
         }
 
-        let mut ft = Self::get().0.lock();
+        let mut guard = Self::get().0.lock();
+        let inner = &mut *guard;
 
-        unsafe {
-            if HEAD > ft.len() {
-                HEAD = 0;
-            }
-            loop {
-                if ft[HEAD].is_pinned() {
-                    HEAD = (HEAD + 1) % ft.len();
-                    continue;
-                }
+        // Slots are only taken with the frame table locked.
+        let swap_free = SwapTable::has_free();
+        let index = inner
+            .policy
+            .select(&mut inner.frames, &|desc| is_evictable(desc, swap_free))?;
+        let frame = inner.frame(index);
+        let desc = &mut inner.frames[index];
+        let cached = desc.is_cached();
+        let rmap = core::mem::take(&mut desc.rmap);
 
-                if !ft[HEAD].is_used() {
-                    let frame = ft[HEAD].frame;
-                    let thread = ft[HEAD].thread.clone();
-                    let va = ft[HEAD].va_and_flag.floor();
+        let mut dirty = false;
+        for mapping in &rmap {
+            let mut pt = mapping.thread.pagetable.as_ref().unwrap().lock();
+            let flag = pt.get_pte(mapping.va).unwrap().flag();
+            dirty |= flag.contains(PTEFlags::D);
+            // The page cache tracks whether its page is dirty, not the mapping.
+            let flag = match cached {
+                true => flag - PTEFlags::V - PTEFlags::D,
+                false => flag - PTEFlags::V,
+            };
+            pt.map(PhysAddr::from_pa(frame), mapping.va, 1, flag);
+        }
+        PageTable::flush_tlb();
 
-                    {
-                        let mut pt = thread.pagetable.as_ref().unwrap().lock();
-                        let flag = pt.get_pte(va).unwrap().flag();
-                        pt.map(PhysAddr::from_pa(frame), va, 1, flag & !PTEFlags::V);
-                    }
+        // A clean page of a file is still in the file, as the entries say.
+        let to_swap = !cached
+            && (dirty
+                || !rmap
+                    .iter()
+                    .all(|mapping| in_file(&mapping.thread, mapping.va)));
+        let swap_offset = if to_swap { write_to_swap(frame) } else { None };
+        if to_swap && swap_offset.is_none() {
+            // Mapped again as it was, for the caller to kill a process instead.
+            for mapping in &rmap {
+                let mut pt = mapping.thread.pagetable.as_ref().unwrap().lock();
+                let flag = pt.get_pte(mapping.va).unwrap().flag();
+                pt.map(PhysAddr::from_pa(frame), mapping.va, 1, flag | PTEFlags::V);
+            }
+            desc.rmap = rmap;
+            return None;
+        }
 
-                    let swap_offset = write_to_swap(frame);
-                    thread.suppt.as_ref().unwrap().map_in_swap(va, swap_offset);
+        for mapping in &rmap {
+            account(&mapping.thread, cached, VmStats::sub);
+            VmStats::count(&mapping.thread, Event::Eviction);
+        }
 
-                    let index = HEAD;
-                    HEAD = HEAD % (ft.len() - 1);
+        if cached {
+            // The entries stay while the page is resident.
+            let (file, offset) = match rmap[0].thread.suppt.as_ref().unwrap().query(rmap[0].va) {
+                Some(SupPageEntry::InFileMapped(file, offset))
+                | Some(SupPageEntry::InFileLazyLoad(file, offset, _)) => (file, offset),
+                _ => panic!("cached page is not in the supplemental page table"),
+            };
+            // One reference of the mappings is kept until the page is written back.
+            desc.refs -= rmap.len() - 1;
+            return Some(Evicted::Mapped {
+                file,
+                offset,
+                frame,
+                dirty,
+            });
+        }
 
-                    return ft.remove(index).frame;
+        if let Some(swap_offset) = swap_offset {
+            for (i, mapping) in rmap.iter().enumerate() {
+                if i > 0 {
+                    SwapTable::share(swap_offset);
                 }
-
-                ft[HEAD].unset_used();
-                HEAD = (HEAD + 1) % ft.len();
+                let spt = mapping.thread.suppt.as_ref().unwrap();
+                spt.map_in_swap(mapping.va, swap_offset);
+                VmStats::add(&mapping.thread, Pages::Swapped, 1);
             }
         }
+
+        assert_eq!(desc.refs, rmap.len());
+        *desc = FrameDesc::free();
+        Some(Evicted::Freed(frame))
+    }
+}
+
+/// Whether the frame of `desc` can be evicted, given whether there is room in swap.
+///
+/// Frames held by anyone but their mappings and the page cache, or not mapped
+/// at all, are not.
+fn is_evictable(desc: &FrameDesc, swap_free: bool) -> bool {
+    if desc.is_pinned() || desc.rmap.is_empty() {
+        return false;
+    }
+    if desc.refs != desc.rmap.len() + desc.is_cached() as usize {
+        return false;
+    }
+    desc.is_cached()
+        || swap_free
+        || (!desc.is_dirty()
+            && desc
+                .rmap
+                .iter()
+                .all(|mapping| in_file(&mapping.thread, mapping.va)))
+}
+
+/// Counts a page mapped by `thread` for its process, `count` being
+/// [`VmStats::add`] or [`VmStats::sub`].
+fn account(thread: &Thread, cached: bool, count: fn(&Thread, Pages, usize)) {
+    count(thread, Pages::Resident, 1);
+    if cached {
+        count(thread, Pages::FileMapped, 1);
+    }
+}
+
+/// Whether page `va` of `thread` can be loaded from a file again, if it is clean.
+fn in_file(thread: &Thread, va: usize) -> bool {
+    matches!(
+        thread.suppt.as_ref().unwrap().query(va),
+        Some(SupPageEntry::InFileLazyLoad(..))
+    )
+}
+
+/// A frame evicted by [`FrameTable::select_and_evict`].
+enum Evicted {
+    /// Dropped or written to swap, the frame is free.
+    Freed(usize),
+    /// The mappings of a cached page of `file` at `offset` are gone, but
+    /// the page cache still holds `frame`.
+    Mapped {
+        file: File,
+        offset: usize,
+        frame: usize,
+        dirty: bool,
+    },
+}
+
+/// Writes the page of `file` at `offset`, cached in `frame`, back to the file.
+fn write_back(mut file: File, offset: usize, frame: usize) {
+    let len = file
+        .len()
+        .map_or(0, |len| len.saturating_sub(offset).min(PG_SIZE));
+    // Copied out first, as writing updates the cached page itself.
+    let mut buf = vec![0u8; len];
+    buf.copy_from_slice(unsafe {
+        core::slice::from_raw_parts((frame + VM_OFFSET) as *const u8, len)
+    });
+    let result = file
+        .seek(crate::io::SeekFrom::Start(offset))
+        .and_then(|_| file.write(&buf));
+    if let Err(err) = result {
+        kprintln!("Failed to write back {:?}: {:?}", file, err);
     }
 }
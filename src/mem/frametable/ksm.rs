@@ -0,0 +1,286 @@
+//! Kernel same-page merging.
+//!
+//! A kernel thread scans the frames of the user pool in the background, a few
+//! at a time, for anonymous pages with the same contents, e.g. zeroed buffers
+//! or data a forked child computed again. It merges them into one frame, which
+//! all of them map copy-on-write, as after a [`fork`](FrameTable::fork).
+//!
+//! Pages are found by a hash of their contents, kept for one pass over the
+//! frames. Two pages are compared byte by byte before they are merged, once
+//! both are read-only, so that neither can change meanwhile.
+//!
+//! Merging is opt-in: the thread wakes every `ksm_interval=` ticks, 0, the
+//! default, disables it. The `ksm` syscall changes the interval at run time,
+//! starting the thread if needed, and reports the [`Stats`].
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+
+use crate::sync::{Lazy, Mutex, Primitive};
+use crate::thread::{self, Thread};
+use crate::Result;
+
+use super::{FrameDesc, FrameFlags, FrameTable, FrameTableInner, Mapping, UserPool};
+use crate::mem::{PTEFlags, PageTable, PhysAddr, PG_SIZE, VM_OFFSET};
+
+/// Ticks between scans, unless the `ksm_interval=` kernel parameter says
+/// otherwise: none, merging is disabled.
+const DEFAULT_INTERVAL: usize = 0;
+
+/// Frames scanned each time the thread wakes.
+const PAGES_PER_SCAN: usize = 64;
+
+static INTERVAL: AtomicUsize = AtomicUsize::new(DEFAULT_INTERVAL);
+
+/// Whether the thread was started.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Set the ticks between scans, for the `ksm_interval=` kernel parameter
+pub fn set_interval(value: &str) -> Result<()> {
+    INTERVAL.store(crate::cmdline::parse_number(value)?, SeqCst);
+    Ok(())
+}
+
+/// What merging did, see [`Ksm::stats`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// Frames that pages were merged into, mapped more than once now.
+    pub shared: usize,
+    /// Frames saved, the mappings of shared frames but one each.
+    pub saved: usize,
+    /// Most frames saved at once.
+    pub peak_saved: usize,
+    /// Pages merged into another one.
+    pub merged: usize,
+    /// Passes over all frames.
+    pub passes: usize,
+}
+
+struct KsmInner {
+    /// Frames scanned in this pass, by the hash of their contents.
+    hashes: BTreeMap<u64, usize>,
+    /// Index of the next frame to scan.
+    cursor: usize,
+    stats: Stats,
+}
+
+pub struct Ksm(Mutex<KsmInner, Primitive>);
+
+impl Ksm {
+    pub fn get() -> &'static Self {
+        static KSM: Lazy<Ksm> = Lazy::new(|| {
+            Ksm(Mutex::new(KsmInner {
+                hashes: BTreeMap::new(),
+                cursor: 0,
+                stats: Stats::default(),
+            }))
+        });
+        &KSM
+    }
+
+    /// Starts the thread, unless `ksm_interval=0` or it runs already.
+    pub fn start() {
+        if INTERVAL.load(SeqCst) == 0 || STARTED.swap(true, SeqCst) {
+            return;
+        }
+        thread::spawn("ksm", || loop {
+            // Disabled again, it checks back every tick.
+            let interval = INTERVAL.load(SeqCst);
+            thread::sleep(interval.max(1) as i64);
+            if interval > 0 {
+                Self::scan(PAGES_PER_SCAN);
+            }
+        });
+    }
+
+    /// Sets the ticks between scans, 0 stops merging, and starts the thread
+    /// if it isn't running yet.
+    pub fn set_interval(interval: usize) {
+        INTERVAL.store(interval, SeqCst);
+        Self::start();
+    }
+
+    /// Scans the next `count` frames, merging each page with a page scanned
+    /// before in this pass that has the same contents.
+    pub fn scan(count: usize) {
+        let mut ksm = Self::get().0.lock();
+        let mut ft = FrameTable::get().0.lock();
+
+        for _ in 0..count.min(ft.frames.len()) {
+            if ksm.cursor == ft.frames.len() {
+                // Hashes of the last pass are stale.
+                ksm.cursor = 0;
+                ksm.hashes.clear();
+                ksm.stats.passes += 1;
+            }
+            let index = ksm.cursor;
+            ksm.cursor += 1;
+            if !is_mergeable(&ft.frames[index]) {
+                continue;
+            }
+
+            let frame = ft.frame(index);
+            let hash = hash(page(frame));
+            match ksm.hashes.get(&hash) {
+                Some(&into) if into != frame && merge(&mut ft, frame, into) => {
+                    ksm.stats.merged += 1
+                }
+                _ => {
+                    ksm.hashes.insert(hash, frame);
+                }
+            }
+        }
+
+        let (_, saved) = sharing(&ft);
+        ksm.stats.peak_saved = ksm.stats.peak_saved.max(saved);
+    }
+
+    pub fn stats() -> Stats {
+        let ksm = Self::get().0.lock();
+        let (shared, saved) = sharing(&FrameTable::get().0.lock());
+        Stats {
+            shared,
+            saved,
+            ..ksm.stats
+        }
+    }
+
+    /// Prints the statistics, if any page was merged.
+    pub fn report() {
+        let stats = Self::stats();
+        if stats.merged == 0 {
+            return;
+        }
+        kprintln!(
+            "ksm: {} pages merged in {} passes, {} frames saved at most; {} shared, {} saved now",
+            stats.merged,
+            stats.passes,
+            stats.peak_saved,
+            stats.shared,
+            stats.saved,
+        );
+    }
+}
+
+/// Whether the page in the frame of `desc` can be merged: a resident anonymous
+/// page, that only its mappings use.
+fn is_mergeable(desc: &FrameDesc) -> bool {
+    if desc.is_pinned() || desc.is_cached() || desc.rmap.is_empty() {
+        return false;
+    }
+    // Pages of files keep their supplemental page table entry.
+    desc.refs == desc.rmap.len()
+        && desc.rmap.iter().all(|mapping| {
+            !mapping.flag().contains(PTEFlags::SHARED)
+                && mapping
+                    .thread
+                    .suppt
+                    .as_ref()
+                    .unwrap()
+                    .query(mapping.va)
+                    .is_none()
+        })
+}
+
+/// Merges the page in `frame` into `into`, if they are the same, freeing `frame`.
+fn merge(ft: &mut FrameTableInner, frame: usize, into: usize) -> bool {
+    if !is_mergeable(ft.desc(into)) {
+        return false;
+    }
+    let mut protected = write_protect(ft.desc(frame));
+    protected.extend(write_protect(ft.desc(into)));
+    PageTable::flush_tlb();
+    if page(frame) != page(into) {
+        // Not to take a copy-on-write fault for nothing.
+        write_enable(protected);
+        return false;
+    }
+
+    let rmap = ft.desc(frame).rmap.clone();
+    let mut freed = false;
+    for mapping in &rmap {
+        move_mapping(ft, frame, into, mapping);
+        freed = ft.release(frame);
+    }
+    if !freed {
+        // Someone else holds the frame, its mappings go back to it.
+        for mapping in &rmap {
+            move_mapping(ft, into, frame, mapping);
+            ft.release(into);
+        }
+        PageTable::flush_tlb();
+        write_enable(protected);
+        return false;
+    }
+    PageTable::flush_tlb();
+
+    ft.desc(into).flags |= FrameFlags::K;
+    unsafe { UserPool::dealloc_pages((frame + VM_OFFSET) as *mut _, 1) };
+    true
+}
+
+/// Maps the page of `mapping` to `to` instead of `from`, taking a reference
+/// to `to` and leaving the one to `from` to the caller.
+fn move_mapping(ft: &mut FrameTableInner, from: usize, to: usize, mapping: &Mapping) {
+    let mapping = ft
+        .remove_mapping(from, &mapping.thread, mapping.va)
+        .unwrap();
+    {
+        let mut pt = mapping.thread.pagetable.as_ref().unwrap().lock();
+        let flag = pt.get_pte(mapping.va).unwrap().flag();
+        pt.map(PhysAddr::from_pa(to), mapping.va, 1, flag);
+    }
+    ft.add_mapping(to, mapping);
+}
+
+/// Makes all writable mappings of the frame of `desc` copy-on-write,
+/// returning them. The caller flushes the TLB.
+fn write_protect(desc: &FrameDesc) -> Vec<(Arc<Thread>, usize)> {
+    let mut protected = Vec::new();
+    for mapping in &desc.rmap {
+        let mut pt = mapping.thread.pagetable.as_ref().unwrap().lock();
+        let pte = *pt.get_pte(mapping.va).unwrap();
+        if pte.flag().contains(PTEFlags::W) {
+            let flag = (pte.flag() - PTEFlags::W) | PTEFlags::COW;
+            pt.map(pte.pa(), mapping.va, 1, flag);
+            protected.push((mapping.thread.clone(), mapping.va));
+        }
+    }
+    protected
+}
+
+/// Makes the mappings [`write_protect`] returned writable again.
+fn write_enable(protected: Vec<(Arc<Thread>, usize)>) {
+    for (thread, va) in protected {
+        let mut pt = thread.pagetable.as_ref().unwrap().lock();
+        let pte = *pt.get_pte(va).unwrap();
+        let flag = (pte.flag() - PTEFlags::COW) | PTEFlags::W;
+        pt.map(pte.pa(), va, 1, flag);
+    }
+    PageTable::flush_tlb();
+}
+
+/// Frames shared by merged pages, and the frames they save.
+fn sharing(ft: &FrameTableInner) -> (usize, usize) {
+    ft.frames
+        .iter()
+        .filter(|desc| desc.flags.contains(FrameFlags::K) && desc.rmap.len() > 1)
+        .fold((0, 0), |(shared, saved), desc| {
+            (shared + 1, saved + desc.rmap.len() - 1)
+        })
+}
+
+fn page(frame: usize) -> &'static [u8; PG_SIZE] {
+    unsafe { &*((frame + VM_OFFSET) as *const [u8; PG_SIZE]) }
+}
+
+/// FNV-1a, a word at a time.
+fn hash(page: &[u8; PG_SIZE]) -> u64 {
+    page.chunks_exact(8)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, word| {
+            (hash ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(0x100_0000_01b3)
+        })
+}
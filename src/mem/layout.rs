@@ -3,31 +3,30 @@
 //     /\/\/\/\/\/\/\/\/\/\
 //     /\/\/\/\/\/\/\/\/\/\
 //     |                  |
-//     |      Unused      |
+//     |       RAM        |
 //     |                  |
 //     +------------------+  <- End of kernel
 //     |      kernel      |
 //     +------------------+  <- 0x80200000
+//     |     firmware     |
+//     +------------------+  <- 0x80000000
 //     |                  |
-//     |      Unused      |
-//     |                  |
-//     +------------------+
-//     |       MMIO       |
-//     +------------------+  <- 0x10001000
-//     |                  |
-//     |      Unused      |
-//     |                  |
-//     +------------------+  <- 0x0c400000
-//     |       PLIC       |
-//     +------------------+  <- 0x0c000000
-//     |                  |
-//     |    Low Memory    |
+//     |   MMIO devices   |  PLIC, virtio, ...
 //     |                  |
 //     +------------------+  <- 0x00000000
+//
+// Only the kernel image is at a fixed place. RAM regions and the addresses of
+// MMIO devices are read from the device tree, see [`crate::platform`].
 
 pub const VM_BASE: usize = 0xFFFFFFC080000000;
 pub const PM_BASE: usize = 0x0000000080000000;
 pub const KERN_BASE: usize = 0x0000000080200000;
 pub const VM_OFFSET: usize = VM_BASE - PM_BASE;
-pub const PLIC_BASE: usize = 0xC000000 + VM_OFFSET;
-pub const MMIO_BASE: usize = 0x10001000 + VM_OFFSET;
+
+/// End of the physical memory mapped by the boot page table, see `boot.rs`.
+pub const BOOT_MAP_END: usize = PM_BASE + (1 << 30);
+
+/// Kernel virtual address of the PLIC.
+pub fn plic_base() -> usize {
+    crate::platform::get().plic.base + VM_OFFSET
+}
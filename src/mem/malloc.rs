@@ -3,7 +3,7 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::cmp::max;
 use core::mem::size_of;
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
 
 use crate::mem::palloc::Palloc;
@@ -79,34 +79,32 @@ impl Desc {
         }
     }
 
-    /// Allocates a free memory block from this descriptor
-    unsafe fn alloc(&mut self) -> *mut u8 {
-        if self.free_list.is_empty() {
-            let mut arena: NonNull<Arena> = NonNull::new_unchecked(
-                Palloc::alloc(1).unwrap_or_else(|| panic!("memory exhausted")),
-            )
-            .cast();
-            arena.as_mut().magic = ARENA_MAGIC;
-            arena.as_mut().desc = self as *const Self;
-            arena.as_mut().free_cnt = self.blocks_per_arena as u32;
-
-            for i in 0..self.blocks_per_arena {
-                let block = arena.as_ref().get_block(i);
-                self.free_list.push(block as *mut _);
-            }
-
-            self.total += PG_SIZE;
-            self.free += self.block_size * self.blocks_per_arena;
-        }
-
+    /// Allocates a free memory block from this descriptor, if it has any left
+    unsafe fn alloc(&mut self) -> Option<*mut u8> {
+        let block = self.free_list.pop()? as *mut u8;
         self.allocated += self.block_size;
         self.free -= self.block_size;
 
-        let block = self.free_list.pop().unwrap() as *mut u8;
         let arena = Arena::from_block(block);
         arena.free_cnt -= 1;
 
-        block
+        Some(block)
+    }
+
+    /// Makes the blocks of page `page` free blocks of this descriptor
+    unsafe fn add_arena(&mut self, page: *mut u8) {
+        let mut arena: NonNull<Arena> = NonNull::new_unchecked(page).cast();
+        arena.as_mut().magic = ARENA_MAGIC;
+        arena.as_mut().desc = self as *const Self;
+        arena.as_mut().free_cnt = self.blocks_per_arena as u32;
+
+        for i in 0..self.blocks_per_arena {
+            let block = arena.as_ref().get_block(i);
+            self.free_list.push(block as *mut _);
+        }
+
+        self.total += PG_SIZE;
+        self.free += self.block_size * self.blocks_per_arena;
     }
 
     /// Returns a memory block back to this descriptor
@@ -150,6 +148,8 @@ impl Heap {
     }
 
     /// Allocates a memory block that is in align with the layout.
+    ///
+    /// Returns null once memory is exhausted, as [`GlobalAlloc::alloc`] does.
     pub unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         if layout.size() == 0 {
             // return an invalid but well-aligned pointer for zero-sized requests
@@ -162,17 +162,29 @@ impl Heap {
 
         let size = max(layout.size().next_power_of_two(), layout.align());
         if size <= MAX_BLKSIZE {
-            return self.descs[size.trailing_zeros().saturating_sub(3) as usize]
-                .lock()
-                .alloc();
+            let desc = &self.descs[size.trailing_zeros().saturating_sub(3) as usize];
+            loop {
+                if let Some(block) = desc.lock().alloc() {
+                    return block;
+                }
+                // Asked for without the descriptor locked, as making room may
+                // allocate as well.
+                match Palloc::alloc(1) {
+                    Some(page) => desc.lock().add_arena(page),
+                    None => return ptr::null_mut(),
+                }
+            }
         }
 
         // delegate the allocation request to PALLOC
         let pages = (layout.size() + PG_SIZE - 1) / PG_SIZE;
+        let ptr = match Palloc::alloc(pages) {
+            Some(ptr) => ptr,
+            None => return ptr::null_mut(),
+        };
         self.spilled_alloc.fetch_add(size, Relaxed);
         self.spilled_total.fetch_add(pages, Relaxed);
-
-        Palloc::alloc(pages).unwrap_or_else(|| panic!("memory exhausted"))
+        ptr
     }
 
     /// Deallocates a memory block
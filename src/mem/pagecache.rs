@@ -0,0 +1,107 @@
+//! Page cache: pages of files in memory, shared by file I/O and mappings.
+//!
+//! A cached page is a frame from the user pool, keyed by inode number and the
+//! page aligned offset in the file. The cache holds a reference to the frame in
+//! the [`FrameTable`], as does every mapping of it. Writes go through to the
+//! disk right away, so a page only differs from the disk while a shared
+//! mapping has dirtied it.
+//!
+//! Pages no one else uses are reclaimed when the user pool runs out, before
+//! anything is evicted. A mapped page is evicted with all its mappings, see
+//! [`FrameTable::select_and_evict`]. All pages of an inode are dropped when it
+//! leaves memory.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::mem::frametable::FrameTable;
+use crate::mem::palloc::UserPool;
+use crate::mem::{PhysAddr, VM_OFFSET};
+use crate::sync::{Lazy, Mutex, Primitive};
+
+pub struct PageCache(Mutex<BTreeMap<(usize, usize), usize>, Primitive>);
+
+impl PageCache {
+    pub fn get() -> &'static Self {
+        static PAGE_CACHE: Lazy<PageCache> = Lazy::new(|| PageCache(Mutex::new(BTreeMap::new())));
+        &PAGE_CACHE
+    }
+
+    /// Caches `buf`, a frame holding the page of inode `inum` at `offset`, and
+    /// returns it held for the caller, see [`FrameTable::put`]. If the page is
+    /// cached already, `buf` is freed and the cached frame returned instead.
+    ///
+    /// The inode must be locked while `buf` is read in and cached, so that no
+    /// one changes the file meanwhile.
+    pub fn insert(inum: usize, offset: usize, buf: *mut u8) -> usize {
+        let mut cache = Self::get().0.lock();
+        match cache.get(&(inum, offset)) {
+            Some(&cached) => {
+                FrameTable::hold(cached);
+                unsafe { UserPool::dealloc_pages(buf, 1) };
+                cached
+            }
+            None => {
+                let frame = PhysAddr::from(buf).value();
+                cache.insert((inum, offset), frame);
+                FrameTable::hold(frame);
+                FrameTable::hold(frame);
+                frame
+            }
+        }
+    }
+
+    /// The frame caching the page of inode `inum` at `offset`, if it is
+    /// cached, held for the caller.
+    pub fn find(inum: usize, offset: usize) -> Option<usize> {
+        let cache = Self::get().0.lock();
+        let frame = *cache.get(&(inum, offset))?;
+        FrameTable::hold(frame);
+        Some(frame)
+    }
+
+    /// Whether the page of inode `inum` at `offset` is cached.
+    pub fn contains(inum: usize, offset: usize) -> bool {
+        Self::get().0.lock().contains_key(&(inum, offset))
+    }
+
+    /// Drops all pages of inode `inum`. Pages still mapped stay with their
+    /// mappings.
+    pub fn drop_inode(inum: usize) {
+        let mut cache = Self::get().0.lock();
+        let offsets = cache
+            .range((inum, 0)..=(inum, usize::MAX))
+            .map(|(&(_, offset), _)| offset)
+            .collect::<Vec<_>>();
+        for offset in offsets {
+            FrameTable::put(cache.remove(&(inum, offset)).unwrap());
+        }
+    }
+
+    /// Takes the frame of a page no one but the cache uses, when the user
+    /// pool runs out.
+    pub fn reclaim() -> Option<*mut u8> {
+        let mut cache = Self::get().0.lock();
+        let (&key, &frame) = cache
+            .iter()
+            .find(|(_, &frame)| FrameTable::take(frame, 1))?;
+        cache.remove(&key);
+        Some((frame + VM_OFFSET) as *mut u8)
+    }
+
+    /// Drops the page of inode `inum` at `offset`, cached in `frame`, if no one
+    /// but the cache and the caller holds it. The caller gives up its reference.
+    ///
+    /// ## Return
+    /// Whether the frame is the caller's now.
+    pub fn evict(inum: usize, offset: usize, frame: usize) -> bool {
+        let mut cache = Self::get().0.lock();
+        if cache.get(&(inum, offset)) == Some(&frame) && FrameTable::take(frame, 2) {
+            cache.remove(&(inum, offset));
+            true
+        } else {
+            FrameTable::put(frame);
+            false
+        }
+    }
+}
@@ -18,6 +18,12 @@
 //! This method replicates the kernel page table as a template for all user page tables.
 //! Having kernel pages existing in all user memory spaces, there will be no need to
 //! switch page table when doing a system call.
+//!
+//! ## Superpages
+//! A leaf entry of level 1 or 2 maps a whole 2 MiB megapage or 1 GiB gigapage,
+//! see [`PageSize`]. The kernel maps RAM with them, sparing page tables and TLB
+//! entries. Mapping a page inside a superpage splits it into a page table of
+//! smaller leaves first, so that the rest of it stays mapped.
 
 mod entry;
 
@@ -25,18 +31,40 @@ use core::ptr;
 use core::{arch::asm, mem::transmute};
 
 use crate::mem::{
-    layout::{MMIO_BASE, PLIC_BASE, VM_BASE},
     malloc::{kalloc, kfree},
     palloc::UserPool,
     utils::{PageAlign, PhysAddr, PG_SIZE},
 };
-use crate::mem::{KERN_BASE, PG_SHIFT, VM_OFFSET};
+use crate::mem::{KERN_BASE, PG_SHIFT, PM_BASE, VM_OFFSET};
+use crate::platform::{Platform, Region, Regions};
 use crate::sync::OnceCell;
+use crate::{OsError, Result};
 
 pub use self::entry::*;
 
 const PPN_MASK: usize = (1 << 44) - 1;
 
+/// Size of the memory a leaf entry maps, by the level of the entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    /// 4 KiB, mapped by a level-0 entry.
+    Page = 0,
+    /// 2 MiB, mapped by a level-1 entry.
+    Mega = 1,
+    /// 1 GiB, mapped by a level-2 entry.
+    Giga = 2,
+}
+
+impl PageSize {
+    pub const fn bytes(self) -> usize {
+        PG_SIZE << (9 * self as usize)
+    }
+
+    const fn level(self) -> u32 {
+        self as u32
+    }
+}
+
 /// Reference to a in-memory page table
 pub struct PageTable {
     /// Each page table has 512 entries.
@@ -62,29 +90,111 @@ impl PageTable {
         }
     }
 
-    /// Maps `pa` to `va` and allocates page table when necessary.
+    /// Maps `pa` to `va` with 4 KiB pages and allocates page table when necessary.
+    /// Superpages in the way are split.
     pub fn map(&mut self, pa: PhysAddr, va: usize, size: usize, flag: PTEFlags) {
+        self.map_leaves(pa, va, size, flag, PageSize::Page);
+    }
+
+    /// Maps `pa` to `va` like [`map`](Self::map), but with the largest leaves
+    /// that the alignment of both addresses and the size left allow.
+    pub fn map_superpages(&mut self, pa: PhysAddr, va: usize, size: usize, flag: PTEFlags) {
+        self.map_leaves(pa, va, size, flag, PageSize::Giga);
+    }
+
+    fn map_leaves(&mut self, pa: PhysAddr, va: usize, size: usize, flag: PTEFlags, max: PageSize) {
         assert!(pa.is_aligned() && va.is_aligned(), "address misaligns");
 
         let pa_end = pa.value() + size;
         let (mut pa, mut va) = (pa.value(), va);
 
         while pa < pa_end {
-            let mut l1_table = self.walk_or_create(Self::px(2, va), flag.contains(PTEFlags::G));
-            let l0_table = l1_table.walk_or_create(Self::px(1, va), flag.contains(PTEFlags::G));
-            l0_table.entries[Self::px(0, va)] = Entry::new(PhysAddr::from_pa(pa), flag);
-            pa += PG_SIZE;
-            va += PG_SIZE;
+            let leaf = [PageSize::Giga, PageSize::Mega]
+                .iter()
+                .copied()
+                .filter(|leaf| leaf.level() <= max.level())
+                .find(|leaf| (pa | va) % leaf.bytes() == 0 && pa + leaf.bytes() <= pa_end)
+                .unwrap_or(PageSize::Page);
+            let entry = self.leaf_or_create(va, leaf, flag.contains(PTEFlags::G));
+            if leaf != PageSize::Page && entry.is_valid() && !entry.is_leaf() {
+                // The pages mapped below are replaced, their page tables go.
+                unsafe { Self::free_table(*entry, leaf.level()) };
+            }
+            *entry = Entry::new(PhysAddr::from_pa(pa), flag);
+            pa += leaf.bytes();
+            va += leaf.bytes();
         }
     }
 
-    /// Finds the corresponding entry by the given virtual address
+    /// Finds the entry of the 4 KiB page mapping `va`, `None` in a superpage,
+    /// whose entry [`get_leaf`](Self::get_leaf) finds.
     pub fn get_pte(&self, va: usize) -> Option<&Entry> {
-        self.walk(Self::px(2, va)).and_then(|l1_table| {
-            l1_table
-                .walk(Self::px(1, va))
-                .map(|l0_table| l0_table.entries.get(Self::px(0, va)).unwrap())
-        })
+        self.get_leaf(va)
+            .filter(|&(_, size)| size == PageSize::Page)
+            .map(|(entry, _)| entry)
+    }
+
+    /// Finds the leaf entry mapping `va`, and the size of memory it maps.
+    /// Entries of 4 KiB pages are found even if they are not valid.
+    pub fn get_leaf(&self, va: usize) -> Option<(&Entry, PageSize)> {
+        let l2_entry = &self.entries[Self::px(2, va)];
+        if l2_entry.is_valid() && l2_entry.is_leaf() {
+            return Some((l2_entry, PageSize::Giga));
+        }
+        let l1_table = self.walk(Self::px(2, va))?;
+        let l1_entry = &l1_table.entries[Self::px(1, va)];
+        if l1_entry.is_valid() && l1_entry.is_leaf() {
+            return Some((l1_entry, PageSize::Mega));
+        }
+        let l0_table = l1_table.walk(Self::px(1, va))?;
+        Some((&l0_table.entries[Self::px(0, va)], PageSize::Page))
+    }
+
+    /// Calls `f` on every user leaf entry with its virtual address, including
+    /// lazily mapped entries that are not valid yet.
+    pub fn for_each_user_entry<F: FnMut(usize, &mut Entry)>(&mut self, mut f: F) {
+        // Next level of a user mapping. Kernel mappings are global all the way down.
+        let subtable = |entry: &Entry| {
+            (entry.is_valid() && !entry.is_global() && !entry.is_leaf())
+                .then(|| unsafe { PageTable::from_raw(entry.pa().into_va() as *mut _) })
+        };
+
+        for (i2, l1_table) in self.entries.iter().enumerate() {
+            let l1_table = match subtable(l1_table) {
+                Some(table) => table,
+                None => continue,
+            };
+            for (i1, l0_table) in l1_table.entries.iter().enumerate() {
+                let l0_table = match subtable(l0_table) {
+                    Some(table) => table,
+                    None => continue,
+                };
+                for (i0, entry) in l0_table.entries.iter_mut().enumerate() {
+                    if !entry.flag().is_empty() {
+                        f((i2 << 30) | (i1 << 21) | (i0 << PG_SHIFT), entry);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flushes stale translations after entries of the active page table are modified.
+    pub fn flush_tlb() {
+        unsafe { asm!("sfence.vma zero, zero") };
+    }
+
+    /// Frees the page tables below `entry` of level `level`, but not the pages
+    /// they map.
+    unsafe fn free_table(entry: Entry, level: u32) {
+        let table = PageTable::from_raw(entry.pa().into_va() as *mut _);
+        if level > 1 {
+            table
+                .entries
+                .iter()
+                .filter(|entry| entry.is_valid() && !entry.is_leaf())
+                .for_each(|entry| Self::free_table(*entry, level - 1));
+        }
+        kfree(table.entries.as_mut_ptr().cast(), PG_SIZE, PG_SIZE);
     }
 
     /// Free all memory used by this pagetable back to where they were allocated.
@@ -109,15 +219,21 @@ impl PageTable {
     }
 
     /// Allocates a page to build a new page table
-    fn new() -> Self {
+    ///
+    /// ## Return
+    /// - `Err(OutOfMemory)`: kernel memory is exhausted, user pool included.
+    fn new() -> Result<Self> {
         let page = kalloc(PG_SIZE, PG_SIZE);
+        if page.is_null() {
+            return Err(OsError::OutOfMemory);
+        }
 
         unsafe {
             // Clear the pagetable. A page table is exactly the size of
             // a page and must always be aligned to a page boundary.
             ptr::write_bytes(page, 0, PG_SIZE);
 
-            Self::from_raw(page.cast())
+            Ok(Self::from_raw(page.cast()))
         }
     }
 
@@ -147,18 +263,55 @@ impl PageTable {
             .map(|e| unsafe { Self::from_raw(e.pa().into_va() as *mut _) })
     }
 
-    fn walk_or_create(&mut self, index: usize, is_global: bool) -> PageTable {
+    /// The next-level table of entry `index`, a new one if there is none. A
+    /// superpage there, of a `level` entry, is split first.
+    fn walk_or_create(&mut self, index: usize, level: u32, is_global: bool) -> PageTable {
         let mut flag = PTEFlags::V;
         flag.set(PTEFlags::G, is_global);
 
+        let entry = self.entries[index];
+        if entry.is_valid() && entry.is_leaf() {
+            self.split(index, level);
+        }
+
         self.walk(index).unwrap_or_else(|| {
-            let table = PageTable::new();
+            let table = PageTable::new().expect("kernel memory exhausted, user pool included");
             let pa = PhysAddr::from(table.entries.as_ptr());
             self.entries[index] = Entry::new(pa, flag);
             table
         })
     }
 
+    /// The entry for a leaf of `size` mapping `va`, creating page tables down
+    /// to it and splitting superpages above it.
+    fn leaf_or_create(&mut self, va: usize, size: PageSize, is_global: bool) -> &mut Entry {
+        if size == PageSize::Giga {
+            return &mut self.entries[Self::px(2, va)];
+        }
+        let mut table = self.walk_or_create(Self::px(2, va), 2, is_global);
+        if size == PageSize::Page {
+            table = table.walk_or_create(Self::px(1, va), 1, is_global);
+        }
+        let PageTable { entries } = table;
+        &mut entries[Self::px(size.level(), va)]
+    }
+
+    /// Replaces the superpage leaf at `index`, of a `level` entry, with a page
+    /// table of leaves one level down, mapping the same memory the same way.
+    fn split(&mut self, index: usize, level: u32) {
+        let superpage = self.entries[index];
+        let table = PageTable::new().expect("kernel memory exhausted, user pool included");
+        let size = PG_SIZE << (9 * (level - 1));
+        for (i, entry) in table.entries.iter_mut().enumerate() {
+            let pa = PhysAddr::from_pa(superpage.pa().value() + i * size);
+            *entry = Entry::new(pa, superpage.flag());
+        }
+
+        let mut flag = PTEFlags::V;
+        flag.set(PTEFlags::G, superpage.is_global());
+        self.entries[index] = Entry::new(PhysAddr::from(table.entries.as_ptr()), flag);
+    }
+
     fn px(level: u32, va: usize) -> usize {
         fn px_shift(level: u32) -> usize {
             PG_SHIFT + 9 * level as usize
@@ -177,24 +330,25 @@ impl KernelPgTable {
 
     /// Clones entries in the kernel page table. Use them as a template for user page tables.
     /// This method ensures all kernel memory mappings exist in user memory space.
-    pub fn clone() -> PageTable {
-        let other = PageTable::new();
+    pub fn clone() -> Result<PageTable> {
+        let other = PageTable::new()?;
         other.entries.copy_from_slice(Self::get().entries);
-        other
+        Ok(other)
     }
 
-    /// Initializes the kernel page table which manages `ram_size` bytes of memory
-    pub fn init(ram_size: usize) {
-        Self::instance().init(|| Self::init_inner(ram_size))
+    /// Initializes the kernel page table, which maps all RAM and devices of `platform`
+    pub fn init(platform: &Platform) {
+        Self::instance().init(|| Self::init_inner(platform))
     }
 
     /// Set up all kernel page table entries.
     ///
     /// At the entrance of kernel, a crude page table was set up to support basic
     /// paging capability. To strengthen memory protection, it's necessary to set up
-    /// a fine-grained page table.
-    pub fn init_inner(ram_size: usize) -> PageTable {
-        let mut root = PageTable::new();
+    /// a fine-grained page table. RAM past the kernel text is mapped with
+    /// superpages where it is aligned enough.
+    pub fn init_inner(platform: &Platform) -> PageTable {
+        let mut root = PageTable::new().unwrap();
 
         // Kernel's code and data exist in all memory spaces, therefore the global bit is set.
         let rx = PTEFlags::R | PTEFlags::X | PTEFlags::G | PTEFlags::V;
@@ -206,19 +360,38 @@ impl KernelPgTable {
 
         let etext = etext as usize;
         let kr_base = KERN_BASE + VM_OFFSET;
-        let kr_end = VM_BASE + ram_size;
 
         // map kernel text executable and read-only.
         root.map(PhysAddr::from_pa(KERN_BASE), kr_base, etext - kr_base, rx);
 
         // map kernel data and the physical RAM we'll make use of.
-        root.map(PhysAddr::from(etext), etext, kr_end - etext, rw);
-
-        // PLIC
-        root.map(PhysAddr::from(PLIC_BASE), PLIC_BASE, 0x400000, rw);
-
-        // virtio mmio disk interface
-        root.map(PhysAddr::from(MMIO_BASE), MMIO_BASE, PG_SIZE, rw);
+        let mut text = Regions::new();
+        text.push(Region::new(0, etext - VM_OFFSET));
+        platform
+            .memory
+            .iter()
+            .filter(|r| r.base >= PM_BASE)
+            .for_each(|r| {
+                text.subtract_from(r.page_inner(), |ram| {
+                    root.map_superpages(
+                        PhysAddr::from_pa(ram.base),
+                        ram.base + VM_OFFSET,
+                        ram.size,
+                        rw,
+                    )
+                })
+            });
+
+        // PLIC and virtio mmio interfaces
+        let mmio = core::iter::once(platform.plic).chain(platform.virtio().iter().map(|v| v.reg));
+        for dev in mmio.map(|r| r.page_outer()) {
+            root.map(
+                PhysAddr::from_pa(dev.base),
+                dev.base + VM_OFFSET,
+                dev.size,
+                rw,
+            );
+        }
 
         root.activate();
         root
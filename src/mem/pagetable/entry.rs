@@ -28,6 +28,12 @@ bitflags::bitflags! {
         const A = 0b0100_0000;
         /// Dirty
         const D = 0b1000_0000;
+        /// Copy-on-write (the first bit reserved for software). The page is
+        /// shared read-only and becomes writable once it is copied.
+        const COW = 0b1_0000_0000;
+        /// Shared mapping of a file (the second bit reserved for software).
+        /// The page is written in place, never copied.
+        const SHARED = 0b10_0000_0000;
     }
 }
 
@@ -75,6 +81,14 @@ impl Entry {
         self.flag().contains(PTEFlags::X)
     }
 
+    pub fn is_cow(&self) -> bool {
+        self.flag().contains(PTEFlags::COW)
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.flag().contains(PTEFlags::SHARED)
+    }
+
     pub fn is_accessed(&self) -> bool {
         self.flag().contains(PTEFlags::A)
     }
@@ -1,15 +1,36 @@
 //! Global Page Allocator
 
 use core::cmp::min;
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 
+use crate::mem::frametable::FrameTable;
 use crate::mem::utils::*;
+use crate::sbi::interrupt;
 use crate::sync::{Intr, Lazy, Mutex};
+use crate::thread;
+use crate::Result;
 
 // BuddyAllocator allocates at most `1<<MAX_ORDER` pages at a time
 const MAX_ORDER: usize = 8;
-// How many pages are there in the user memory pool
+// How many pages are there in the user memory pool by default
 pub(super) const USER_POOL_LIMIT: usize = 256;
 
+static USER_POOL_PAGES: AtomicUsize = AtomicUsize::new(USER_POOL_LIMIT);
+
+/// Start and end of the user memory pool.
+static USER_POOL_RANGE: (AtomicUsize, AtomicUsize) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+/// How many pages are there in the user memory pool
+pub fn user_pool_pages() -> usize {
+    USER_POOL_PAGES.load(SeqCst)
+}
+
+/// Set the size of the user memory pool, for the `user_pool_pages=` kernel parameter
+pub fn set_user_pool_pages(value: &str) -> Result<()> {
+    USER_POOL_PAGES.store(crate::cmdline::parse_count(value)?, SeqCst);
+    Ok(())
+}
+
 /// Buddy Allocator. It allocates and deallocates memory page-wise.
 #[derive(Debug)]
 struct BuddyAllocator {
@@ -124,12 +145,27 @@ impl Palloc {
     }
 
     /// Allocate n pages of a consecutive memory segment
+    ///
+    /// Once kernel memory runs out, pages are taken from the [`UserPool`]. A
+    /// single page is made room for there as for a user page, by eviction or
+    /// by killing a process, see [`FrameTable::alloc_frame`]. That blocks, and
+    /// takes locks, so it is only done with interrupts on and no sleep lock held.
     pub unsafe fn alloc(n: usize) -> Option<*mut u8> {
-        Self::instance().lock().alloc(n)
+        let ptr = Self::instance().lock().alloc(n);
+        ptr.or_else(|| UserPool::alloc_pages(n)).or_else(|| {
+            if n == 1 && interrupt::get() && !thread::current().holds_locks() {
+                FrameTable::alloc_frame().ok()
+            } else {
+                None
+            }
+        })
     }
 
     /// Free n pages of memory starting at `ptr`
     pub unsafe fn dealloc(ptr: *mut u8, n: usize) {
+        if UserPool::contains(ptr) {
+            return UserPool::dealloc_pages(ptr, n);
+        }
         Self::instance().lock().dealloc(ptr, n)
     }
 
@@ -157,9 +193,21 @@ impl UserPool {
 
     /// Initialize the page-based allocator
     pub unsafe fn init(start: usize, end: usize) {
+        USER_POOL_RANGE.0.store(start, SeqCst);
+        USER_POOL_RANGE.1.store(end, SeqCst);
         Self::instance().lock().insert_range(start, end);
     }
 
+    /// Kernel virtual addresses of the user pool.
+    pub fn range() -> core::ops::Range<usize> {
+        USER_POOL_RANGE.0.load(SeqCst)..USER_POOL_RANGE.1.load(SeqCst)
+    }
+
+    /// Whether `ptr` is in the user pool.
+    pub fn contains(ptr: *mut u8) -> bool {
+        Self::range().contains(&(ptr as usize))
+    }
+
     fn instance() -> &'static Mutex<BuddyAllocator, Intr> {
         static USERPOOL: UserPool = UserPool(Lazy::new(|| Mutex::new(BuddyAllocator::empty())));
 
@@ -0,0 +1,110 @@
+//! Page replacement
+//!
+//! [`FrameTable`](crate::mem::frametable::FrameTable) relies on a replacement
+//! policy to choose which page to evict when the user pool runs out. Clock is
+//! the default, you can add new policies by implementing [`Replace`] trait.
+//!
+
+pub mod aging;
+pub mod clock;
+pub mod fifo;
+pub mod second_chance;
+
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use crate::mem::frametable::FrameDesc;
+use crate::{OsError, Result};
+
+use self::aging::Aging;
+use self::clock::Clock;
+use self::fifo::Fifo;
+use self::second_chance::SecondChance;
+
+/// Replacement policies, selected with the `replace=` kernel parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Clock,
+    SecondChance,
+    Aging,
+    Fifo,
+}
+
+const POLICIES: [(&str, Policy); 4] = [
+    ("clock", Policy::Clock),
+    ("second-chance", Policy::SecondChance),
+    ("aging", Policy::Aging),
+    ("fifo", Policy::Fifo),
+];
+
+static POLICY: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the replacement policy in use
+pub fn policy() -> Policy {
+    POLICIES[POLICY.load(SeqCst)].1
+}
+
+/// Name of the replacement policy in use
+pub fn policy_name() -> &'static str {
+    POLICIES[POLICY.load(SeqCst)].0
+}
+
+/// Select the replacement policy by its name. Takes effect only before the
+/// first page is evicted.
+pub fn set_policy(name: &str) -> Result<()> {
+    let index = POLICIES
+        .iter()
+        .position(|(n, _)| *n == name)
+        .ok_or(OsError::InvalidArgument)?;
+    POLICY.store(index, SeqCst);
+    Ok(())
+}
+
+/// The replacer for the policy selected at boot.
+pub enum Replacer {
+    Clock(Clock),
+    SecondChance(SecondChance),
+    Aging(Aging),
+    Fifo(Fifo),
+}
+
+impl Default for Replacer {
+    fn default() -> Self {
+        match policy() {
+            Policy::Clock => Self::Clock(Clock::default()),
+            Policy::SecondChance => Self::SecondChance(SecondChance::default()),
+            Policy::Aging => Self::Aging(Aging),
+            Policy::Fifo => Self::Fifo(Fifo),
+        }
+    }
+}
+
+impl Replace for Replacer {
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize> {
+        match self {
+            Self::Clock(r) => r.select(frames, evictable),
+            Self::SecondChance(r) => r.select(frames, evictable),
+            Self::Aging(r) => r.select(frames, evictable),
+            Self::Fifo(r) => r.select(frames, evictable),
+        }
+    }
+}
+
+/// Basic functionalities of page replacement policies
+pub trait Replace: Default {
+    /// Choose the frame to evict: its index in `frames` for which `evictable`
+    /// holds, `None` if there is no such frame. Accessed bits may be cleared
+    /// along the way.
+    ///
+    /// `frames` are all frames of the user pool, by physical page number, free
+    /// ones included. The chosen one is unmapped by the caller, and others may
+    /// be mapped and freed between calls.
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize>;
+}
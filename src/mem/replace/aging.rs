@@ -0,0 +1,29 @@
+use crate::mem::frametable::FrameDesc;
+use crate::mem::replace::Replace;
+
+/// Approximate LRU by aging: on every eviction, the accessed bit of each frame
+/// is shifted into the top of its age, and the frame with the lowest age goes.
+#[derive(Default)]
+pub struct Aging;
+
+impl Replace for Aging {
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize> {
+        for frame in frames.iter_mut() {
+            let used = frame.is_used();
+            frame.age = (frame.age >> 1) | ((used as u8) << 7);
+            if used {
+                frame.unset_used();
+            }
+        }
+        frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| evictable(frame))
+            .min_by_key(|(_, frame)| frame.age)
+            .map(|(index, _)| index)
+    }
+}
@@ -0,0 +1,31 @@
+use crate::mem::frametable::FrameDesc;
+use crate::mem::replace::Replace;
+
+/// Clock: the hand sweeps over the frames, giving accessed ones another round.
+#[derive(Default)]
+pub struct Clock {
+    hand: usize,
+}
+
+impl Replace for Clock {
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize> {
+        if !frames.iter().any(evictable) {
+            return None;
+        }
+        loop {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % frames.len();
+            let frame = &frames[index];
+            if evictable(frame) {
+                if !frame.is_used() {
+                    return Some(index);
+                }
+                frame.unset_used();
+            }
+        }
+    }
+}
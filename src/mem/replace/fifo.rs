@@ -0,0 +1,21 @@
+use crate::mem::frametable::FrameDesc;
+use crate::mem::replace::Replace;
+
+/// FIFO: the page mapped first goes first, accessed or not.
+#[derive(Default)]
+pub struct Fifo;
+
+impl Replace for Fifo {
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize> {
+        frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| evictable(frame))
+            .min_by_key(|(_, frame)| frame.since)
+            .map(|(index, _)| index)
+    }
+}
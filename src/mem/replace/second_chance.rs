@@ -0,0 +1,41 @@
+use crate::mem::frametable::FrameDesc;
+use crate::mem::replace::Replace;
+
+/// Second chance preferring clean pages, which are evicted without being
+/// written anywhere.
+///
+/// The hand first looks for a frame neither accessed nor dirty, then for one
+/// not accessed, clearing accessed bits on the way, and so on.
+#[derive(Default)]
+pub struct SecondChance {
+    hand: usize,
+}
+
+impl Replace for SecondChance {
+    fn select(
+        &mut self,
+        frames: &mut [FrameDesc],
+        evictable: &dyn Fn(&FrameDesc) -> bool,
+    ) -> Option<usize> {
+        if !frames.iter().any(evictable) {
+            return None;
+        }
+        loop {
+            for clean_only in [true, false] {
+                for _ in 0..frames.len() {
+                    let index = self.hand;
+                    self.hand = (self.hand + 1) % frames.len();
+                    let frame = &frames[index];
+                    if evictable(frame) {
+                        if !frame.is_used() && (!clean_only || !frame.is_dirty()) {
+                            return Some(index);
+                        }
+                        if !clean_only {
+                            frame.unset_used();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,13 +3,19 @@ use core::fmt::Debug;
 use alloc::collections::BTreeMap;
 
 use crate::fs::File;
+use crate::mem::swaptable::SwapTable;
 use crate::sync::{Intr, Mutex};
 
 #[derive(Clone, Debug)]
 pub enum SupPageEntry {
     InSwap(usize),
+    /// Private copy of `len` bytes of a file at an offset, the rest zeroed.
     InFileLazyLoad(File, usize, usize),
-    InFileMapped(File, usize, usize),
+    /// Shared mapping of a file at an offset, see
+    /// [`PageCache`](crate::mem::pagecache::PageCache).
+    InFileMapped(File, usize),
+    /// Anonymous memory, zero-filled on first access.
+    Zeroed,
 }
 
 pub struct SupPageTable(pub Mutex<BTreeMap<usize, SupPageEntry>, Intr>);
@@ -29,10 +35,14 @@ impl SupPageTable {
             .insert(va, SupPageEntry::InFileLazyLoad(file, file_offset, len));
     }
 
-    pub fn map_in_file_mapped(&self, va: usize, file: File, file_offset: usize, len: usize) {
+    pub fn map_in_file_mapped(&self, va: usize, file: File, file_offset: usize) {
         self.0
             .lock()
-            .insert(va, SupPageEntry::InFileMapped(file, file_offset, len));
+            .insert(va, SupPageEntry::InFileMapped(file, file_offset));
+    }
+
+    pub fn map_zeroed(&self, va: usize) {
+        self.0.lock().insert(va, SupPageEntry::Zeroed);
     }
 
     pub fn remove(&self, va: usize) {
@@ -42,4 +52,18 @@ impl SupPageTable {
     pub fn query(&self, va: usize) -> Option<SupPageEntry> {
         self.0.lock().get(&va).cloned()
     }
+
+    /// Drops all entries, freeing the swap slots they hold. Returns how many
+    /// pages were in swap.
+    pub fn release(&self) -> usize {
+        let entries = core::mem::take(&mut *self.0.lock());
+        let mut swapped = 0;
+        for spte in entries.into_values() {
+            if let SupPageEntry::InSwap(offset) = spte {
+                SwapTable::dealloc(offset);
+                swapped += 1;
+            }
+        }
+        swapped
+    }
 }
@@ -1,23 +1,35 @@
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use fs::disk::Swap;
 
 use crate::{
-    mem::PG_SIZE,
+    io::{Read, Seek, SeekFrom, Write},
+    mem::{zswap::ZSwap, PG_SIZE},
     sync::{Lazy, Mutex, Primitive},
+    Result,
 };
 
-pub struct SwapTable(Mutex<VecDeque<usize>, Primitive>);
+struct SwapTableInner {
+    /// Offsets of free swap slots
+    free: VecDeque<usize>,
+    /// Slots held by more than one page, with their extra references
+    shared: BTreeMap<usize, usize>,
+}
+
+pub struct SwapTable(Mutex<SwapTableInner, Primitive>);
 
 impl SwapTable {
     pub fn get() -> &'static Self {
         static SWAP_TABLE: Lazy<SwapTable> = Lazy::new(|| {
-            let swaptable = SwapTable(Mutex::new(VecDeque::new()));
+            let swaptable = SwapTable(Mutex::new(SwapTableInner {
+                free: VecDeque::new(),
+                shared: BTreeMap::new(),
+            }));
 
             {
                 let mut spt = swaptable.0.lock();
                 let page_num = Swap::page_num();
                 for i in 0..page_num {
-                    spt.push_back(i * PG_SIZE);
+                    spt.free.push_back(i * PG_SIZE);
                 }
             }
 
@@ -26,15 +38,55 @@ impl SwapTable {
         &SWAP_TABLE
     }
 
-    pub fn alloc() -> usize {
-        Self::get()
-            .0
-            .lock()
-            .pop_front()
-            .unwrap_or_else(|| panic!("run out of swap"))
+    /// Takes a free swap slot, if there is one.
+    pub fn alloc() -> Option<usize> {
+        Self::get().0.lock().free.pop_front()
+    }
+
+    /// Whether a swap slot is free.
+    pub fn has_free() -> bool {
+        !Self::get().0.lock().free.is_empty()
+    }
+
+    /// Add a reference to a swap slot, when a swapped out page is shared by a forked process.
+    pub fn share(offset: usize) {
+        *Self::get().0.lock().shared.entry(offset).or_insert(0) += 1;
     }
 
+    /// Drop a reference to a swap slot. The slot is freed with the last one.
     pub fn dealloc(offset: usize) {
-        Self::get().0.lock().push_back(offset);
+        let mut table = Self::get().0.lock();
+        match table.shared.get_mut(&offset) {
+            Some(1) => {
+                table.shared.remove(&offset);
+            }
+            Some(refs) => *refs -= 1,
+            None => {
+                ZSwap::invalidate(offset);
+                table.free.push_back(offset);
+            }
+        }
+    }
+
+    /// Writes `page` to swap slot `offset`, compressed in memory if there is room.
+    pub fn write(offset: usize, page: &[u8; PG_SIZE]) -> Result<()> {
+        if ZSwap::store(offset, page) {
+            return Ok(());
+        }
+        let mut swap = Swap::lock();
+        swap.seek(SeekFrom::Start(offset))?;
+        swap.write(page)?;
+        Ok(())
+    }
+
+    /// Reads swap slot `offset` into `page`.
+    pub fn read(offset: usize, page: &mut [u8; PG_SIZE]) -> Result<()> {
+        if ZSwap::load(offset, page) {
+            return Ok(());
+        }
+        let mut swap = Swap::lock();
+        swap.seek(SeekFrom::Start(offset))?;
+        swap.read(page)?;
+        Ok(())
     }
 }
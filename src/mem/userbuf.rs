@@ -9,7 +9,13 @@ use crate::error::OsError;
 use crate::mem::in_kernel_space;
 use crate::Result;
 
-use super::PageAlign;
+use super::{PageAlign, PG_MASK, PG_SIZE};
+
+/// Whether `va` is in a page the user may not access, see `PROT_NONE`. The
+/// kernel can still access it, so it has to check.
+fn is_inaccessible(va: usize) -> bool {
+    super::get_leaf(va).map_or(false, |(pte, _)| !pte.flag().is_empty() && !pte.is_user())
+}
 
 /// Read a single byte from user space.
 ///
@@ -17,7 +23,7 @@ use super::PageAlign;
 /// - `Ok(byte)`
 /// - `Err`: A page fault happened.
 pub fn read_user_byte(user_src: *const u8) -> Result<u8> {
-    if in_kernel_space(user_src as usize) {
+    if in_kernel_space(user_src as usize) || is_inaccessible(user_src as usize) {
         return Err(OsError::BadPtr);
     }
 
@@ -37,7 +43,7 @@ pub fn read_user_byte(user_src: *const u8) -> Result<u8> {
 /// - `Ok(())`
 /// - `Err`: A page fault happened.
 pub fn write_user_byte(user_src: *const u8, value: u8) -> Result<()> {
-    if in_kernel_space(user_src as usize) {
+    if in_kernel_space(user_src as usize) || is_inaccessible(user_src as usize) {
         return Err(OsError::BadPtr);
     }
 
@@ -75,6 +81,66 @@ pub fn read_user_doubleword(ptr: usize) -> Result<u64> {
     )
 }
 
+/// Split the user buffer at `ptr` of `len` bytes by page, as `(va, offset,
+/// len)` spans, `offset` being relative to `ptr`. Each page is checked once.
+/// The buffer must not wrap around the address space.
+fn spans(ptr: usize, len: usize) -> impl Iterator<Item = Result<(usize, usize, usize)>> {
+    let mut off = 0;
+    core::iter::from_fn(move || {
+        if off == len {
+            return None;
+        }
+        let va = ptr + off;
+        let span = (PG_SIZE - (va & PG_MASK)).min(len - off);
+        off += span;
+        if in_kernel_space(va) || is_inaccessible(va) {
+            return Some(Err(OsError::BadPtr));
+        }
+        Some(Ok((va, off - span, span)))
+    })
+}
+
+/// Read `buf.len()` bytes from user space at `ptr` into `buf`.
+///
+/// Each page is checked once, then copied at once. A page that is not present
+/// is faulted in by the copy, in the page fault handler.
+///
+/// ## Return
+/// - `Ok(())`
+/// - `Err`: A page fault happened.
+pub fn read_user_bytes(ptr: usize, buf: &mut [u8]) -> Result<()> {
+    if ptr.checked_add(buf.len()).is_none() {
+        return Err(OsError::BadPtr);
+    }
+    for span in spans(ptr, buf.len()) {
+        let (va, off, len) = span?;
+        let dst = buf[off..off + len].as_mut_ptr();
+        if unsafe { __knrl_copy_from_usr(dst, va as *const u8, len) } != 0 {
+            return Err(OsError::BadPtr);
+        }
+    }
+    Ok(())
+}
+
+/// Write `buf` to user space at `ptr`, a page at a time like [`read_user_bytes`].
+///
+/// ## Return
+/// - `Ok(())`
+/// - `Err`: A page fault happened.
+pub fn write_user_bytes(ptr: usize, buf: &[u8]) -> Result<()> {
+    if ptr.checked_add(buf.len()).is_none() {
+        return Err(OsError::BadPtr);
+    }
+    for span in spans(ptr, buf.len()) {
+        let (va, off, len) = span?;
+        let src = buf[off..off + len].as_ptr();
+        if unsafe { __knrl_copy_to_usr(va as *mut u8, src, len) } != 0 {
+            return Err(OsError::BadPtr);
+        }
+    }
+    Ok(())
+}
+
 /// Read a c-style (`NULL` terminated) string from user space.
 ///
 /// ## Return
@@ -150,6 +216,12 @@ extern "C" {
     pub fn __knrl_write_usr_byte(user_src: *const u8, value: u8) -> u8;
     pub fn __knrl_write_usr_byte_pc();
     pub fn __knrl_write_usr_exit();
+    pub fn __knrl_copy_from_usr(dst: *mut u8, user_src: *const u8, len: usize) -> u8;
+    pub fn __knrl_copy_from_usr_pc();
+    pub fn __knrl_copy_from_usr_exit();
+    pub fn __knrl_copy_to_usr(user_dst: *mut u8, src: *const u8, len: usize) -> u8;
+    pub fn __knrl_copy_to_usr_pc();
+    pub fn __knrl_copy_to_usr_exit();
 }
 
 global_asm! {r#"
@@ -182,4 +254,43 @@ global_asm! {r#"
         # pagefault handler will set a1 if any error occurs
         mv a0, a1
         ret
+
+        .globl __knrl_copy_from_usr
+        .globl __knrl_copy_from_usr_exit
+        .globl __knrl_copy_from_usr_pc
+
+    __knrl_copy_from_usr:
+        mv t1, a1
+        li a1, 0
+    __knrl_copy_from_usr_pc:
+        lb t0, (t1)
+        sb t0, (a0)
+        addi t1, t1, 1
+        addi a0, a0, 1
+        addi a2, a2, -1
+        bnez a2, __knrl_copy_from_usr_pc
+    __knrl_copy_from_usr_exit:
+        # pagefault handler will set a1 if any error occurs
+        mv a0, a1
+        ret
+
+        .globl __knrl_copy_to_usr
+        .globl __knrl_copy_to_usr_exit
+        .globl __knrl_copy_to_usr_pc
+
+    __knrl_copy_to_usr:
+        mv t1, a1
+        li a1, 0
+    1:
+        lb t0, (t1)
+    __knrl_copy_to_usr_pc:
+        sb t0, (a0)
+        addi t1, t1, 1
+        addi a0, a0, 1
+        addi a2, a2, -1
+        bnez a2, 1b
+    __knrl_copy_to_usr_exit:
+        # pagefault handler will set a1 if any error occurs
+        mv a0, a1
+        ret
 "#}
@@ -0,0 +1,129 @@
+//! Paging statistics of user processes.
+
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use crate::thread::Thread;
+
+/// Paging events counted for a process.
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    /// A page fault was handled.
+    Fault,
+    /// A page fault read a page from disk.
+    MajorFault,
+    /// A page was evicted.
+    Eviction,
+    /// A page was read back from swap.
+    SwapIn,
+}
+
+/// Where pages of a process are, counted for it.
+#[derive(Debug, Clone, Copy)]
+pub enum Pages {
+    /// In frames mapped by the process.
+    Resident,
+    /// In swap slots.
+    Swapped,
+    /// In frames of the page cache mapped by the process, also [`Resident`](Pages::Resident).
+    FileMapped,
+}
+
+/// Counts of paging events and pages of a process, see [`Event`] and [`Pages`].
+#[derive(Default)]
+pub struct VmStats {
+    faults: AtomicUsize,
+    major_faults: AtomicUsize,
+    evictions: AtomicUsize,
+    swap_ins: AtomicUsize,
+    resident: AtomicUsize,
+    swapped: AtomicUsize,
+    file_mapped: AtomicUsize,
+    /// The most pages ever resident at once.
+    peak_resident: AtomicUsize,
+}
+
+impl VmStats {
+    /// Counts `event` for the process `thread` runs, if any.
+    pub fn count(thread: &Thread, event: Event) {
+        let stats = match &thread.userproc {
+            Some(proc) => &proc.vmstats,
+            None => return,
+        };
+        let counter = match event {
+            Event::Fault => &stats.faults,
+            Event::MajorFault => &stats.major_faults,
+            Event::Eviction => &stats.evictions,
+            Event::SwapIn => &stats.swap_ins,
+        };
+        counter.fetch_add(1, SeqCst);
+    }
+
+    /// Counts `n` more `pages` for the process `thread` runs, if any.
+    pub fn add(thread: &Thread, pages: Pages, n: usize) {
+        if let Some(proc) = &thread.userproc {
+            let stats = &proc.vmstats;
+            let old = stats.pages(pages).fetch_add(n, SeqCst);
+            if let Pages::Resident = pages {
+                stats.peak_resident.fetch_max(old + n, SeqCst);
+            }
+        }
+    }
+
+    /// Counts `n` fewer `pages` for the process `thread` runs, if any.
+    pub fn sub(thread: &Thread, pages: Pages, n: usize) {
+        if let Some(proc) = &thread.userproc {
+            proc.vmstats.pages(pages).fetch_sub(n, SeqCst);
+        }
+    }
+
+    fn pages(&self, pages: Pages) -> &AtomicUsize {
+        match pages {
+            Pages::Resident => &self.resident,
+            Pages::Swapped => &self.swapped,
+            Pages::FileMapped => &self.file_mapped,
+        }
+    }
+
+    pub fn faults(&self) -> usize {
+        self.faults.load(SeqCst)
+    }
+
+    /// Faults that read from disk, see [`Event::MajorFault`].
+    pub fn major_faults(&self) -> usize {
+        self.major_faults.load(SeqCst)
+    }
+
+    /// Faults handled without reading from disk.
+    pub fn minor_faults(&self) -> usize {
+        self.faults().saturating_sub(self.major_faults())
+    }
+
+    pub fn evictions(&self) -> usize {
+        self.evictions.load(SeqCst)
+    }
+
+    pub fn swap_ins(&self) -> usize {
+        self.swap_ins.load(SeqCst)
+    }
+
+    pub fn resident(&self) -> usize {
+        self.resident.load(SeqCst)
+    }
+
+    pub fn swapped(&self) -> usize {
+        self.swapped.load(SeqCst)
+    }
+
+    pub fn file_mapped(&self) -> usize {
+        self.file_mapped.load(SeqCst)
+    }
+
+    pub fn peak_resident(&self) -> usize {
+        self.peak_resident.load(SeqCst)
+    }
+
+    /// Pages the process holds, in memory or in swap.
+    pub fn footprint(&self) -> usize {
+        self.resident() + self.swapped()
+    }
+}
@@ -0,0 +1,178 @@
+//! Compressed swap cache, in front of the swap file.
+//!
+//! Pages written to swap are compressed with [`lz`] and kept in a pool of
+//! kernel memory, keyed by their swap slot, so that swapping them in again
+//! takes no disk I/O. Pages go to the disk only when the pool is full, or when
+//! they do not compress well enough to be worth keeping. A slot leaves the
+//! cache when it is freed, see [`SwapTable::dealloc`](super::swaptable::SwapTable::dealloc).
+//!
+//! The pool holds `zswap_pages=` pages at most, 0 disables the cache.
+
+pub mod lz;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+use crate::mem::PG_SIZE;
+use crate::sync::{Lazy, Mutex, Primitive};
+use crate::Result;
+
+/// Size of the pool, in pages, unless the `zswap_pages=` kernel parameter says otherwise.
+const DEFAULT_POOL_PAGES: usize = 64;
+
+/// Largest compressed page kept. Larger kernel heap blocks take a whole page.
+const MAX_COMPRESSED: usize = PG_SIZE / 4;
+
+static POOL_PAGES: AtomicUsize = AtomicUsize::new(DEFAULT_POOL_PAGES);
+
+/// Set the size of the pool, for the `zswap_pages=` kernel parameter
+pub fn set_pool_pages(value: &str) -> Result<()> {
+    POOL_PAGES.store(crate::cmdline::parse_number(value)?, SeqCst);
+    Ok(())
+}
+
+/// Counts of what the cache did, see [`ZSwap::stats`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// Pages compressed into the pool.
+    pub stores: usize,
+    /// Pages written to disk, as the pool was full.
+    pub spills: usize,
+    /// Pages written to disk, as they did not compress well enough.
+    pub rejects: usize,
+    /// Pages swapped in from the pool.
+    pub hits: usize,
+    /// Pages swapped in from disk.
+    pub misses: usize,
+    /// Compressed size of all pages stored, in bytes.
+    pub compressed: usize,
+}
+
+struct ZSwapInner {
+    /// Compressed pages, by swap slot.
+    pages: BTreeMap<usize, Box<[u8]>>,
+    /// Bytes of the pool in use.
+    used: usize,
+    stats: Stats,
+}
+
+pub struct ZSwap(Mutex<ZSwapInner, Primitive>);
+
+impl ZSwap {
+    pub fn get() -> &'static Self {
+        static ZSWAP: Lazy<ZSwap> = Lazy::new(|| {
+            ZSwap(Mutex::new(ZSwapInner {
+                pages: BTreeMap::new(),
+                used: 0,
+                stats: Stats::default(),
+            }))
+        });
+        &ZSWAP
+    }
+
+    /// Keeps `page`, bound for swap slot `offset`, in the pool.
+    ///
+    /// ## Return
+    /// Whether it is kept. If not, it must be written to the slot on disk.
+    pub fn store(offset: usize, page: &[u8; PG_SIZE]) -> bool {
+        let capacity = POOL_PAGES.load(SeqCst) * PG_SIZE;
+        if capacity == 0 {
+            return false;
+        }
+
+        let mut buf = [0; MAX_COMPRESSED];
+        let len = lz::compress(page, &mut buf);
+        let mut zswap = Self::get().0.lock();
+        let len = match len {
+            Some(len) => len,
+            None => {
+                zswap.stats.rejects += 1;
+                return false;
+            }
+        };
+        if zswap.used + footprint(len) > capacity {
+            zswap.stats.spills += 1;
+            return false;
+        }
+
+        zswap.used += footprint(len);
+        zswap.stats.stores += 1;
+        zswap.stats.compressed += len;
+        let old = zswap.pages.insert(offset, Box::from(&buf[..len]));
+        assert!(old.is_none(), "swap slot {:#x} is cached twice", offset);
+        true
+    }
+
+    /// Reads the page in swap slot `offset` from the pool into `page`.
+    ///
+    /// ## Return
+    /// Whether it is in the pool. If not, it must be read from the slot on disk.
+    pub fn load(offset: usize, page: &mut [u8; PG_SIZE]) -> bool {
+        let mut zswap = Self::get().0.lock();
+        let hit = match zswap.pages.get(&offset) {
+            Some(compressed) => {
+                let len = lz::decompress(compressed, page);
+                assert_eq!(
+                    len,
+                    Some(PG_SIZE),
+                    "corrupt page in swap slot {:#x}",
+                    offset
+                );
+                true
+            }
+            None => false,
+        };
+        match hit {
+            true => zswap.stats.hits += 1,
+            false => zswap.stats.misses += 1,
+        }
+        hit
+    }
+
+    /// Whether the page in swap slot `offset` is in the pool.
+    pub fn contains(offset: usize) -> bool {
+        Self::get().0.lock().pages.contains_key(&offset)
+    }
+
+    /// Drops the page of swap slot `offset`, which is free now, from the pool.
+    pub fn invalidate(offset: usize) {
+        let mut zswap = Self::get().0.lock();
+        if let Some(compressed) = zswap.pages.remove(&offset) {
+            zswap.used -= footprint(compressed.len());
+        }
+    }
+
+    pub fn stats() -> Stats {
+        Self::get().0.lock().stats
+    }
+
+    /// Prints the statistics, if any page was swapped out.
+    pub fn report() {
+        let stats = Self::stats();
+        let swapped = stats.stores + stats.spills + stats.rejects;
+        if swapped == 0 {
+            return;
+        }
+        // In hundredths.
+        let ratio = (stats.stores * PG_SIZE * 100)
+            .checked_div(stats.compressed)
+            .unwrap_or(0);
+        kprintln!(
+            "zswap: {} of {} pages compressed ({}.{:02}x), {} spilled, {} rejected; {} hits, {} misses",
+            stats.stores,
+            swapped,
+            ratio / 100,
+            ratio % 100,
+            stats.spills,
+            stats.rejects,
+            stats.hits,
+            stats.misses,
+        );
+    }
+}
+
+/// Bytes of the kernel heap a compressed page of `len` bytes takes.
+fn footprint(len: usize) -> usize {
+    len.next_power_of_two().max(8)
+}
@@ -0,0 +1,123 @@
+//! A small LZ77 codec for pages.
+//!
+//! The output is a sequence of tokens, each starting with a tag byte:
+//!
+//! - `0x00..=0x7f`: a run of `tag + 1` literal bytes, which follow the tag.
+//! - `0x80..=0xff`: a match of `(tag & 0x7f) + MIN_MATCH` bytes, copied from
+//!   `distance` bytes back in the output. `distance - 1` follows the tag, as
+//!   a 16-bit little-endian number. The match may overlap the bytes it makes.
+//!
+//! Matches are found through a hash table of the last position each 4-byte
+//! sequence was seen at, like LZ4 does.
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const HASH_BITS: u32 = 10;
+
+/// Compresses `src` into `dst`.
+///
+/// ## Return
+/// - `Some(len)`: The compressed size.
+/// - `None`: The output does not fit in `dst`.
+pub fn compress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    assert!(src.len() <= u16::MAX as usize);
+
+    // Positions plus one, 0 being none.
+    let mut table = [0u16; 1 << HASH_BITS];
+    let mut out = Output { buf: dst, len: 0 };
+    let mut literals = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= src.len() {
+        let hash = hash(&src[pos..pos + MIN_MATCH]);
+        let candidate = table[hash] as usize;
+        table[hash] = (pos + 1) as u16;
+
+        if candidate > 0
+            && src[candidate - 1..candidate - 1 + MIN_MATCH] == src[pos..pos + MIN_MATCH]
+        {
+            let from = candidate - 1;
+            let mut len = MIN_MATCH;
+            while pos + len < src.len() && len < MAX_MATCH && src[from + len] == src[pos + len] {
+                len += 1;
+            }
+            out.literals(&src[literals..pos])?;
+            out.push(0x80 | (len - MIN_MATCH) as u8)?;
+            out.extend(&((pos - from - 1) as u16).to_le_bytes())?;
+            pos += len;
+            literals = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    out.literals(&src[literals..])?;
+    Some(out.len)
+}
+
+/// Decompresses `src`, the output of [`compress`], into `dst`.
+///
+/// ## Return
+/// The decompressed size, `None` if `src` is corrupt or does not fit in `dst`.
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    let mut pos = 0;
+    let mut len = 0;
+    while pos < src.len() {
+        let tag = src[pos] as usize;
+        pos += 1;
+        if tag < 0x80 {
+            let run = src.get(pos..pos + tag + 1)?;
+            dst.get_mut(len..len + run.len())?.copy_from_slice(run);
+            pos += run.len();
+            len += run.len();
+        } else {
+            let distance = u16::from_le_bytes([*src.get(pos)?, *src.get(pos + 1)?]) as usize + 1;
+            pos += 2;
+            let from = len.checked_sub(distance)?;
+            let count = (tag & 0x7f) + MIN_MATCH;
+            if len + count > dst.len() {
+                return None;
+            }
+            // Byte by byte, as the match may overlap.
+            for i in 0..count {
+                dst[len + i] = dst[from + i];
+            }
+            len += count;
+        }
+    }
+    Some(len)
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Bytes written so far to a buffer.
+struct Output<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Output<'_> {
+    fn push(&mut self, byte: u8) -> Option<()> {
+        *self.buf.get_mut(self.len)? = byte;
+        self.len += 1;
+        Some(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> Option<()> {
+        self.buf
+            .get_mut(self.len..self.len + bytes.len())?
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        Some(())
+    }
+
+    fn literals(&mut self, bytes: &[u8]) -> Option<()> {
+        for run in bytes.chunks(MAX_LITERALS) {
+            self.push((run.len() - 1) as u8)?;
+            self.extend(run)?;
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,237 @@
+//! Platform configuration discovered from the flattened device tree (FDT).
+//!
+//! The bootloader passes the physical address of a device tree blob in `a1`.
+//! [`init`] walks it once during boot and records everything the kernel needs
+//! to know about the machine: RAM and reserved regions, the PLIC, all virtio
+//! MMIO transports and the timebase frequency. Other modules then query
+//! [`get`] instead of relying on hard-coded addresses.
+//!
+//! The kernel heap is not available while the FDT is parsed, so all tables
+//! here are fixed-capacity.
+
+use fdt::Fdt;
+
+use crate::mem::{round_down, round_up, PG_SIZE, VM_OFFSET};
+use crate::sync::OnceCell;
+
+/// Maximum number of entries recorded for each kind of region.
+const MAX_REGIONS: usize = 16;
+
+/// A range of physical memory, `[base, base + size)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+}
+
+impl Region {
+    pub const fn new(base: usize, size: usize) -> Self {
+        Self { base, size }
+    }
+
+    pub fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    /// Shrinks the region to whole pages.
+    pub fn page_inner(&self) -> Self {
+        let base = round_up(self.base, PG_SIZE);
+        let end = round_down(self.end(), PG_SIZE);
+        Self::new(base, end.saturating_sub(base))
+    }
+
+    /// Grows the region to whole pages.
+    pub fn page_outer(&self) -> Self {
+        let base = round_down(self.base, PG_SIZE);
+        Self::new(base, round_up(self.end(), PG_SIZE) - base)
+    }
+
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.base < other.end() && other.base < self.end()
+    }
+}
+
+/// A fixed-capacity list of regions.
+#[derive(Debug, Clone, Copy)]
+pub struct Regions {
+    regions: [Region; MAX_REGIONS],
+    len: usize,
+}
+
+impl Regions {
+    pub const fn new() -> Self {
+        Self {
+            regions: [Region::new(0, 0); MAX_REGIONS],
+            len: 0,
+        }
+    }
+
+    /// Appends a region. Empty regions are ignored.
+    pub fn push(&mut self, region: Region) {
+        if region.size == 0 {
+            return;
+        }
+        assert!(self.len < MAX_REGIONS, "too many regions in device tree");
+        self.regions[self.len] = region;
+        self.len += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions[..self.len].iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Calls `f` on every piece of `region` that none of `self` covers, in
+    /// ascending order.
+    pub fn subtract_from<F: FnMut(Region)>(&self, region: Region, mut f: F) {
+        let mut cursor = region.base;
+        while cursor < region.end() {
+            let rest = Region::new(cursor, region.end() - cursor);
+            match self
+                .iter()
+                .filter(|r| r.overlaps(&rest))
+                .min_by_key(|r| r.base)
+            {
+                Some(hole) => {
+                    if hole.base > cursor {
+                        f(Region::new(cursor, hole.base - cursor));
+                    }
+                    cursor = hole.end();
+                }
+                None => {
+                    f(rest);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A virtio MMIO transport, which may or may not have a device behind it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtioMmio {
+    pub reg: Region,
+    /// Interrupt source ID at the PLIC.
+    pub irq: usize,
+}
+
+#[derive(Debug)]
+pub struct Platform {
+    /// Usable RAM.
+    pub memory: Regions,
+    /// RAM that must not be handed to the allocators.
+    pub reserved: Regions,
+    /// The device tree blob itself. Free after boot has copied what it needs.
+    pub dtb: Region,
+    pub plic: Region,
+    pub virtio: [VirtioMmio; MAX_REGIONS],
+    pub nvirtio: usize,
+    /// Frequency of the `time` CSR, in Hz.
+    pub timebase_frequency: usize,
+}
+
+static PLATFORM: OnceCell<Platform> = OnceCell::new();
+
+/// Parses the device tree at physical address `dtb`.
+///
+/// The blob is accessed through the kernel linear map, so the returned tree
+/// stays valid after the kernel page table is activated, until the DTB
+/// area is reclaimed.
+pub fn devtree(dtb: usize) -> Fdt<'static> {
+    unsafe { Fdt::from_ptr((dtb + VM_OFFSET) as *const u8).expect("Invalid device tree") }
+}
+
+/// Collects the platform configuration from the device tree at physical address `dtb`.
+pub fn init(dtb: usize) {
+    PLATFORM.init(|| {
+        let devtree = devtree(dtb);
+
+        let mut memory = Regions::new();
+        devtree
+            .all_nodes()
+            .filter(|node| node.name == "memory" || node.name.starts_with("memory@"))
+            .filter_map(|node| node.reg())
+            .flatten()
+            .for_each(|r| {
+                memory.push(Region::new(
+                    r.starting_address as usize,
+                    r.size.expect("Unknown physical memory length"),
+                ))
+            });
+        assert!(!memory.is_empty(), "No memory info.");
+
+        // Firmware reservations come from both the memory reservation block
+        // and the `/reserved-memory` node.
+        let mut reserved = Regions::new();
+        devtree
+            .memory_reservations()
+            .for_each(|r| reserved.push(Region::new(r.address() as usize, r.size())));
+        if let Some(node) = devtree.find_node("/reserved-memory") {
+            node.children()
+                .filter_map(|child| child.reg())
+                .flatten()
+                .for_each(|r| {
+                    reserved.push(Region::new(
+                        r.starting_address as usize,
+                        r.size.unwrap_or(0),
+                    ))
+                });
+        }
+
+        let plic = devtree
+            .find_compatible(&["riscv,plic0", "sifive,plic-1.0.0"])
+            .and_then(|node| node.reg())
+            .and_then(|mut reg| reg.next())
+            .map(|r| Region::new(r.starting_address as usize, r.size.unwrap_or(0)))
+            .expect("No PLIC in device tree");
+
+        let mut virtio = [VirtioMmio::default(); MAX_REGIONS];
+        let mut nvirtio = 0;
+        devtree
+            .all_nodes()
+            .filter(|node| {
+                node.compatible()
+                    .map_or(false, |c| c.all().any(|s| s == "virtio,mmio"))
+            })
+            .for_each(|node| {
+                let reg = node.reg().and_then(|mut reg| reg.next());
+                let irq = node.interrupts().and_then(|mut irqs| irqs.next());
+                if let (Some(reg), Some(irq), true) = (reg, irq, nvirtio < MAX_REGIONS) {
+                    virtio[nvirtio] = VirtioMmio {
+                        reg: Region::new(reg.starting_address as usize, reg.size.unwrap_or(0)),
+                        irq,
+                    };
+                    nvirtio += 1;
+                }
+            });
+
+        let timebase_frequency = devtree
+            .cpus()
+            .next()
+            .map(|cpu| cpu.timebase_frequency())
+            .expect("No timebase frequency in device tree");
+
+        Platform {
+            memory,
+            reserved,
+            dtb: Region::new(dtb, devtree.total_size()),
+            plic,
+            virtio,
+            nvirtio,
+            timebase_frequency,
+        }
+    });
+}
+
+pub fn get() -> &'static Platform {
+    PLATFORM.get()
+}
+
+impl Platform {
+    pub fn virtio(&self) -> &[VirtioMmio] {
+        &self.virtio[..self.nvirtio]
+    }
+}
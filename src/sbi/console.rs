@@ -1,6 +1,8 @@
 use core::fmt::{Result, Write};
+use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 
 use crate::sbi::{console_putchar, interrupt};
+use crate::OsError;
 
 pub struct Stdout;
 
@@ -63,6 +65,37 @@ impl Drop for StdoutLock<'_> {
     }
 }
 
+/// Console log levels, from the least to the most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+}
+
+static LOGLEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Info as usize);
+
+/// Get the current log level
+pub fn loglevel() -> LogLevel {
+    match LOGLEVEL.load(SeqCst) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warn,
+        _ => LogLevel::Info,
+    }
+}
+
+/// Set the log level by its name, for the `loglevel=` kernel parameter
+pub fn set_loglevel(name: &str) -> crate::Result<()> {
+    let level = match name {
+        "error" => LogLevel::Error,
+        "warn" => LogLevel::Warn,
+        "info" => LogLevel::Info,
+        _ => return Err(OsError::InvalidArgument),
+    };
+    LOGLEVEL.store(level as usize, SeqCst);
+    Ok(())
+}
+
 #[macro_export]
 macro_rules! kprint {
     ($($arg:tt)*) => {{
@@ -86,3 +119,13 @@ macro_rules! kprintln {
         kprint!("\n");
     }};
 }
+
+/// Print a line if the log level is at least `warn`
+#[macro_export]
+macro_rules! kwarn {
+    ($($arg:tt)*) => {{
+        if $crate::sbi::console::loglevel() >= $crate::sbi::console::LogLevel::Warn {
+            kprintln!($($arg)*);
+        }
+    }};
+}
@@ -1,11 +1,46 @@
 //! RISC-V Timer Interface
 
-use core::sync::atomic::{AtomicI64, Ordering::SeqCst};
+use core::sync::atomic::{AtomicI64, AtomicUsize, Ordering::SeqCst};
 
 use crate::sbi::set_timer;
+use crate::{OsError, Result};
 
+/// Default frequency of timer interrupts
 pub const TICKS_PER_SEC: usize = 10;
-pub const CLOCK_PRE_SEC: usize = 12500000;
+
+static TICK_FREQ: AtomicUsize = AtomicUsize::new(TICKS_PER_SEC);
+
+/// Frequency of the `time` CSR. Replaced by the device tree's timebase frequency at boot.
+static CLOCK_PER_SEC: AtomicUsize = AtomicUsize::new(12500000);
+
+/// Get the number of clock cycles per second
+#[inline]
+pub fn clock_per_sec() -> usize {
+    CLOCK_PER_SEC.load(SeqCst)
+}
+
+/// Set the frequency of the clock, in Hz
+pub fn set_clock_per_sec(freq: usize) {
+    assert!(freq > 0, "timebase frequency must not be zero");
+    CLOCK_PER_SEC.store(freq, SeqCst);
+}
+
+/// Get the number of timer interrupts per second
+#[inline]
+pub fn ticks_per_sec() -> usize {
+    TICK_FREQ.load(SeqCst)
+}
+
+/// Set the frequency of timer interrupts, for the `ticks_per_sec=` kernel parameter.
+/// It must not exceed [`clock_per_sec`], or the timer would fire continuously.
+pub fn set_ticks_per_sec(value: &str) -> Result<()> {
+    let ticks = crate::cmdline::parse_count(value)?;
+    if ticks > clock_per_sec() {
+        return Err(OsError::InvalidArgument);
+    }
+    TICK_FREQ.store(ticks, SeqCst);
+    Ok(())
+}
 
 /// Get the clock's raw reading
 pub fn clock() -> usize {
@@ -15,19 +50,19 @@ pub fn clock() -> usize {
 /// Get the current clock reading in milliseconds
 #[inline]
 pub fn time_ms() -> usize {
-    clock() * 1_000 / CLOCK_PRE_SEC
+    clock() * 1_000 / clock_per_sec()
 }
 
 /// Get the current clock reading in microseconds
 #[inline]
 pub fn time_us() -> usize {
-    clock() * 1_000_000 / CLOCK_PRE_SEC
+    clock() * 1_000_000 / clock_per_sec()
 }
 
 /// Set the next moment when timer interrupt should happen
 #[inline]
 pub fn next() {
-    set_timer(clock() + CLOCK_PRE_SEC / TICKS_PER_SEC);
+    set_timer(clock() + clock_per_sec() / ticks_per_sec());
 }
 
 static TICKS: AtomicI64 = AtomicI64::new(0);
@@ -47,6 +47,7 @@ use alloc::sync::Arc;
 use core::cell::RefCell;
 
 use crate::sync::{Lock, MutexGuard, Semaphore};
+use crate::Result;
 
 pub struct Condvar(RefCell<VecDeque<Arc<Semaphore>>>);
 
@@ -67,6 +68,27 @@ impl Condvar {
         guard.acquire();
     }
 
+    /// Like [`wait`](Self::wait), but gives up if the current thread is
+    /// interrupted, see [`Semaphore::down_interruptible`].
+    pub fn wait_interruptible<T, L: Lock>(&self, guard: &mut MutexGuard<'_, T, L>) -> Result<()> {
+        let sema = Arc::new(Semaphore::new(0));
+        self.0.borrow_mut().push_front(sema.clone());
+
+        guard.release();
+        let result = sema.down_interruptible();
+        guard.acquire();
+
+        if result.is_err() {
+            let waiters = self.0.borrow().len();
+            self.0.borrow_mut().retain(|s| !Arc::ptr_eq(s, &sema));
+            // Pass on a notification that came too late.
+            if self.0.borrow().len() == waiters {
+                self.notify_one();
+            }
+        }
+        result
+    }
+
     /// For priority scheduling, pop the waiter with the maximum priority
     #[cfg(feature = "thread-scheduler-priority")]
     fn pop_max_priority_waiter(&self) -> Option<Arc<Semaphore>> {
@@ -4,6 +4,7 @@ use core::cell::{Cell, RefCell};
 
 use crate::sbi;
 use crate::thread::{self, Thread};
+use crate::{OsError, Result};
 
 /// Atomic counting semaphore
 ///
@@ -85,6 +86,51 @@ impl Semaphore {
         sbi::interrupt::set(old);
     }
 
+    /// P operation, unless the current thread is interrupted before or while
+    /// it waits, see [`thread::interrupt`].
+    ///
+    /// ## Return
+    /// - `Ok(())`: the semaphore was taken.
+    /// - `Err(Interrupted)`: the current thread is interrupted.
+    pub fn down_interruptible(&self) -> Result<()> {
+        let old = sbi::interrupt::set(false);
+        let current = thread::current();
+
+        let result = loop {
+            if self.value() > 0 {
+                self.value.set(self.value() - 1);
+                break Ok(());
+            }
+            if current.is_interrupted() {
+                break Err(OsError::Interrupted);
+            }
+            self.waiters.borrow_mut().push_back(current.clone());
+            current.set_channel(Some(self));
+            thread::block();
+            current.set_channel(None);
+        };
+
+        sbi::interrupt::set(old);
+        result
+    }
+
+    /// Wakes up `thread`, if it waits in [`down_interruptible`](Self::down_interruptible).
+    pub fn interrupt(&self, thread: &Arc<Thread>) {
+        let old = sbi::interrupt::set(false);
+
+        let pos = self
+            .waiters
+            .borrow()
+            .iter()
+            .position(|waiter| Arc::ptr_eq(waiter, thread));
+        if let Some(pos) = pos {
+            self.waiters.borrow_mut().remove(pos);
+            thread::wake_up(thread.clone());
+        }
+
+        sbi::interrupt::set(old);
+    }
+
     /// V operation
     pub fn up(&self) {
         let old = sbi::interrupt::set(false);
@@ -56,6 +56,7 @@ impl Lock for Sleep {
         // }
 
         self.inner.down();
+        thread::current().acquired_lock();
         self.holder.borrow_mut().replace(thread::current());
 
         #[cfg(feature = "thread-scheduler-priority")]
@@ -79,6 +80,7 @@ impl Lock for Sleep {
             self.holder.borrow().as_ref().unwrap(),
             &current
         ));
+        current.released_lock();
 
         #[cfg(feature = "thread-scheduler-priority")]
         {
@@ -76,11 +76,27 @@ pub fn wake_up(thread: Arc<Thread>) {
     Manager::get().scheduler.lock().register(thread.clone());
 
     #[cfg(feature = "thread-scheduler-priority")]
-    if thread.effective_priority() > get_priority() {
+    if scheduler::policy() == scheduler::Policy::Priority
+        && thread.effective_priority() > get_priority()
+    {
         schedule();
     }
 }
 
+/// Makes `thread` give up its interruptible wait, if it is blocked in one, and
+/// fail those it starts later, see [`Semaphore::down_interruptible`].
+///
+/// [`Semaphore::down_interruptible`]: crate::sync::Semaphore::down_interruptible
+pub fn interrupt(thread: &Arc<Thread>) {
+    let old = interrupt::set(false);
+    thread.set_interrupted(true);
+    // The semaphore outlives the wait, which cannot end while interrupts are off.
+    if let Some(sema) = unsafe { thread.channel().as_ref() } {
+        sema.interrupt(thread);
+    }
+    interrupt::set(old);
+}
+
 /// (Lab1) Sets the current thread's priority to a given value
 pub fn set_priority(_priority: u32) {
     let old = interrupt::set(false);
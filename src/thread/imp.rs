@@ -8,11 +8,15 @@ use core::arch::global_asm;
 use alloc::vec::Vec;
 
 use core::fmt::{self, Debug};
-use core::sync::atomic::{AtomicIsize, AtomicU32, Ordering::SeqCst};
+use core::ptr;
+use core::sync::atomic::{
+    AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize, Ordering::SeqCst,
+};
 
 use crate::mem::suppagetable::SupPageTable;
 use crate::mem::{kalloc, kfree, PageTable, PG_SIZE};
 use crate::sbi::interrupt;
+use crate::sync::Semaphore;
 use crate::thread::Manager;
 
 #[cfg(feature = "thread-scheduler-priority")]
@@ -24,6 +28,7 @@ use crate::thread::current;
 use crate::userproc::fileop::fdtable::FDTable;
 use crate::userproc::fileop::mmaptable::MmapTable;
 use crate::userproc::UserProc;
+use crate::{OsError, Result};
 
 pub const PRI_DEFAULT: u32 = 31;
 pub const PRI_MAX: u32 = 63;
@@ -45,6 +50,12 @@ pub struct Thread {
     status: Mutex<Status>,
     context: Mutex<Context>,
     pub priority: AtomicU32,
+    /// Set by [`interrupt`](crate::thread::interrupt).
+    interrupted: AtomicBool,
+    /// The semaphore it is blocked on in an interruptible wait, or null.
+    channel: AtomicPtr<Semaphore>,
+    /// Number of sleep locks it holds.
+    locks: AtomicUsize,
 
     #[cfg(feature = "thread-scheduler-priority")]
     pub effective_priority: AtomicU32,
@@ -55,11 +66,13 @@ pub struct Thread {
     #[cfg(feature = "thread-scheduler-priority")]
     pub donors: Mutex<Vec<Arc<Thread>>>,
 
-    pub userproc: Option<UserProc>,
-    pub pagetable: Option<Mutex<PageTable>>,
-    pub fdtable: Option<FDTable>,
-    pub mmaptable: Option<MmapTable>,
-    pub suppt: Option<SupPageTable>,
+    /// The user process this thread runs, if any. Threads of the same
+    /// process share it, its address space and its open files.
+    pub userproc: Option<Arc<UserProc>>,
+    pub pagetable: Option<Arc<Mutex<PageTable>>>,
+    pub fdtable: Option<Arc<FDTable>>,
+    pub mmaptable: Option<Arc<MmapTable>>,
+    pub suppt: Option<Arc<SupPageTable>>,
 }
 
 impl Thread {
@@ -68,11 +81,11 @@ impl Thread {
         stack: usize,
         priority: u32,
         entry: usize,
-        userproc: Option<UserProc>,
-        pagetable: Option<PageTable>,
-        fdtable: Option<FDTable>,
-        mmaptable: Option<MmapTable>,
-        suppt: Option<SupPageTable>,
+        userproc: Option<Arc<UserProc>>,
+        pagetable: Option<Arc<Mutex<PageTable>>>,
+        fdtable: Option<Arc<FDTable>>,
+        mmaptable: Option<Arc<MmapTable>>,
+        suppt: Option<Arc<SupPageTable>>,
     ) -> Self {
         /// The next thread's id
         static TID: AtomicIsize = AtomicIsize::new(0);
@@ -84,6 +97,9 @@ impl Thread {
             status: Mutex::new(Status::Ready),
             context: Mutex::new(Context::new(stack, entry)),
             priority: AtomicU32::new(priority),
+            interrupted: AtomicBool::new(false),
+            channel: AtomicPtr::new(ptr::null_mut()),
+            locks: AtomicUsize::new(0),
 
             #[cfg(feature = "thread-scheduler-priority")]
             effective_priority: AtomicU32::new(priority),
@@ -95,7 +111,7 @@ impl Thread {
             donors: Mutex::new(Vec::new()),
 
             userproc,
-            pagetable: pagetable.map(Mutex::new),
+            pagetable,
             fdtable,
             mmaptable,
             suppt,
@@ -130,6 +146,39 @@ impl Thread {
         self.priority.load(SeqCst)
     }
 
+    /// Whether its interruptible waits fail, see [`interrupt`](crate::thread::interrupt).
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(SeqCst)
+    }
+
+    pub fn set_interrupted(&self, interrupted: bool) {
+        self.interrupted.store(interrupted, SeqCst);
+    }
+
+    /// The semaphore it is blocked on in an interruptible wait.
+    pub fn channel(&self) -> *const Semaphore {
+        self.channel.load(SeqCst)
+    }
+
+    pub fn set_channel(&self, sema: Option<&Semaphore>) {
+        let sema = sema.map_or(ptr::null(), |sema| sema as *const _);
+        self.channel.store(sema as *mut _, SeqCst);
+    }
+
+    /// Whether it holds any sleep lock, which making room for memory may
+    /// need as well.
+    pub fn holds_locks(&self) -> bool {
+        self.locks.load(SeqCst) > 0
+    }
+
+    pub(crate) fn acquired_lock(&self) {
+        self.locks.fetch_add(1, SeqCst);
+    }
+
+    pub(crate) fn released_lock(&self) {
+        self.locks.fetch_sub(1, SeqCst);
+    }
+
     #[cfg(feature = "thread-scheduler-priority")]
     pub fn effective_priority(&self) -> u32 {
         return self.effective_priority.load(SeqCst);
@@ -177,7 +226,12 @@ impl Drop for Thread {
         );
 
         kfree(self.stack as *mut _, STACK_SIZE, STACK_ALIGN);
-        if let Some(pt) = &self.pagetable {
+        // The last thread of a process frees its address space.
+        if let Some(pt) = self
+            .pagetable
+            .as_ref()
+            .filter(|pt| Arc::strong_count(pt) == 1)
+        {
             unsafe { pt.lock().destroy() };
         }
     }
@@ -188,11 +242,11 @@ pub struct Builder {
     priority: u32,
     name: &'static str,
     function: usize,
-    userproc: Option<UserProc>,
-    pagetable: Option<PageTable>,
-    fdtable: Option<FDTable>,
-    mmaptable: Option<MmapTable>,
-    suppt: Option<SupPageTable>,
+    userproc: Option<Arc<UserProc>>,
+    pagetable: Option<Arc<Mutex<PageTable>>>,
+    fdtable: Option<Arc<FDTable>>,
+    mmaptable: Option<Arc<MmapTable>>,
+    suppt: Option<Arc<SupPageTable>>,
 }
 
 impl Builder {
@@ -226,37 +280,58 @@ impl Builder {
     }
 
     pub fn pagetable(mut self, pagetable: PageTable) -> Self {
-        self.pagetable = Some(pagetable);
+        self.pagetable = Some(Arc::new(Mutex::new(pagetable)));
         self
     }
 
-    pub fn userproc(mut self, userproc: UserProc) -> Self {
+    pub fn userproc(mut self, userproc: Arc<UserProc>) -> Self {
         self.userproc = Some(userproc);
         self
     }
 
     pub fn fdtable(mut self, fdtable: FDTable) -> Self {
-        self.fdtable = Some(fdtable);
+        self.fdtable = Some(Arc::new(fdtable));
         self
     }
 
     pub fn mmaptable(mut self, mmaptable: MmapTable) -> Self {
-        self.mmaptable = Some(mmaptable);
+        self.mmaptable = Some(Arc::new(mmaptable));
         self
     }
 
     pub fn sup_pagetable(mut self, suppt: SupPageTable) -> Self {
-        self.suppt = Some(suppt);
+        self.suppt = Some(Arc::new(suppt));
+        self
+    }
+
+    /// Runs the thread in the same user process as `thread`, sharing its
+    /// address space, file descriptors and mappings.
+    pub fn process_of(mut self, thread: &Thread) -> Self {
+        self.userproc = thread.userproc.clone();
+        self.pagetable = thread.pagetable.clone();
+        self.fdtable = thread.fdtable.clone();
+        self.mmaptable = thread.mmaptable.clone();
+        self.suppt = thread.suppt.clone();
         self
     }
 
-    pub fn build(self) -> Arc<Thread> {
+    /// ## Return
+    /// - `Err(OutOfMemory)`: there is no memory for the kernel stack. The
+    ///   function is dropped, and so is the page table unless shared.
+    pub fn build(self) -> Result<Arc<Thread>> {
         let stack = kalloc(STACK_SIZE, STACK_ALIGN) as usize;
+        if stack == 0 {
+            drop(unsafe { Box::from_raw(self.function as *mut Box<dyn FnOnce()>) });
+            if let Some(pt) = self.pagetable.filter(|pt| Arc::strong_count(pt) == 1) {
+                unsafe { pt.lock().destroy() };
+            }
+            return Err(OsError::OutOfMemory);
+        }
 
         // Put magic number at the bottom of the stack.
         unsafe { (stack as *mut usize).write(MAGIC) };
 
-        Arc::new(Thread::new(
+        Ok(Arc::new(Thread::new(
             self.name,
             stack,
             self.priority,
@@ -266,7 +341,7 @@ impl Builder {
             self.fdtable,
             self.mmaptable,
             self.suppt,
-        ))
+        )))
     }
 
     /// Spawns a kernel thread and registers it to the [`Manager`].
@@ -275,7 +350,14 @@ impl Builder {
     ///
     /// Note that this function CANNOT be called during [`Manager`]'s initialization.
     pub fn spawn(self) -> Arc<Thread> {
-        let new_thread = self.build();
+        self.try_spawn()
+            .expect("kernel memory exhausted, user pool included")
+    }
+
+    /// Spawns a thread like [`spawn`](Self::spawn), but fails instead of
+    /// panicking if memory is exhausted, see [`build`](Self::build).
+    pub fn try_spawn(self) -> Result<Arc<Thread>> {
+        let new_thread = self.build()?;
 
         #[cfg(feature = "debug")]
         kprintln!("[THREAD] create {:?}", new_thread);
@@ -288,7 +370,7 @@ impl Builder {
         }
 
         // Off you go
-        new_thread
+        Ok(new_thread)
     }
 }
 
@@ -9,6 +9,8 @@ use crate::bootstack;
 use crate::mem::KernelPgTable;
 use crate::sbi::interrupt;
 use crate::sync::Lazy;
+#[cfg(feature = "thread-scheduler-priority")]
+use crate::thread::scheduler::{policy, Policy};
 use crate::thread::{
     schedule, switch, Builder, Mutex, Schedule, Scheduler, Status, Thread, MAGIC, PRI_DEFAULT,
     PRI_MIN,
@@ -54,14 +56,16 @@ impl Manager {
             })
             .name("Idle1")
             .priority(PRI_MIN)
-            .build();
+            .build()
+            .unwrap();
 
             let idle2 = Builder::new(|| loop {
                 schedule()
             })
             .name("Idle2")
             .priority(PRI_MIN)
-            .build();
+            .build()
+            .unwrap();
 
             manager.register(idle1);
             manager.register(idle2);
@@ -108,7 +112,8 @@ impl Manager {
 
         if let Some(next) = next {
             #[cfg(feature = "thread-scheduler-priority")]
-            if next.effective_priority() < self.current.lock().effective_priority()
+            if policy() == Policy::Priority
+                && next.effective_priority() < self.current.lock().effective_priority()
                 && self.current.lock().status() == Status::Running
             {
                 self.scheduler.lock().register(next.clone());
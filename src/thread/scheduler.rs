@@ -11,14 +11,82 @@ pub mod fcfs;
 pub mod priority;
 
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering::SeqCst};
 
 use crate::thread::Thread;
+use crate::{OsError, Result};
 
+use self::fcfs::Fcfs;
 #[cfg(feature = "thread-scheduler-priority")]
 // (Lab1) Your task: priority scheduling
-pub type Scheduler = self::priority::PriorityScheduler;
-#[cfg(not(feature = "thread-scheduler-priority"))]
-pub type Scheduler = self::fcfs::Fcfs;
+use self::priority::PriorityScheduler;
+
+/// Scheduling policies, selected with the `sched=` kernel parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Fcfs,
+    /// Only available if the kernel is built with priority scheduling.
+    Priority,
+}
+
+static PRIORITY: AtomicBool = AtomicBool::new(cfg!(feature = "thread-scheduler-priority"));
+
+/// Get the scheduling policy in use
+pub fn policy() -> Policy {
+    match PRIORITY.load(SeqCst) {
+        true => Policy::Priority,
+        false => Policy::Fcfs,
+    }
+}
+
+/// Select the scheduling policy by its name. Takes effect only before the
+/// first thread is created.
+pub fn set_policy(name: &str) -> Result<()> {
+    match name {
+        "fcfs" => PRIORITY.store(false, SeqCst),
+        #[cfg(feature = "thread-scheduler-priority")]
+        "priority" => PRIORITY.store(true, SeqCst),
+        _ => return Err(OsError::InvalidArgument),
+    }
+    Ok(())
+}
+
+/// The scheduler for the policy selected at boot.
+pub enum Scheduler {
+    Fcfs(Fcfs),
+    #[cfg(feature = "thread-scheduler-priority")]
+    Priority(PriorityScheduler),
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        match policy() {
+            Policy::Fcfs => Self::Fcfs(Fcfs::default()),
+            #[cfg(feature = "thread-scheduler-priority")]
+            Policy::Priority => Self::Priority(PriorityScheduler::default()),
+            #[cfg(not(feature = "thread-scheduler-priority"))]
+            Policy::Priority => unreachable!(),
+        }
+    }
+}
+
+impl Schedule for Scheduler {
+    fn register(&mut self, thread: Arc<Thread>) {
+        match self {
+            Self::Fcfs(s) => s.register(thread),
+            #[cfg(feature = "thread-scheduler-priority")]
+            Self::Priority(s) => s.register(thread),
+        }
+    }
+
+    fn schedule(&mut self) -> Option<Arc<Thread>> {
+        match self {
+            Self::Fcfs(s) => s.schedule(),
+            #[cfg(feature = "thread-scheduler-priority")]
+            Self::Priority(s) => s.schedule(),
+        }
+    }
+}
 
 /// Basic functionalities of thread schedulers
 pub trait Schedule: Default {
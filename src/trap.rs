@@ -2,8 +2,9 @@
 //!
 
 pub mod demandpaging;
+mod fault;
 mod pagefault;
-mod stackgrowth;
+pub mod stackgrowth;
 mod syscall;
 
 use crate::device::{plic, virtio};
@@ -20,7 +21,7 @@ use riscv::register::*;
 /* -------------------------------------------------------------------------- */
 
 #[repr(C)]
-
+#[derive(Clone, Copy)]
 /// Trap context
 pub struct Frame {
     /// General regs[0..31].
@@ -60,21 +61,31 @@ pub extern "C" fn trap_handler(frame: &mut Frame) {
 
     match scause {
         Exception(UserEnvCall) => {
-            if let Err(_) = stackgrowth::extend_stack_to_sp(frame.x[2]) {
+            // Stacks of other threads than the main one don't grow.
+            if !userproc::uthread::on_thread_stack(frame.x[2])
+                && stackgrowth::extend_stack_to_sp(frame.x[2]).is_err()
+            {
                 kprintln!(
                     "User thread {} dying due to stack overflowing the max limit.",
                     thread::current().name(),
                 );
-                userproc::exit(-1);
+                userproc::signal::exit_killed(userproc::signal::SIGSEGV);
             }
             let id = frame.x[17];
-            let args = [frame.x[10], frame.x[11], frame.x[12]];
+            let args = [
+                frame.x[10],
+                frame.x[11],
+                frame.x[12],
+                frame.x[13],
+                frame.x[14],
+                frame.x[15],
+            ];
             #[cfg(feature = "debug")]
             kprintln!("[TRAP] User ECall, ID={}, args={:?}", id, args);
             unsafe { riscv::register::sstatus::set_sie() };
             // Increase sepc by 1 to skip ecall.
             frame.sepc += 4;
-            frame.x[10] = syscall::syscall_handler(id, args) as usize;
+            frame.x[10] = syscall::syscall_handler(id, args, frame) as usize;
         }
 
         Interrupt(SupervisorTimer) => {
@@ -91,7 +102,7 @@ pub extern "C" fn trap_handler(frame: &mut Frame) {
             // Handle the interrupt.
             match id as _ {
                 0 => panic!("There should be an interrupt"),
-                plic::VIRTIO0_ID => virtio::handle_interrupt(),
+                id if id == virtio::irq() => virtio::handle_interrupt(),
                 _ => panic!("Unknown Interrupt ID: {}", id),
             }
 
@@ -99,17 +110,14 @@ pub extern "C" fn trap_handler(frame: &mut Frame) {
             plic::write_completion(id);
         },
 
-        Exception(InstructionFault) | Exception(IllegalInstruction) => {
-            // TODO: kill user process but not panic kernel
-            panic!("Instruction failure");
-        }
-
         Exception(f @ LoadPageFault)
         | Exception(f @ StorePageFault)
         | Exception(f @ InstructionPageFault) => {
             pagefault::handler(frame, f, stval);
         }
 
+        Exception(e) => fault::handler(frame, e, stval),
+
         _ => {
             unimplemented!(
                 "Unsupported trap {:?}, stval={:#x}, sepc={:#x}",
@@ -124,6 +132,14 @@ pub extern "C" fn trap_handler(frame: &mut Frame) {
     kprintln!("[TRAP] exit");
 }
 
+/// Runs right before returning to user mode, see `trap_exit_u`. The thread
+/// goes away if its process is exiting, otherwise pending signals are acted on.
+#[no_mangle]
+pub extern "C" fn signal_handler(frame: &mut Frame) {
+    userproc::exit_if_exiting();
+    userproc::signal::deliver(frame);
+}
+
 extern "C" {
     pub fn trap_entry_u();
     pub fn trap_exit_u();
@@ -215,6 +231,11 @@ arch::global_asm! {r#"
 
     trap_exit_u:
 
+    # (0) Deliver pending signals, which may redirect the frame to a handler.
+    # Caller-saved registers are free to use, everything is restored below.
+        mv   a0, sp
+        call signal_handler
+
     # (1) Restore CSR.
     # TODO: should we restore `stvec` here?
         ld   t0, 32*8(sp)
@@ -1,12 +1,12 @@
-use alloc::vec;
-
-use crate::fs::disk::Swap;
-use crate::io::{Read, Seek};
+use crate::fs::File;
 use crate::mem::frametable::FrameTable;
+use crate::mem::pagecache::PageCache;
 use crate::mem::suppagetable::SupPageEntry;
 use crate::mem::swaptable::SwapTable;
+use crate::mem::vmstats::{Event, Pages, VmStats};
+use crate::mem::zswap::ZSwap;
 use crate::mem::{PTEFlags, PageAlign, PhysAddr, PG_SIZE};
-use crate::thread;
+use crate::thread::{self, Thread};
 use crate::OsError;
 use crate::Result;
 
@@ -14,17 +14,27 @@ pub fn demand_page(va: usize) -> Result<()> {
     let va = PageAlign::floor(va);
     let current = thread::current();
     let suppt = current.suppt.as_ref().unwrap();
+    let resident = current
+        .pagetable
+        .as_ref()
+        .unwrap()
+        .lock()
+        .get_leaf(va)
+        .is_some_and(|(pte, _)| pte.is_valid());
+    if resident {
+        // Pages of files keep their entry, the access is not allowed.
+        return Err(OsError::BadPtr);
+    }
     if let Some(spte) = suppt.query(va) {
         match spte {
             SupPageEntry::InSwap(offset) => {
-                let buf = FrameTable::alloc_frame();
+                let buf = FrameTable::alloc_frame()?;
                 let page = unsafe { (buf as *mut [u8; PG_SIZE]).as_mut().unwrap() };
 
-                {
-                    let mut swapfile = Swap::lock();
-                    swapfile.seek(crate::io::SeekFrom::Start(offset))?;
-                    swapfile.read(page)?;
+                if !ZSwap::contains(offset) {
+                    VmStats::count(&current, Event::MajorFault);
                 }
+                SwapTable::read(offset, page)?;
 
                 {
                     let mut pt = current.pagetable.as_ref().unwrap().lock();
@@ -34,10 +44,38 @@ pub fn demand_page(va: usize) -> Result<()> {
 
                 FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, false);
                 SwapTable::dealloc(offset);
+                VmStats::sub(&current, Pages::Swapped, 1);
+                VmStats::count(&current, Event::SwapIn);
+            }
+            SupPageEntry::InFileLazyLoad(file, offset, len)
+                if len == PG_SIZE && !is_writable(&current, va) =>
+            {
+                // A whole read-only page, e.g. of code, is the same in all
+                // processes, which map the cached page.
+                let frame = file_page(&current, &file, offset)?;
+
+                {
+                    let mut pt = current.pagetable.as_ref().unwrap().lock();
+                    let pte_flag = pt.get_pte(va).unwrap().flag();
+                    pt.map(PhysAddr::from_pa(frame), va, 1, pte_flag | PTEFlags::V);
+                }
+
+                FrameTable::map_cached(frame, current.clone(), va);
+                FrameTable::put(frame);
+                return Ok(());
             }
             SupPageEntry::InFileLazyLoad(file, offset, len) => {
-                let buf = FrameTable::alloc_frame();
+                let cached = file_page(&current, &file, offset)?;
+                let buf = FrameTable::alloc_frame().map_err(|err| {
+                    FrameTable::put(cached);
+                    err
+                })?;
                 let page = unsafe { (buf as *mut [u8; PG_SIZE]).as_mut().unwrap() };
+                let cached_page =
+                    unsafe { &*(PhysAddr::from_pa(cached).into_va() as *const [u8; PG_SIZE]) };
+                page[..len].copy_from_slice(&cached_page[..len]);
+                page[len..].fill(0);
+                FrameTable::put(cached);
 
                 {
                     let mut pt = current.pagetable.as_ref().unwrap().lock();
@@ -45,20 +83,30 @@ pub fn demand_page(va: usize) -> Result<()> {
                     pt.map(buf.into(), va, 1, pte_flag | PTEFlags::V);
                 }
 
-                FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, true);
+                // The entry stays, so that the page is dropped rather than
+                // swapped while it is clean.
+                FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, false);
+                return Ok(());
+            }
+            SupPageEntry::InFileMapped(file, offset) => {
+                // All shared mappings of the file map the cached page. The entry
+                // stays, for the page to be mapped again once evicted.
+                let frame = file_page(&current, &file, offset)?;
 
-                let mut filebuf = vec![0u8; len];
-                let mut file = file.clone();
-                file.seek(crate::io::SeekFrom::Start(offset))?;
-                file.read(&mut filebuf)?;
-                page[..len].copy_from_slice(&filebuf);
-                page[len..].fill(0);
+                {
+                    let mut pt = current.pagetable.as_ref().unwrap().lock();
+                    let pte_flag = pt.get_pte(va).unwrap().flag();
+                    pt.map(PhysAddr::from_pa(frame), va, 1, pte_flag | PTEFlags::V);
+                }
 
-                FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, false);
+                FrameTable::map_cached(frame, current.clone(), va);
+                FrameTable::put(frame);
+                return Ok(());
             }
-            SupPageEntry::InFileMapped(file, offset, len) => {
-                let buf = FrameTable::alloc_frame();
+            SupPageEntry::Zeroed => {
+                let buf = FrameTable::alloc_frame()?;
                 let page = unsafe { (buf as *mut [u8; PG_SIZE]).as_mut().unwrap() };
+                page.fill(0);
 
                 {
                     let mut pt = current.pagetable.as_ref().unwrap().lock();
@@ -66,15 +114,6 @@ pub fn demand_page(va: usize) -> Result<()> {
                     pt.map(buf.into(), va, 1, pte_flag | PTEFlags::V);
                 }
 
-                FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, true);
-
-                let mut filebuf = vec![0u8; len];
-                let mut file = file.clone();
-                file.seek(crate::io::SeekFrom::Start(offset))?;
-                file.read(&mut filebuf)?;
-                page[..len].copy_from_slice(&filebuf);
-                page[len..].fill(0);
-
                 FrameTable::map(PhysAddr::from(buf).value(), current.clone(), va, false);
             }
         }
@@ -84,3 +123,19 @@ pub fn demand_page(va: usize) -> Result<()> {
         Err(OsError::BadPtr)
     }
 }
+
+/// The frame caching the page of `file` at `offset`, see [`File::page`]. Counts
+/// a major fault for `thread` if it is read from disk.
+fn file_page(thread: &Thread, file: &File, offset: usize) -> Result<usize> {
+    if !PageCache::contains(file.inum(), offset) {
+        VmStats::count(thread, Event::MajorFault);
+    }
+    file.page(offset)
+}
+
+/// Whether page `va` of `thread` is writable, now or once copied.
+fn is_writable(thread: &Thread, va: usize) -> bool {
+    let pt = thread.pagetable.as_ref().unwrap().lock();
+    let flag = pt.get_pte(va).unwrap().flag();
+    flag.intersects(PTEFlags::W | PTEFlags::COW)
+}
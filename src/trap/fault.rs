@@ -0,0 +1,47 @@
+//! Exceptions other than syscalls and page faults.
+//!
+//! In user mode they are the fault of the running program: its process takes
+//! the matching signal, which kills it unless it has a handler. In kernel mode
+//! they are a bug in the kernel.
+
+use crate::thread;
+use crate::trap::Frame;
+use crate::userproc::signal;
+
+use riscv::register::scause::Exception;
+use riscv::register::sstatus::SPP;
+
+/// The signal a user process takes for `cause`.
+fn signal_of(cause: Exception) -> usize {
+    match cause {
+        Exception::IllegalInstruction | Exception::Unknown => signal::SIGILL,
+        Exception::Breakpoint => signal::SIGTRAP,
+        Exception::InstructionFault | Exception::LoadFault | Exception::StoreFault => {
+            signal::SIGSEGV
+        }
+        // Misaligned fetches and accesses.
+        _ => signal::SIGBUS,
+    }
+}
+
+pub fn handler(frame: &mut Frame, cause: Exception, stval: usize) {
+    match frame.sstatus.spp() {
+        SPP::Supervisor => panic!(
+            "Kernel exception {:?}, stval={:#x}, sepc={:#x}",
+            cause, stval, frame.sepc,
+        ),
+        SPP::User => {
+            let sig = signal_of(cause);
+            kprintln!(
+                "User thread {:?} gets signal {} due to {:?}, stval={:#x}, sepc={:#x}.",
+                thread::current(),
+                sig,
+                cause,
+                stval,
+                frame.sepc,
+            );
+            // Acted on right before returning to user mode.
+            signal::force(sig);
+        }
+    }
+}
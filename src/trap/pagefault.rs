@@ -1,13 +1,18 @@
 use crate::error::OsError;
+use crate::mem::frametable::FrameTable;
 use crate::mem::userbuf::{
-    __knrl_read_usr_byte_pc, __knrl_read_usr_exit, __knrl_write_usr_byte_pc, __knrl_write_usr_exit,
+    __knrl_copy_from_usr_exit, __knrl_copy_from_usr_pc, __knrl_copy_to_usr_exit,
+    __knrl_copy_to_usr_pc, __knrl_read_usr_byte_pc, __knrl_read_usr_exit, __knrl_write_usr_byte_pc,
+    __knrl_write_usr_exit,
 };
-use crate::mem::PageTable;
+use crate::mem::vmstats::{Event, VmStats};
+use crate::mem::{PageAlign, PageTable};
 use crate::thread::{self};
 use crate::trap::demandpaging::demand_page;
 use crate::trap::stackgrowth;
 use crate::trap::Frame;
-use crate::userproc;
+use crate::userproc::signal;
+use crate::Result;
 
 use riscv::register::scause::Exception;
 
@@ -18,14 +23,16 @@ pub fn handler(frame: &mut Frame, _fault: Exception, addr: usize) {
 
     let _present = {
         let table = unsafe { PageTable::effective_pagetable() };
-        match table.get_pte(addr) {
-            Some(entry) => entry.is_valid(),
+        match table.get_leaf(addr) {
+            Some((entry, _)) => entry.is_valid(),
             None => false,
         }
     };
 
     unsafe { sstatus::set_sie() };
 
+    VmStats::count(&thread::current(), Event::Fault);
+
     #[cfg(feature = "my-test")]
     kprintln!(
         "[{:?}] Page fault at {:#x} on instruction {:#x}: {} error {} page in {} context.",
@@ -47,44 +54,76 @@ pub fn handler(frame: &mut Frame, _fault: Exception, addr: usize) {
 
     match privilege {
         SPP::Supervisor => {
-            if frame.sepc == __knrl_read_usr_byte_pc as _ {
+            let read_exit = if frame.sepc == __knrl_read_usr_byte_pc as _ {
+                Some(__knrl_read_usr_exit as _)
+            } else if frame.sepc == __knrl_copy_from_usr_pc as _ {
+                Some(__knrl_copy_from_usr_exit as _)
+            } else {
+                None
+            };
+            let write_exit = if frame.sepc == __knrl_write_usr_byte_pc as _ {
+                Some(__knrl_write_usr_exit as _)
+            } else if frame.sepc == __knrl_copy_to_usr_pc as _ {
+                Some(__knrl_copy_to_usr_exit as _)
+            } else {
+                None
+            };
+
+            if let Some(exit) = read_exit {
                 // try demand paging
                 if demand_page(addr).is_ok() {
                     return;
                 }
 
-                // Failed to read user byte from kernel space when trap in pagefault
+                // Failed to read user memory from kernel space when trap in pagefault,
+                // or out of memory: the syscall fails, and if the process was
+                // killed for memory, it exits on its way back to user mode.
                 frame.x[11] = 1; // set a1 to non-zero
-                frame.sepc = __knrl_read_usr_exit as _;
-            } else if frame.sepc == __knrl_write_usr_byte_pc as _ {
-                // try demand paging
-                if demand_page(addr).is_ok() {
+                frame.sepc = exit;
+            } else if let Some(exit) = write_exit {
+                // try copy-on-write and demand paging
+                if resolve(_fault, addr).is_ok() {
                     return;
                 }
 
-                // Failed to write user byte from kernel space when trap in pagefault
+                // Failed to write user memory from kernel space when trap in pagefault
                 frame.x[11] = 1; // set a1 to non-zero
-                frame.sepc = __knrl_write_usr_exit as _;
+                frame.sepc = exit;
             } else {
-                // try demand paging
-                if thread::current().suppt.is_some() && demand_page(addr).is_ok() {
-                    return;
-                }
+                // User memory is only accessed through `userbuf`.
                 panic!("Kernel page fault");
             }
         }
         SPP::User => {
-            // try demand paging
-            if demand_page(addr).is_ok() {
-                return;
+            // Pages mapped without `U` are inaccessible on purpose, see `PROT_NONE`.
+            let inaccessible = thread::current().pagetable.as_ref().map_or(false, |pt| {
+                pt.lock()
+                    .get_leaf(addr)
+                    .map_or(false, |(pte, _)| !pte.flag().is_empty() && !pte.is_user())
+            });
+            if inaccessible {
+                kprintln!(
+                    "User thread {:?} gets SIGSEGV due to accessing an inaccessible page.",
+                    thread::current(),
+                );
+                return signal::force(signal::SIGSEGV);
+            }
+
+            // try copy-on-write and demand paging
+            match resolve(_fault, addr) {
+                Ok(()) => return,
+                // Either the process is being killed, or the fault is taken
+                // again once memory is freed.
+                Err(OsError::OutOfMemory) => return,
+                Err(_) => {}
             }
 
             // try stack growth
             match stackgrowth::user_stack_growth(addr, frame.x[2]) {
-                Ok(_) => return,
+                Ok(_) | Err(OsError::OutOfMemory) => return,
                 Err(err) => {
                     kprintln!(
-                        "User thread {:?} dying due to page fault with error {}.",
+                        "User thread {:?} gets SIGSEGV due to page fault with error {}.",
                         thread::current(),
                         match err {
                             OsError::BadPtr => "BadPtr",
@@ -92,9 +131,39 @@ pub fn handler(frame: &mut Frame, _fault: Exception, addr: usize) {
                             _ => unreachable!(),
                         }
                     );
-                    userproc::exit(-1);
+                    // Acted on right before returning to user mode.
+                    signal::force(signal::SIGSEGV);
                 }
             }
         }
     }
 }
+
+/// Resolve a fault by copy-on-write, or else by demand paging.
+fn resolve(fault: Exception, addr: usize) -> Result<()> {
+    match copy_on_write(fault, addr) {
+        Err(OsError::BadPtr) => demand_page(addr),
+        result => result,
+    }
+}
+
+/// Resolve a store to a copy-on-write page by giving the current process its own copy.
+fn copy_on_write(fault: Exception, addr: usize) -> Result<()> {
+    if fault != Exception::StorePageFault {
+        return Err(OsError::BadPtr);
+    }
+
+    let va = addr.floor();
+    let current = thread::current();
+    let is_cow = current.pagetable.as_ref().map_or(false, |pt| {
+        pt.lock()
+            .get_pte(va)
+            .map_or(false, |pte| pte.is_valid() && pte.is_cow())
+    });
+    if !is_cow {
+        return Err(OsError::BadPtr);
+    }
+
+    FrameTable::copy_on_write(va, FrameTable::alloc_frame()?);
+    Ok(())
+}
@@ -2,12 +2,24 @@ use crate::error::OsError;
 use crate::mem::frametable::FrameTable;
 use crate::mem::PTEFlags;
 use crate::mem::PageAlign;
+use crate::mem::PageTable;
 use crate::mem::PhysAddr;
 use crate::mem::PG_SIZE;
 use crate::thread;
 use crate::Result;
 
-const STACK_LIMIT: usize = PG_SIZE * 2 * 1024; // 8MB
+/// Maximum size of the main user stack.
+pub const STACK_LIMIT: usize = PG_SIZE * 2 * 1024; // 8MB
+
+/// Flags of new stack pages, those of the initial one. It is executable only if
+/// the program asks for it with `PT_GNU_STACK`.
+fn stack_flags(pt: &PageTable) -> PTEFlags {
+    let flags = PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::U;
+    match pt.get_pte(0x80500000 - PG_SIZE) {
+        Some(pte) if pte.is_executable() => flags | PTEFlags::X,
+        _ => flags,
+    }
+}
 
 /// Lazily check an address to see if a pagefault on it can be handled by growing the stack.
 ///
@@ -22,14 +34,14 @@ pub fn user_stack_growth(addr: usize, sp: usize) -> Result<()> {
         return Err(OsError::StackOverflow);
     }
 
-    let new_stack_page_va = FrameTable::alloc_frame();
+    let new_stack_page_va = FrameTable::alloc_frame()?;
     let new_stack_page_pa = PhysAddr::from(new_stack_page_va);
     let new_stack_page_begin = PageAlign::floor(addr);
-    let flags = PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::U;
     let thread = thread::current();
-    let pt = thread.pagetable.as_ref().unwrap();
-    pt.lock()
-        .map(new_stack_page_pa, new_stack_page_begin, PG_SIZE, flags);
+    let mut pt = thread.pagetable.as_ref().unwrap().lock();
+    let flags = stack_flags(&pt);
+    pt.map(new_stack_page_pa, new_stack_page_begin, PG_SIZE, flags);
+    drop(pt);
     FrameTable::map(
         new_stack_page_pa.value(),
         thread::current(),
@@ -53,7 +65,7 @@ pub fn extend_stack_to_sp(sp: usize) -> Result<()> {
     let thread = thread::current();
     let pt = thread.pagetable.as_ref().unwrap();
     let spt = thread.suppt.as_ref().unwrap();
-    let flags = PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::U;
+    let flags = stack_flags(&pt.lock());
     while new_stack_page_begin < 0x80500000 {
         if pt
             .lock()
@@ -64,7 +76,7 @@ pub fn extend_stack_to_sp(sp: usize) -> Result<()> {
             break;
         }
 
-        let new_stack_page_va = FrameTable::alloc_frame();
+        let new_stack_page_va = FrameTable::alloc_frame()?;
         let new_stack_page_pa = PhysAddr::from(new_stack_page_va);
         pt.lock()
             .map(new_stack_page_pa, new_stack_page_begin, PG_SIZE, flags);
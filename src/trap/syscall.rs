@@ -7,15 +7,17 @@
 /*                               SYSCALL NUMBER                               */
 /* -------------------------------------------------------------------------- */
 
-use alloc::slice;
+use alloc::string::String;
 use alloc::vec::Vec;
 
 use crate::fs::disk::DISKFS;
 use crate::fs::FileSys;
 use crate::mem::userbuf;
 use crate::sbi;
+use crate::trap::Frame;
 use crate::userproc;
 use crate::userproc::fileop;
+use crate::userproc::{futex, mman, signal, uthread};
 use crate::Result;
 
 const SYS_HALT: usize = 1;
@@ -32,9 +34,35 @@ const SYS_CLOSE: usize = 11;
 const SYS_FSTAT: usize = 12;
 const SYS_MMAP: usize = 13;
 const SYS_MUNMAP: usize = 14;
+const SYS_IOCTL: usize = 17;
+const SYS_FORK: usize = 18;
+const SYS_GETPID: usize = 19;
+const SYS_GETPPID: usize = 20;
+const SYS_KILL: usize = 21;
+const SYS_SIGACTION: usize = 22;
+const SYS_SIGPROCMASK: usize = 23;
+const SYS_SIGRETURN: usize = 24;
+const SYS_PIPE: usize = 25;
+const SYS_DUP: usize = 26;
+const SYS_DUP2: usize = 27;
+const SYS_THREAD_CREATE: usize = 28;
+const SYS_THREAD_EXIT: usize = 29;
+const SYS_THREAD_JOIN: usize = 30;
+const SYS_FUTEX: usize = 31;
+const SYS_EXECVE: usize = 32;
+const SYS_SBRK: usize = 33;
+const SYS_MAP: usize = 34;
+const SYS_UNMAP: usize = 35;
+const SYS_MPROTECT: usize = 36;
+const SYS_MSYNC: usize = 37;
+const SYS_GETRUSAGE: usize = 38;
+const SYS_TERMSIG: usize = 39;
+const SYS_KSM: usize = 40;
 
 /// Handle all kinds of syscalls
-pub fn syscall_handler(_id: usize, _args: [usize; 3]) -> isize {
+///
+/// `frame` is the user context, with `sepc` already past the `ecall`.
+pub fn syscall_handler(_id: usize, _args: [usize; 6], frame: &mut Frame) -> isize {
     match _id {
         SYS_HALT => {
             kprintln!("Goodbye, World!");
@@ -43,10 +71,9 @@ pub fn syscall_handler(_id: usize, _args: [usize; 3]) -> isize {
 
         SYS_EXIT => userproc::exit(_args[0] as isize),
 
-        SYS_EXEC => match syscall_exec(_args[0], _args[1]) {
-            Ok(ret) => ret,
-            Err(_) => -1,
-        },
+        SYS_EXEC => syscall_exec(_args[0], _args[1], 0).unwrap_or(-1),
+
+        SYS_EXECVE => syscall_exec(_args[0], _args[1], _args[2]).unwrap_or(-1),
 
         SYS_WAIT => userproc::wait(_args[0] as isize).unwrap_or(-1),
 
@@ -59,9 +86,7 @@ pub fn syscall_handler(_id: usize, _args: [usize; 3]) -> isize {
             if userbuf::check_buf_writable(_args[1], _args[2]).is_err() {
                 return -1;
             }
-            let fd = _args[0] as isize;
-            let buf = unsafe { slice::from_raw_parts_mut(_args[1] as *mut u8, _args[2] as usize) };
-            fileop::read(fd, buf).unwrap_or(-1)
+            fileop::read(_args[0] as isize, _args[1], _args[2]).unwrap_or(-1)
         }
 
         SYS_WRITE => {
@@ -69,9 +94,7 @@ pub fn syscall_handler(_id: usize, _args: [usize; 3]) -> isize {
                 kprintln!("Invalid buffer");
                 return -1;
             }
-            let fd = _args[0] as isize;
-            let buf = unsafe { slice::from_raw_parts(_args[1] as *const u8, _args[2] as usize) };
-            fileop::write(fd, buf).unwrap_or(-1)
+            fileop::write(_args[0] as isize, _args[1], _args[2]).unwrap_or(-1)
         }
 
         SYS_REMOVE => syscall_remove(_args[0]).unwrap_or(-1),
@@ -88,28 +111,92 @@ pub fn syscall_handler(_id: usize, _args: [usize; 3]) -> isize {
 
         SYS_MUNMAP => fileop::munmap(_args[0] as isize).unwrap_or(-1),
 
+        SYS_SBRK => mman::sbrk(_args[0] as isize).unwrap_or(-1),
+
+        SYS_MAP => mman::map(
+            _args[0],
+            _args[1],
+            _args[2],
+            _args[3],
+            _args[4] as isize,
+            _args[5],
+        )
+        .unwrap_or(-1),
+
+        SYS_UNMAP => mman::unmap(_args[0], _args[1]).unwrap_or(-1),
+
+        SYS_MPROTECT => mman::mprotect(_args[0], _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_MSYNC => mman::msync(_args[0], _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_GETRUSAGE => mman::getrusage(_args[0] as isize, _args[1]).unwrap_or(-1),
+
+        SYS_KSM => mman::ksm(_args[0] as isize, _args[1]).unwrap_or(-1),
+
+        SYS_IOCTL => fileop::ioctl(_args[0] as isize, _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_FORK => userproc::fork(frame),
+
+        SYS_GETPID => userproc::current().pid(),
+
+        SYS_GETPPID => userproc::current().ppid(),
+
+        SYS_KILL => signal::kill(_args[0] as isize, _args[1]).unwrap_or(-1),
+
+        SYS_TERMSIG => userproc::termsig(_args[0] as isize).unwrap_or(-1),
+
+        SYS_SIGACTION => signal::sigaction(_args[0], _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_SIGPROCMASK => signal::sigprocmask(_args[0], _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_SIGRETURN => signal::sigreturn(frame),
+
+        SYS_PIPE => fileop::pipe(_args[0]).unwrap_or(-1),
+
+        SYS_DUP => fileop::dup(_args[0] as isize).unwrap_or(-1),
+
+        SYS_DUP2 => fileop::dup2(_args[0] as isize, _args[1] as isize).unwrap_or(-1),
+
+        SYS_THREAD_CREATE => uthread::create(frame, _args[0], _args[1], _args[2]).unwrap_or(-1),
+
+        SYS_THREAD_EXIT => userproc::exit_thread(_args[0]),
+
+        SYS_THREAD_JOIN => uthread::join(_args[0] as isize, _args[1]).unwrap_or(-1),
+
+        SYS_FUTEX => futex::futex(_args[0], _args[1], _args[2]).unwrap_or(-1),
+
         _ => -1,
     }
 }
 
-/// Handle the `exec` syscall
-///
-/// convert raw pointers `pathname` and `argv` to Rust `String` and `Vec<String>`, open the file and call `userproc::execute`
-fn syscall_exec(pathname: usize, argv_ptr: usize) -> Result<isize> {
-    let pathname = userbuf::read_user_string(pathname)?;
-    let mut argv = Vec::new();
+/// Convert a null-terminated array of user string pointers to `Vec<String>`,
+/// a null `array` being empty
+fn read_user_strings(array: usize) -> Result<Vec<String>> {
+    let mut strings = Vec::new();
+    if array == 0 {
+        return Ok(strings);
+    }
     let mut i = 0;
     loop {
-        let ptr = userbuf::read_user_doubleword(argv_ptr + i * 8)?;
+        let ptr = userbuf::read_user_doubleword(array + i * 8)?;
         if ptr == 0 {
             break;
         }
-        let arg = userbuf::read_user_string(ptr as usize)?;
-        argv.push(arg);
+        strings.push(userbuf::read_user_string(ptr as usize)?);
         i += 1;
     }
+    Ok(strings)
+}
+
+/// Handle the `exec` and `execve` syscalls
+///
+/// convert raw pointers `pathname`, `argv` and `envp` to Rust `String` and `Vec<String>`, open the file and call `userproc::execute_with_env`
+fn syscall_exec(pathname: usize, argv_ptr: usize, envp_ptr: usize) -> Result<isize> {
+    let pathname = userbuf::read_user_string(pathname)?;
+    let argv = read_user_strings(argv_ptr)?;
+    let envp = read_user_strings(envp_ptr)?;
     let file = DISKFS.open(pathname.as_str().into())?;
-    Ok(userproc::execute(file, argv))
+    Ok(userproc::execute_with_env(file, pathname, argv, envp))
 }
 
 /// Handle the `open` syscall
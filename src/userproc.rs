@@ -1,60 +1,212 @@
 //! User process.
 //!
+//! Processes form a tree. Every process has a pid, distinct from the tid of the
+//! thread running it, and a parent. When a process exits it becomes a zombie,
+//! holding its exit status until the parent reaps it with [`wait`]. Children of
+//! an exiting process are reparented to [`init`], the process the kernel itself
+//! runs as, which reaps them as soon as they exit.
+//!
+//! A process runs one or more threads, see [`uthread`].
 
 pub mod fileop;
+pub mod futex;
 mod load;
-mod wait;
+pub mod mman;
+pub mod oom;
+pub mod signal;
+pub mod uthread;
 
+use alloc::collections::BTreeMap;
 use alloc::string::String;
-
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::arch::asm;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicIsize, Ordering::SeqCst};
 use riscv::register::sstatus;
 
+use crate::device::tty::Tty;
 use crate::fs::File;
 use crate::mem::frametable::FrameTable;
 use crate::mem::pagetable::KernelPgTable;
 use crate::mem::suppagetable::SupPageTable;
+use crate::mem::vmstats::{Pages, VmStats};
 use crate::sbi::interrupt;
-use crate::thread;
+use crate::sync::{Lazy, Mutex, Semaphore};
+use crate::thread::{self, Thread};
 use crate::trap::{trap_exit_u, Frame};
 use crate::userproc::fileop::fdtable::FDTable;
 use crate::userproc::fileop::mmaptable::MmapTable;
+use crate::Result;
 
 pub struct UserProc {
-    #[allow(dead_code)]
-    bin: File,
+    pid: isize,
+    /// The executable, kept write-protected until the process exits.
+    bin: Mutex<Option<File>>,
+    parent: Mutex<Weak<UserProc>>,
+    /// Children not reaped yet, running or zombie.
+    children: Mutex<Vec<Arc<UserProc>>>,
+    /// Exit status, set once the process is a zombie.
+    status: Mutex<Option<isize>>,
+    /// Upped when the process exits.
+    exited: Semaphore,
+    signals: Mutex<signal::Signals>,
+    threads: Mutex<uthread::Threads>,
+    futexes: futex::Futexes,
+    heap: Mutex<mman::Heap>,
+    pub vmstats: VmStats,
+}
+
+/// All live processes, by pid.
+fn processes() -> &'static Mutex<BTreeMap<isize, Weak<UserProc>>> {
+    static PROCESSES: Lazy<Mutex<BTreeMap<isize, Weak<UserProc>>>> =
+        Lazy::new(|| Mutex::new(BTreeMap::new()));
+    &PROCESSES
+}
+
+/// The live process `pid`. Neither zombies nor [`init`] can be found.
+pub fn find(pid: isize) -> Option<Arc<UserProc>> {
+    processes().lock().get(&pid).and_then(Weak::upgrade)
 }
 
 impl UserProc {
-    pub fn new(file: File) -> Self {
-        Self { bin: file }
+    /// Creates a process running `bin`, as a child of the current process.
+    pub fn new(bin: File) -> Arc<Self> {
+        /// The next process's id, 1 is taken by init.
+        static PID: AtomicIsize = AtomicIsize::new(2);
+
+        let parent = current();
+        let proc = Arc::new(Self {
+            pid: PID.fetch_add(1, SeqCst),
+            bin: Mutex::new(Some(bin)),
+            parent: Mutex::new(Arc::downgrade(&parent)),
+            children: Mutex::new(Vec::new()),
+            status: Mutex::new(None),
+            exited: Semaphore::new(0),
+            signals: Mutex::new(signal::Signals::default()),
+            threads: Mutex::new(uthread::Threads::default()),
+            futexes: futex::Futexes::default(),
+            heap: Mutex::new(mman::Heap::default()),
+            vmstats: VmStats::default(),
+        });
+        parent.children.lock().push(proc.clone());
+        processes().lock().insert(proc.pid, Arc::downgrade(&proc));
+        proc
+    }
+
+    /// A child of the current process running the same program, for [`fork`].
+    pub fn fork(&self) -> Arc<Self> {
+        // The clone keeps the executable write-protected on its own.
+        let mut bin = self.bin.lock().clone().expect("forking an exited process");
+        bin.deny_write();
+        let child = Self::new(bin);
+        *child.signals.lock() = self.signals.lock().fork();
+        *child.heap.lock() = *self.heap.lock();
+        child
+    }
+
+    /// Undoes [`new`](Self::new) for a process of the current one that could
+    /// not be started.
+    fn discard(&self) {
+        current()
+            .children
+            .lock()
+            .retain(|child| child.pid != self.pid);
+        processes().lock().remove(&self.pid);
+    }
+
+    pub fn pid(&self) -> isize {
+        self.pid
+    }
+
+    /// Pid of the parent. Orphans are adopted by [`init`].
+    pub fn ppid(&self) -> isize {
+        self.parent
+            .lock()
+            .upgrade()
+            .map_or(INIT_PID, |parent| parent.pid)
     }
 }
 
-/// Execute an object file with arguments.
+/// Pid of [`init`].
+pub const INIT_PID: isize = 1;
+
+/// The process the kernel runs as, root of the process tree.
+///
+/// Kernel threads, and in particular the tests and the `init=` program
+/// launcher, spawn processes as its children and may wait for them. Orphans
+/// reparented to it are not tracked, so they are reaped as soon as they exit.
+pub fn init() -> &'static Arc<UserProc> {
+    static INIT: Lazy<Arc<UserProc>> = Lazy::new(|| {
+        Arc::new(UserProc {
+            pid: INIT_PID,
+            bin: Mutex::new(None),
+            parent: Mutex::new(Weak::new()),
+            children: Mutex::new(Vec::new()),
+            status: Mutex::new(None),
+            exited: Semaphore::new(0),
+            signals: Mutex::new(signal::Signals::default()),
+            threads: Mutex::new(uthread::Threads::default()),
+            futexes: futex::Futexes::default(),
+            heap: Mutex::new(mman::Heap::default()),
+            vmstats: VmStats::default(),
+        })
+    });
+    &INIT
+}
+
+/// The process of the current thread, [`init`] for kernel threads.
+pub fn current() -> Arc<UserProc> {
+    thread::current()
+        .userproc
+        .clone()
+        .unwrap_or_else(|| init().clone())
+}
+
+/// Execute an object file with arguments, and an empty environment. The path
+/// of the file is `argv[0]`.
+///
+/// ## Return
+/// - `-1`: On error.
+/// - `pid`: Pid of the new process.
+pub fn execute(file: File, argv: Vec<String>) -> isize {
+    let path = argv.first().cloned().unwrap_or_default();
+    execute_with_env(file, path, argv, Vec::new())
+}
+
+/// Execute an object file with arguments and environment strings `envp`, each
+/// of the form `NAME=value`.
+///
+/// `file`, opened at `path`, may also be a script starting with a `#!` line,
+/// run by the interpreter it names.
 ///
 /// ## Return
 /// - `-1`: On error.
-/// - `tid`: Tid of the newly spawned thread.
-// #[allow(unused_variables)]
-pub fn execute(mut file: File, argv: Vec<String>) -> isize {
+/// - `pid`: Pid of the new process.
+pub fn execute_with_env(file: File, path: String, argv: Vec<String>, envp: Vec<String>) -> isize {
+    let (mut file, argv) = match load::resolve_script(file, path, argv) {
+        Ok(x) => x,
+        Err(_) => return -1,
+    };
     #[cfg(feature = "debug")]
     kprintln!(
-        "[{:?}] prepare to execute a process with args {:?}",
+        "[{:?}] prepare to execute a process with args {:?}, env {:?}",
         thread::current(),
-        argv
+        argv,
+        envp
     );
 
     // It only copies L2 pagetable. This approach allows the new thread
     // to access kernel code and data during syscall without the need to
     // swithch pagetables.
-    let mut pt = KernelPgTable::clone();
+    let mut pt = match KernelPgTable::clone() {
+        Ok(pt) => pt,
+        Err(_) => return -1,
+    };
     let mut spt = SupPageTable::new();
 
-    let (exec_info, stack_pa, stack_va) =
-        match load::load_executable(&mut file, &mut pt, &mut spt, &argv) {
+    let (exec_info, frames) =
+        match load::load_executable(&mut file, &mut pt, &mut spt, &argv, &envp) {
             Ok(x) => x,
             Err(_) => unsafe {
                 pt.destroy();
@@ -68,43 +220,259 @@ pub fn execute(mut file: File, argv: Vec<String>) -> isize {
     frame.x[2] = exec_info.init_sp;
     frame.x[10] = argv.len() as usize;
     frame.x[11] = frame.x[2] + 8; // argv = sp + 8
+    frame.x[12] = frame.x[11] + (argv.len() + 1) * 8; // envp, right after argv
 
     // Here the new process will be created.
     let userproc = UserProc::new(file);
+    *userproc.heap.lock() = mman::Heap::new(exec_info.brk);
+    let pid = userproc.pid();
+
+    let spawned = spawn(
+        frame,
+        None,
+        |builder| {
+            builder
+                .pagetable(pt)
+                .userproc(userproc.clone())
+                .fdtable(FDTable::new())
+                .mmaptable(MmapTable::new())
+                .sup_pagetable(spt)
+        },
+        |child| {
+            for (pa, va) in frames {
+                FrameTable::map(pa, child.clone(), va, false);
+            }
+        },
+    );
+    if spawned.is_err() {
+        userproc.discard();
+        return -1;
+    }
+
+    // A child started by the kernel or by the foreground process takes over the console.
+    let parent = current();
+    if parent.pid == INIT_PID || Tty::get().foreground() == Some(parent.pid) {
+        Tty::get().set_foreground(Some(pid));
+    }
+
+    pid
+}
+
+/// Forks the current process.
+///
+/// The child gets the address space of the parent, shared copy-on-write, and
+/// copies of its file descriptor and mmap tables. It resumes from `frame` as
+/// the parent does, but with `0` returned. Other threads of the parent are not
+/// duplicated.
+///
+/// ## Return
+/// - `-1`: On error.
+/// - `pid`: Pid of the child, in the parent.
+pub fn fork(frame: &Frame) -> isize {
+    let current = thread::current();
+    if current.userproc.is_none() {
+        return -1;
+    }
+    let pagetable = match KernelPgTable::clone() {
+        Ok(pt) => pt,
+        Err(_) => return -1,
+    };
+    let userproc = current.userproc.as_ref().unwrap().fork();
 
-    let child = thread::Builder::new(move || start(frame))
-        .pagetable(pt)
-        .userproc(userproc)
-        .fdtable(FDTable::new())
-        .mmaptable(MmapTable::new())
-        .sup_pagetable(spt)
-        .spawn();
+    let pid = userproc.pid();
+    let (threads, stack) = current
+        .userproc
+        .as_ref()
+        .unwrap()
+        .threads
+        .lock()
+        .fork(current.id());
+    *userproc.threads.lock() = threads;
 
-    FrameTable::map(stack_pa, child.clone(), stack_va, false);
+    let mut frame = *frame;
+    frame.x[10] = 0;
 
-    wait::WaitManager::register(child.id());
+    let spawned = spawn(
+        frame,
+        stack,
+        |builder| {
+            builder
+                .priority(current.priority())
+                .pagetable(pagetable)
+                .userproc(userproc.clone())
+                .fdtable(current.fdtable.as_ref().unwrap().fork())
+                .mmaptable(current.mmaptable.as_ref().unwrap().fork())
+                .sup_pagetable(SupPageTable::new())
+        },
+        |child| FrameTable::fork(&current, child),
+    );
+    if spawned.is_err() {
+        userproc.discard();
+        return -1;
+    }
+
+    pid
+}
+
+/// Spawns a thread of a user process, which enters user mode with `frame` on
+/// stack slot `stack`, see [`uthread`].
+///
+/// `configure` sets the process up in the thread builder. The thread does not
+/// run before `setup` has been called on it, e.g. to finish its address space.
+///
+/// ## Return
+/// - `Err(OutOfMemory)`: there is no memory for the thread, see
+///   [`Builder::build`](thread::Builder::build). `setup` is not called.
+fn spawn<C, S>(frame: Frame, stack: Option<usize>, configure: C, setup: S) -> Result<Arc<Thread>>
+where
+    C: FnOnce(thread::Builder) -> thread::Builder,
+    S: FnOnce(&Arc<Thread>),
+{
+    let ready = Arc::new(Semaphore::new(0));
+    let child = {
+        let ready = ready.clone();
+        configure(thread::Builder::new(move || {
+            ready.down();
+            start(frame)
+        }))
+    }
+    .try_spawn()?;
+
+    setup(&child);
+    child
+        .userproc
+        .as_ref()
+        .unwrap()
+        .threads
+        .lock()
+        .add(&child, stack);
+    ready.up();
 
-    child.id()
+    Ok(child)
 }
 
 /// Exits a process.
 ///
+/// Other threads of the process terminate at their next return to user mode,
+/// which those blocked in the kernel are interrupted to reach. The process
+/// stays a zombie until its parent reaps it, its children are reparented to
+/// [`init`].
+///
 /// Panic if the current thread doesn't own a user process.
-pub fn exit(_value: isize) -> ! {
+pub fn exit(value: isize) -> ! {
+    let proc = thread::current().userproc.clone().unwrap();
+    {
+        let mut threads = proc.threads.lock();
+        threads.set_exiting(value);
+        // Threads blocked in the kernel won't return to user mode otherwise.
+        threads.interrupt_all();
+    }
+    drop(proc);
+    exit_thread(0)
+}
+
+/// Exits the current thread of a user process, passing `value` to whoever joins it.
+///
+/// The last thread to exit ends the process.
+pub fn exit_thread(value: usize) -> ! {
+    let proc = thread::current().userproc.clone().unwrap();
+    let status = proc.threads.lock().exit(thread::current().id(), value);
+    match status {
+        Some(status) => terminate(proc, status),
+        None => drop(proc),
+    }
     interrupt::set(false);
-    wait::WaitManager::clean_up(thread::current().id());
-    wait::WaitManager::exit(_value);
-    FrameTable::cleanup();
     thread::exit();
 }
 
-/// Waits for a child thread, which must own a user process.
+/// Exits the current thread if its process is exiting, before returning to user mode.
+pub fn exit_if_exiting() {
+    let exiting = thread::current()
+        .userproc
+        .as_ref()
+        .map_or(false, |proc| proc.threads.lock().exiting());
+    if exiting {
+        exit_thread(0);
+    }
+}
+
+/// Turns the current process, which has no threads left, into a zombie.
+fn terminate(proc: Arc<UserProc>, status: isize) {
+    mman::sync_all();
+    processes().lock().remove(&proc.pid);
+    proc.bin.lock().take();
+    // Pipe ends must close now, not whenever the thread is freed.
+    thread::current().fdtable.as_ref().unwrap().clear();
+
+    interrupt::set(false);
+    for child in core::mem::take(&mut *proc.children.lock()) {
+        *child.parent.lock() = Arc::downgrade(init());
+    }
+
+    let ppid = proc.ppid();
+    if Tty::get().foreground() == Some(proc.pid) {
+        Tty::get().set_foreground(Some(ppid).filter(|ppid| *ppid != INIT_PID));
+    }
+    if let Some(parent) = find(ppid) {
+        signal::send(&parent, signal::SIGCHLD);
+    }
+
+    #[cfg(feature = "debug")]
+    kprintln!(
+        "[VM] pid {}: {} faults, {} evictions, {} swap-ins ({} replacement)",
+        proc.pid,
+        proc.vmstats.faults(),
+        proc.vmstats.evictions(),
+        proc.vmstats.swap_ins(),
+        crate::mem::replace::policy_name(),
+    );
+    *proc.status.lock() = Some(status);
+    proc.exited.up();
+
+    FrameTable::cleanup();
+    let swapped = thread::current().suppt.as_ref().unwrap().release();
+    VmStats::sub(&thread::current(), Pages::Swapped, swapped);
+}
+
+/// Waits for a child of the current process to exit and reaps it.
 ///
 /// ## Return
 /// - `Some(exit_value)`
-/// - `None`: if tid was not created by the current thread.
-pub fn wait(_tid: isize) -> Option<isize> {
-    wait::WaitManager::wait(_tid)
+/// - `None`: if `pid` is not a child of the current process, or was reaped
+///   already, or if the current thread is interrupted while waiting.
+pub fn wait(pid: isize) -> Option<isize> {
+    let parent = current();
+    let child = parent
+        .children
+        .lock()
+        .iter()
+        .find(|child| child.pid == pid)
+        .cloned()?;
+
+    child.exited.down_interruptible().ok()?;
+    parent.children.lock().retain(|child| child.pid != pid);
+    let status = *child.status.lock();
+    status
+}
+
+/// Waits for a child of the current process to exit, like [`wait`], but
+/// leaves it to be reaped.
+///
+/// ## Return
+/// - `Some(sig)`: the signal that killed it, 0 if it exited on its own
+/// - `None`: as for [`wait`]
+pub fn termsig(pid: isize) -> Option<isize> {
+    let child = current()
+        .children
+        .lock()
+        .iter()
+        .find(|child| child.pid == pid)
+        .cloned()?;
+
+    child.exited.down_interruptible().ok()?;
+    child.exited.up();
+    let sig = child.threads.lock().killed_by();
+    Some(sig as isize)
 }
 
 /// Initializes a user process in current thread.
@@ -1,20 +1,23 @@
 pub mod fdtable;
 pub mod mmaptable;
+pub mod pipe;
 
+use alloc::sync::Arc;
+use alloc::vec;
+
+use crate::device::tty::{Tty, TtyMode, TCGETMODE, TCSETMODE};
 use crate::fs::disk::Path;
 use crate::fs::disk::DISKFS;
-use crate::fs::File;
 use crate::fs::FileSys;
 use crate::io::Read;
 use crate::io::Seek;
 use crate::io::SeekFrom;
 use crate::io::Write;
 use crate::mem::userbuf;
-use crate::mem::PTEFlags;
-use crate::mem::PhysAddr;
-use crate::mem::PG_SIZE;
-use crate::thread;
+use crate::mem::{PTEFlags, PG_SIZE};
 use crate::thread::current;
+use crate::userproc::fileop::fdtable::FileDesc;
+use crate::userproc::{self, mman, signal};
 use crate::OsError;
 use crate::Result;
 
@@ -24,13 +27,21 @@ const O_RDWR: u32 = 0x002;
 const O_CREATE: u32 = 0x200;
 const O_TRUNC: u32 = 0x400;
 
+/// Bytes copied between user buffers and the kernel at a time.
+const CHUNK_SIZE: usize = PG_SIZE;
+
 /// Helper function to check if the file is opened read-only
 ///
 /// treated sepcial because the `O_RDONLY` flag equals to 0
-fn is_readonly(flags: u32) -> bool {
+pub(super) fn is_readonly(flags: u32) -> bool {
     flags & 0b11 == O_RDONLY
 }
 
+/// Helper function to check if the file is opened write-only
+pub(super) fn is_writeonly(flags: u32) -> bool {
+    flags & 0b11 == O_WRONLY
+}
+
 /// Open file by path `str` and access flags `flags`
 ///
 /// use DISKFS to open/create a file, then add it to the current process's fdtable
@@ -65,59 +76,130 @@ pub fn open(path: &str, flags: u32) -> Result<isize> {
     Ok(fdtable.alloc_fd(file, flags))
 }
 
-/// Read from file descriptor `fd` to buffer `buf`
+/// Read from file descriptor `fd` to the user buffer `buf` of `len` bytes
 ///
 /// ## Return
 /// - `Ok(size)`: number of bytes read
 /// - `Err`: error
-pub fn read(fd: isize, buf: &mut [u8]) -> Result<isize> {
-    if fd == 0 {
-        let mut cnt = 0;
-        while cnt < buf.len() {
-            let c = crate::sbi::console_getchar();
-            if c == 0 {
-                break;
-            }
-            buf[cnt] = c as u8;
-            cnt += 1;
-        }
-        return Ok(cnt as isize);
-    }
-
+pub fn read(fd: isize, buf: usize, len: usize) -> Result<isize> {
     let current = current();
     let fdtable = current.fdtable.as_ref().unwrap();
-    let (file, flags) = fdtable.fd_to_file(fd).ok_or(OsError::FileNotOpened)?;
+    let (desc, flags) = fdtable.get(fd).ok_or(OsError::FileNotOpened)?;
     if flags & O_WRONLY != 0 {
         return Ok(-1);
     }
-    let size = file.lock().read(buf)?;
+    let size = match desc {
+        // `^C` fails the read, and the `SIGINT` it sent is acted on right after.
+        FileDesc::Tty => read_chunks(buf, len.min(CHUNK_SIZE), |chunk| Tty::get().read(chunk))?,
+        FileDesc::File(file) => {
+            let mut file = file.lock();
+            read_chunks(buf, len, |chunk| file.read(chunk))?
+        }
+        // A pipe has at most a chunk to read at once.
+        FileDesc::PipeReader(pipe) => {
+            read_chunks(buf, len.min(CHUNK_SIZE), |chunk| pipe.read(chunk))?
+        }
+        FileDesc::PipeWriter(_) => return Ok(-1),
+    };
     Ok(size as isize)
 }
 
-/// Write to file descriptor `fd` from buffer `buf`
+/// Write to file descriptor `fd` from the user buffer `buf` of `len` bytes
 ///
 /// ## Return
 /// - `Ok(size)`: number of bytes written
 /// - `Err`: error
-pub fn write(fd: isize, buf: &[u8]) -> Result<isize> {
-    if fd == 1 || fd == 2 {
-        kprint!("{}", core::str::from_utf8(buf).unwrap());
-        return Ok(buf.len() as isize);
-    }
-
+pub fn write(fd: isize, buf: usize, len: usize) -> Result<isize> {
     let current = current();
     let fdtable = current.fdtable.as_ref().unwrap();
-    let (file, flags) = fdtable.fd_to_file(fd).ok_or(OsError::FileNotOpened)?;
+    let (desc, flags) = fdtable.get(fd).ok_or(OsError::FileNotOpened)?;
     if is_readonly(flags) {
         return Ok(-1);
     }
-    let size = file.lock().write(buf)?;
+    let size = match desc {
+        FileDesc::Tty => write_chunks(buf, len, |chunk| Ok(Tty::get().write(chunk)))?,
+        FileDesc::File(file) => {
+            let mut file = file.lock();
+            write_chunks(buf, len, |chunk| file.write(chunk))?
+        }
+        FileDesc::PipeWriter(pipe) => {
+            write_chunks(buf, len, |chunk| pipe.write(chunk)).map_err(|err| {
+                // Nobody will ever read it.
+                if err == OsError::BrokenPipe {
+                    signal::send(&userproc::current(), signal::SIGPIPE);
+                }
+                err
+            })?
+        }
+        FileDesc::PipeReader(_) => return Ok(-1),
+    };
     Ok(size as isize)
 }
 
-/// Close file descriptor `fd`
+/// Reads up to `len` bytes with `read` into the user buffer `buf`, through a
+/// kernel buffer, a chunk at a time, until `read` falls short.
 ///
-/// need to take special care of the stdio file descriptors (i.e. 0, 1, 2)
+/// The kernel never touches `buf` but through [`userbuf`], so that a page of
+/// it that can't be paged in fails the read, instead of faulting in the
+/// kernel. Bytes read before an error are returned.
+fn read_chunks<F>(buf: usize, len: usize, mut read: F) -> Result<usize>
+where
+    F: FnMut(&mut [u8]) -> Result<usize>,
+{
+    let mut chunk = vec![0; len.min(CHUNK_SIZE)];
+    let mut total = 0;
+    while total < len {
+        let chunk = &mut chunk[..(len - total).min(CHUNK_SIZE)];
+        let cnt = match read(chunk) {
+            Ok(cnt) => cnt,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        userbuf::write_user_bytes(buf + total, &chunk[..cnt])?;
+        total += cnt;
+        if cnt < chunk.len() {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Writes `len` bytes of the user buffer `buf` with `write`, like
+/// [`read_chunks`] reads them.
+fn write_chunks<F>(buf: usize, len: usize, mut write: F) -> Result<usize>
+where
+    F: FnMut(&[u8]) -> Result<usize>,
+{
+    let mut chunk = vec![0; len.min(CHUNK_SIZE)];
+    let mut total = 0;
+    while total < len {
+        let chunk = &mut chunk[..(len - total).min(CHUNK_SIZE)];
+        userbuf::read_user_bytes(buf + total, chunk)?;
+        let cnt = match write(chunk) {
+            Ok(cnt) => cnt,
+            Err(_) if total > 0 => break,
+            Err(err) => return Err(err),
+        };
+        total += cnt;
+        if cnt < chunk.len() {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Drop a reference to an open file description, closing it if it was the last one
+///
+/// pipe ends close on their own when dropped
+fn release(desc: FileDesc) {
+    if let FileDesc::File(file) = desc {
+        if Arc::strong_count(&file) == 1 {
+            file.lock().close();
+        }
+    }
+}
+
+/// Close file descriptor `fd`
 ///
 /// ## Return
 /// - `Ok(0)`: successfully closed
@@ -125,17 +207,70 @@ pub fn write(fd: isize, buf: &[u8]) -> Result<isize> {
 pub fn close(fd: isize) -> Result<isize> {
     let current = current();
     let fdtable = current.fdtable.as_ref().unwrap();
-    if fd as usize >= 3 {
-        if let Some((file, _)) = fdtable.fd_to_file(fd) {
-            file.lock().close();
-        } else {
-            return Err(OsError::FileNotOpened);
-        }
+    let (desc, _) = fdtable.close_fd(fd).ok_or(OsError::FileNotOpened)?;
+    release(desc);
+    Ok(0)
+}
+
+/// Create a pipe, and write its read and write file descriptors to the
+/// `int[2]` at `fds_ptr`
+///
+/// ## Return
+/// - `Ok(0)`: successfully created
+/// - `Err`: error
+pub fn pipe(fds_ptr: usize) -> Result<isize> {
+    userbuf::check_buf_writable(fds_ptr, 8)?;
+
+    let (reader, writer) = pipe::pipe();
+    let current = current();
+    let fdtable = current.fdtable.as_ref().unwrap();
+    let rfd = fdtable.install(FileDesc::PipeReader(Arc::new(reader)), O_RDONLY);
+    let wfd = fdtable.install(FileDesc::PipeWriter(Arc::new(writer)), O_WRONLY);
+
+    let fds = (rfd as u32 as u64) | ((wfd as u32 as u64) << 32);
+    if let Err(err) = userbuf::write_user_doubleword(fds_ptr, fds) {
+        fdtable.close_fd(rfd);
+        fdtable.close_fd(wfd);
+        return Err(err);
     }
-    fdtable.close_fd(fd);
     Ok(0)
 }
 
+/// Duplicate file descriptor `fd` to the lowest free one
+///
+/// ## Return
+/// - `Ok(newfd)`: the new file descriptor
+/// - `Err`: error
+pub fn dup(fd: isize) -> Result<isize> {
+    let current = current();
+    let fdtable = current.fdtable.as_ref().unwrap();
+    let (desc, flags) = fdtable.get(fd).ok_or(OsError::FileNotOpened)?;
+    Ok(fdtable.install(desc, flags))
+}
+
+/// Make file descriptor `newfd` a duplicate of `oldfd`, closing `newfd` first
+/// if it is open
+///
+/// this is how stdio gets redirected, e.g. `dup2(pipefd, 1)`
+///
+/// ## Return
+/// - `Ok(newfd)`
+/// - `Err`: error
+pub fn dup2(oldfd: isize, newfd: isize) -> Result<isize> {
+    if newfd < 0 {
+        return Err(OsError::FileNotOpened);
+    }
+    let current = current();
+    let fdtable = current.fdtable.as_ref().unwrap();
+    let (desc, flags) = fdtable.get(oldfd).ok_or(OsError::FileNotOpened)?;
+    if oldfd != newfd {
+        if let Some((old, _)) = fdtable.set(newfd, desc, flags) {
+            release(old);
+        }
+    }
+    Ok(newfd)
+}
+
 /// Seek to position `pos` (expressed by the offset in bytes from the start of the file) in file descriptor `fd`
 ///
 /// ## Return
@@ -177,83 +312,37 @@ pub fn fstat(fd: isize, stat_ptr: usize) -> Result<isize> {
     }
 }
 
-pub fn mmap(fd: isize, addr: usize) -> Result<isize> {
-    fn check(start: usize, len: usize) -> bool {
-        if start % PG_SIZE != 0 {
-            return false;
-        }
-        let current = thread::current();
-        let pt = current.pagetable.as_ref().unwrap().lock();
-        let spt = current.suppt.as_ref().unwrap();
-        for pos in (start..(start + len)).step_by(PG_SIZE) {
-            if pt.get_pte(pos).is_some_and(|pte| pte.is_valid()) {
-                return false;
-            }
-            if spt.query(pos).is_some() {
-                return false;
-            }
-        }
-        true
+/// Control the terminal behind file descriptor `fd`
+///
+/// ## Return
+/// - `Ok(0)`: successfully done
+/// - `Err`: error
+pub fn ioctl(fd: isize, request: usize, arg: usize) -> Result<isize> {
+    match current().fdtable.as_ref().unwrap().get(fd) {
+        Some((FileDesc::Tty, _)) => {}
+        _ => return Err(OsError::NotTty),
     }
-
-    fn map(file: File, start: usize, len: usize) {
-        let current = thread::current();
-        let pt = current.pagetable.as_ref().unwrap();
-        let spt = current.suppt.as_ref().unwrap();
-        for pos in (start..(start + len)).step_by(PG_SIZE) {
-            pt.lock().map(
-                PhysAddr::from_pa(0),
-                pos,
-                1,
-                PTEFlags::R | PTEFlags::W | PTEFlags::U,
-            );
-            spt.map_in_file_mapped(
-                pos,
-                file.clone(),
-                pos - start,
-                PG_SIZE.min(start + len - pos),
-            );
-        }
+    match request {
+        TCGETMODE => userbuf::write_user_doubleword(arg, Tty::get().mode().bits() as u64)?,
+        TCSETMODE => Tty::get().set_mode(TtyMode::from_bits(arg).ok_or(OsError::UserError)?),
+        _ => return Err(OsError::UserError),
     }
+    Ok(0)
+}
 
-    if fd < 3 || addr == 0 {
+/// Map the whole file `fd` at `addr`, readable and writable, see
+/// [`mman::map`]. Writes go back to the file.
+///
+/// ## Return
+/// - `Ok(mapid)`: On success.
+/// - `Ok(-1)`: If the file is empty, or `addr` is misaligned or not free.
+/// - `Err`: If `fd` is not an open file.
+pub fn mmap(fd: isize, addr: usize) -> Result<isize> {
+    if addr == 0 {
         return Ok(-1);
     }
 
     let file = current()
-        .fdtable
-        .as_ref()
-        .unwrap()
-        .fd_to_file(fd)
-        .ok_or(OsError::FileNotOpened)?
-        .0;
-
-    let len = file.lock().len()?;
-
-    if len == 0 || !check(addr, len) {
-        return Ok(-1);
-    }
-
-    map(file.lock().clone(), addr, len);
-
-    let mapid = thread::current()
-        .mmaptable
-        .as_ref()
-        .unwrap()
-        .alloc_mapid(fd, addr, len);
-
-    Ok(mapid)
-}
-
-pub fn munmap(mapid: isize) -> Result<isize> {
-    let current = thread::current();
-    let (fd, start, len) = current
-        .mmaptable
-        .as_ref()
-        .unwrap()
-        .query(mapid)
-        .ok_or(OsError::BadMapid)?;
-    let mut file = current
         .fdtable
         .as_ref()
         .unwrap()
@@ -263,24 +352,11 @@ pub fn munmap(mapid: isize) -> Result<isize> {
         .lock()
         .clone();
 
-    let pt = current.pagetable.as_ref().unwrap();
-    let spt = current.suppt.as_ref().unwrap();
-    for pos in (start..(start + len)).step_by(PG_SIZE) {
-        if pt
-            .lock()
-            .get_pte(pos)
-            .is_some_and(|pte| pte.is_valid() && pte.is_dirty())
-        {
-            file.seek(SeekFrom::Start(pos - start))?;
-            let buf = unsafe {
-                core::slice::from_raw_parts(pos as *const u8, PG_SIZE.min(start + len - pos))
-            };
-            file.write(buf)?;
-        }
-        pt.lock()
-            .map(PhysAddr::from_pa(0), pos, 1, PTEFlags::empty());
-        spt.remove(pos);
-    }
+    Ok(mman::map_whole_file(addr, file, PTEFlags::R | PTEFlags::W | PTEFlags::U).unwrap_or(-1))
+}
 
-    Ok(0)
+/// Unmap the mapping `mapid`, writing it back to its file. The file need not
+/// be open any more.
+pub fn munmap(mapid: isize) -> Result<isize> {
+    mman::unmap_file_by_id(mapid)
 }
@@ -4,50 +4,89 @@ use alloc::sync::Arc;
 use crate::fs::File;
 use crate::sync::Mutex;
 
+use super::pipe::{PipeReader, PipeWriter};
+use super::{O_RDONLY, O_WRONLY};
+
+/// An open file description, shared by all file descriptors duplicated from
+/// the same one, with `dup` or `fork`.
+#[derive(Clone)]
+pub enum FileDesc {
+    /// The console tty.
+    Tty,
+    File(Arc<Mutex<File>>),
+    PipeReader(Arc<PipeReader>),
+    PipeWriter(Arc<PipeWriter>),
+}
+
 /// File descriptor table
 ///
 /// one for each user process
 pub struct FDTable {
-    /// opened/closed status of stdin, stdout, stderr
-    stdfd: Mutex<[bool; 3]>,
-
-    /// user file descriptor mappings, from fd to file and flags
-    userfd: Mutex<BTreeMap<isize, (Arc<Mutex<File>>, u32)>>,
+    /// file descriptor mappings, from fd to open file and flags
+    fds: Mutex<BTreeMap<isize, (FileDesc, u32)>>,
 }
 
 impl FDTable {
+    /// A table with stdin, stdout and stderr open on the console.
     pub fn new() -> Self {
+        let mut fds = BTreeMap::new();
+        fds.insert(0, (FileDesc::Tty, O_RDONLY));
+        fds.insert(1, (FileDesc::Tty, O_WRONLY));
+        fds.insert(2, (FileDesc::Tty, O_WRONLY));
         Self {
-            stdfd: Mutex::new([true, true, true]),
-            userfd: Mutex::new(BTreeMap::new()),
+            fds: Mutex::new(fds),
         }
     }
 
-    /// Allocate a file descriptor
-    pub fn alloc_fd(&self, file: File, flags: u32) -> isize {
-        let mut table = self.userfd.lock();
-        let mut fd = 3;
+    /// Duplicate the table for a forked process. Both tables refer to the same
+    /// open files, which share their positions.
+    pub fn fork(&self) -> Self {
+        Self {
+            fds: Mutex::new(self.fds.lock().clone()),
+        }
+    }
+
+    /// Allocate the lowest free file descriptor for `desc`
+    pub fn install(&self, desc: FileDesc, flags: u32) -> isize {
+        let mut table = self.fds.lock();
+        let mut fd = 0;
         while table.contains_key(&fd) {
             fd += 1;
         }
-        table.insert(fd, (Arc::new(Mutex::new(file)), flags));
+        table.insert(fd, (desc, flags));
         fd
     }
 
-    /// Get the file and flags by file descriptor (assume thet `fd` should not be stdio file descriptor)
+    /// Allocate a file descriptor for a file
+    pub fn alloc_fd(&self, file: File, flags: u32) -> isize {
+        self.install(FileDesc::File(Arc::new(Mutex::new(file))), flags)
+    }
+
+    /// Get the open file and flags by file descriptor
+    pub fn get(&self, fd: isize) -> Option<(FileDesc, u32)> {
+        self.fds.lock().get(&fd).cloned()
+    }
+
+    /// Get the file and flags by file descriptor, if it refers to a file on disk
     pub fn fd_to_file(&self, fd: isize) -> Option<(Arc<Mutex<File>>, u32)> {
-        self.userfd.lock().get_mut(&fd).cloned()
-    }
-
-    /// Close a file descriptor (may be stdio file descriptor or user file descriptor)
-    pub fn close_fd(&self, fd: isize) -> Option<(Arc<Mutex<File>>, u32)> {
-        if (fd as usize) < 3 {
-            let mut table = self.stdfd.lock();
-            table[fd as usize] = false;
-            None
-        } else {
-            let mut table = self.userfd.lock();
-            table.remove(&fd)
+        match self.get(fd)? {
+            (FileDesc::File(file), flags) => Some((file, flags)),
+            _ => None,
         }
     }
+
+    /// Make `fd` refer to `desc`, returns what it referred to before
+    pub fn set(&self, fd: isize, desc: FileDesc, flags: u32) -> Option<(FileDesc, u32)> {
+        self.fds.lock().insert(fd, (desc, flags))
+    }
+
+    /// Close a file descriptor
+    pub fn close_fd(&self, fd: isize) -> Option<(FileDesc, u32)> {
+        self.fds.lock().remove(&fd)
+    }
+
+    /// Close all file descriptors, when the process exits
+    pub fn clear(&self) {
+        self.fds.lock().clear();
+    }
 }
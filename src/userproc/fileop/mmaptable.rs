@@ -1,29 +1,62 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 
+use crate::fs::File;
 use crate::sync::Mutex;
 
-pub struct MmapTable(Mutex<BTreeMap<isize, (isize, usize, usize)>>);
+/// A mapping of a file, see [`mman`](crate::userproc::mman).
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    /// The mapped file, which stays open as long as the mapping does.
+    pub file: File,
+    pub start: usize,
+    pub len: usize,
+    /// Offset in the file of `start`.
+    pub offset: usize,
+    /// Whether writes go back to the file.
+    pub shared: bool,
+    /// Whether the file is open for writing.
+    pub writable: bool,
+}
 
-impl MmapTable {
-    pub fn new() -> Self {
-        Self(Mutex::new(BTreeMap::new()))
+impl Mapping {
+    pub fn end(&self) -> usize {
+        self.start + self.len
     }
+}
+
+/// Memory mappings of an address space.
+///
+/// When both are locked, `anon` is locked first.
+pub struct MmapTable {
+    /// File mappings by mapid.
+    pub files: Mutex<BTreeMap<isize, Mapping>>,
+    /// Pages of anonymous mappings.
+    pub anon: Mutex<BTreeSet<usize>>,
+}
 
-    pub fn query(&self, mapid: isize) -> Option<(isize, usize, usize)> {
-        self.0.lock().get(&mapid).cloned()
+impl MmapTable {
+    pub fn new() -> Self {
+        Self {
+            files: Mutex::new(BTreeMap::new()),
+            anon: Mutex::new(BTreeSet::new()),
+        }
     }
 
-    pub fn alloc_mapid(&self, fd: isize, start: usize, len: usize) -> isize {
-        let mut table = self.0.lock();
-        let mut mapid = 0;
-        while table.contains_key(&mapid) {
-            mapid += 1;
+    /// Duplicate the table for a forked process.
+    pub fn fork(&self) -> Self {
+        Self {
+            files: Mutex::new(self.files.lock().clone()),
+            anon: Mutex::new(self.anon.lock().clone()),
         }
-        table.insert(mapid, (fd, start, len));
-        mapid
     }
+}
 
-    pub fn unmap(&self, mapid: isize) {
-        self.0.lock().remove(&mapid);
+/// Allocate the lowest free mapid in `files` for `mapping`.
+pub fn alloc_mapid(files: &mut BTreeMap<isize, Mapping>, mapping: Mapping) -> isize {
+    let mut mapid = 0;
+    while files.contains_key(&mapid) {
+        mapid += 1;
     }
+    files.insert(mapid, mapping);
+    mapid
 }
@@ -0,0 +1,141 @@
+//! Anonymous pipes.
+//!
+//! A pipe is a bounded ring buffer with a read end and a write end. Reads block
+//! while the pipe is empty and writes block while it is full. Once the write end
+//! is gone, reads drain the buffer and then return end of file. Once the read
+//! end is gone, writes fail with [`OsError::BrokenPipe`].
+//!
+//! Both block interruptibly: a thread of a process being killed gives up with
+//! [`OsError::Interrupted`], see [`thread::interrupt`](crate::thread::interrupt).
+//!
+//! Each end closes when dropped, i.e. when the last file descriptor referring to
+//! it is closed.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+
+use crate::sync::{Condvar, Mutex};
+use crate::{OsError, Result};
+
+/// Capacity of a pipe, in bytes.
+pub const PIPE_SIZE: usize = 4096;
+
+struct PipeInner {
+    buf: Box<[u8]>,
+    /// Index of the first unread byte.
+    head: usize,
+    /// Number of unread bytes.
+    len: usize,
+    reader: bool,
+    writer: bool,
+}
+
+struct Pipe {
+    inner: Mutex<PipeInner>,
+    /// Notified when bytes are written or the write end closes.
+    readable: Condvar,
+    /// Notified when bytes are read or the read end closes.
+    writable: Condvar,
+}
+
+/// Creates a pipe, returns its read end and write end.
+pub fn pipe() -> (PipeReader, PipeWriter) {
+    let pipe = Arc::new(Pipe {
+        inner: Mutex::new(PipeInner {
+            buf: vec![0; PIPE_SIZE].into_boxed_slice(),
+            head: 0,
+            len: 0,
+            reader: true,
+            writer: true,
+        }),
+        readable: Condvar::new(),
+        writable: Condvar::new(),
+    });
+    (PipeReader(pipe.clone()), PipeWriter(pipe))
+}
+
+pub struct PipeReader(Arc<Pipe>);
+
+impl PipeReader {
+    /// Reads at most `buf.len()` bytes, blocking until some are available.
+    ///
+    /// Returns `0` at end of file, after the write end has been closed.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut inner = self.0.inner.lock();
+        while inner.len == 0 && inner.writer {
+            self.0.readable.wait_interruptible(&mut inner)?;
+        }
+
+        let cnt = buf.len().min(inner.len);
+        for byte in buf[..cnt].iter_mut() {
+            *byte = inner.buf[inner.head];
+            inner.head = (inner.head + 1) % PIPE_SIZE;
+        }
+        inner.len -= cnt;
+
+        self.0.writable.notify_all();
+        Ok(cnt)
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut inner = self.0.inner.lock();
+        inner.reader = false;
+        self.0.writable.notify_all();
+    }
+}
+
+pub struct PipeWriter(Arc<Pipe>);
+
+impl PipeWriter {
+    /// Writes all of `buf`, blocking while the pipe is full.
+    ///
+    /// If the read end closes meanwhile, returns the number of bytes written
+    /// so far, or [`OsError::BrokenPipe`] if there are none. Likewise if the
+    /// current thread is interrupted, with [`OsError::Interrupted`].
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.0.inner.lock();
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < buf.len() {
+            while inner.len == PIPE_SIZE && inner.reader && result.is_ok() {
+                result = self.0.writable.wait_interruptible(&mut inner);
+            }
+            if !inner.reader {
+                result = Err(OsError::BrokenPipe);
+            }
+            if result.is_err() {
+                break;
+            }
+
+            let cnt = (buf.len() - written).min(PIPE_SIZE - inner.len);
+            for &byte in buf[written..written + cnt].iter() {
+                let tail = (inner.head + inner.len) % PIPE_SIZE;
+                inner.buf[tail] = byte;
+                inner.len += 1;
+            }
+            written += cnt;
+
+            self.0.readable.notify_all();
+        }
+
+        match result {
+            Err(err) if written == 0 => Err(err),
+            _ => Ok(written),
+        }
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut inner = self.0.inner.lock();
+        inner.writer = false;
+        self.0.readable.notify_all();
+    }
+}
@@ -0,0 +1,85 @@
+//! Fast user-space mutexes.
+//!
+//! A futex is a 32-bit word in user memory. User code manipulates it with
+//! atomic instructions and only calls into the kernel to sleep while the word
+//! holds some value ([`FUTEX_WAIT`]) or to wake sleepers up ([`FUTEX_WAKE`]).
+//! Futexes are private to a process: they are keyed by virtual address.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+use crate::mem::userbuf::read_user_byte;
+use crate::sync::{Mutex, Semaphore};
+use crate::{OsError, Result};
+
+/// Sleep if the futex word equals `val`.
+pub const FUTEX_WAIT: usize = 0;
+/// Wake up at most `val` sleepers.
+pub const FUTEX_WAKE: usize = 1;
+
+/// Threads sleeping on the futexes of a process, by address.
+#[derive(Default)]
+pub struct Futexes(Mutex<BTreeMap<usize, VecDeque<Arc<Semaphore>>>>);
+
+fn read_user_u32(addr: usize) -> Result<u32> {
+    let mut word = 0;
+    for i in 0..4 {
+        word |= (read_user_byte((addr + i) as *const u8)? as u32) << (i * 8);
+    }
+    Ok(word)
+}
+
+/// Operates on the futex at user address `addr`.
+///
+/// ## Return
+/// - `Ok(0)`: [`FUTEX_WAIT`] slept and was woken up.
+/// - `Ok(n)`: [`FUTEX_WAKE`] woke up `n` threads.
+/// - `Err(WouldBlock)`: [`FUTEX_WAIT`] found a value other than `val`.
+/// - `Err(Interrupted)`: [`FUTEX_WAIT`] was interrupted, see [`thread::interrupt`](crate::thread::interrupt).
+/// - `Err`: other errors.
+pub fn futex(addr: usize, op: usize, val: usize) -> Result<isize> {
+    if addr % 4 != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    let proc = super::current();
+
+    match op {
+        FUTEX_WAIT => {
+            // Checking the word under the lock makes sure a wake up in between
+            // is not missed.
+            let mut futexes = proc.futexes.0.lock();
+            if read_user_u32(addr)? != val as u32 {
+                return Err(OsError::WouldBlock);
+            }
+            let sema = Arc::new(Semaphore::new(0));
+            futexes.entry(addr).or_default().push_back(sema.clone());
+            drop(futexes);
+
+            if let Err(err) = sema.down_interruptible() {
+                if let Some(sleepers) = proc.futexes.0.lock().get_mut(&addr) {
+                    sleepers.retain(|s| !Arc::ptr_eq(s, &sema));
+                }
+                return Err(err);
+            }
+            Ok(0)
+        }
+        FUTEX_WAKE => {
+            let mut futexes = proc.futexes.0.lock();
+            let mut woken = 0;
+            if let Some(sleepers) = futexes.get_mut(&addr) {
+                while woken < val {
+                    match sleepers.pop_front() {
+                        Some(sema) => sema.up(),
+                        None => break,
+                    }
+                    woken += 1;
+                }
+                if sleepers.is_empty() {
+                    futexes.remove(&addr);
+                }
+            }
+            Ok(woken as isize)
+        }
+        _ => Err(OsError::InvalidArgument),
+    }
+}
@@ -1,9 +1,12 @@
+use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use elf_rs::{Elf, ElfFile, ProgramHeaderEntry, ProgramHeaderFlags, ProgramType};
+use core::ops::Range;
+use elf_rs::{Elf, ElfFile, ElfType, ProgramHeaderEntry, ProgramHeaderFlags, ProgramType};
 
-use crate::fs::File;
+use crate::fs::disk::DISKFS;
+use crate::fs::{File, FileSys};
 use crate::io::prelude::*;
 use crate::mem::frametable::FrameTable;
 use crate::mem::pagetable::{PTEFlags, PageTable};
@@ -15,6 +18,121 @@ use crate::{OsError, Result};
 pub(super) struct ExecInfo {
     pub entry_point: usize,
     pub init_sp: usize,
+    /// User address of the program headers, if they are loaded.
+    pub phdr: Option<usize>,
+    /// Size of a program header.
+    pub phent: usize,
+    /// Number of program headers.
+    pub phnum: usize,
+    /// Whether the stack is executable, see `PT_GNU_STACK`.
+    pub exec_stack: bool,
+    /// Start of the heap, the first page after all segments.
+    pub brk: usize,
+}
+
+/// Where position-independent executables are loaded.
+pub const PIE_BASE: usize = 0x4000_0000;
+
+/* Program header types unknown to `elf_rs` */
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const PT_GNU_RELRO: u32 = 0x6474_e552;
+
+/* Tags of the dynamic section */
+const DT_NULL: u64 = 0;
+const DT_SYMTAB: u64 = 6;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_SYMENT: u64 = 11;
+const DT_REL: u64 = 17;
+
+/* Symbols */
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+const STB_WEAK: u8 = 2;
+
+/* Relocation types */
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_64: u32 = 2;
+const R_RISCV_RELATIVE: u32 = 3;
+const R_RISCV_JUMP_SLOT: u32 = 5;
+
+/* Keys of the auxiliary vector */
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// Longest `#!` line of a script, including the `#!`.
+const SHEBANG_MAX: usize = 128;
+
+/// Most scripts run in a row by interpreters that are scripts themselves.
+const MAX_SCRIPT_DEPTH: usize = 4;
+
+/// Separators in a `#!` line.
+const BLANK: &[char] = &[' ', '\t'];
+
+/// Reads the `#!` line of a script, returns its interpreter path and optional
+/// argument, or `None` if `file` is not a script.
+fn read_shebang(file: &mut File) -> Result<Option<(String, Option<String>)>> {
+    file.rewind()?;
+    let mut buf = [0u8; SHEBANG_MAX];
+    let len = file.read(&mut buf)?;
+    if !buf[..len].starts_with(b"#!") {
+        return Ok(None);
+    }
+
+    let line = match buf[..len].iter().position(|&c| c == b'\n') {
+        Some(end) => &buf[2..end],
+        None if len < SHEBANG_MAX => &buf[2..len],
+        None => return Err(OsError::ArgumentTooLong),
+    };
+    let line = core::str::from_utf8(line)
+        .map_err(|_| OsError::UnknownFormat)?
+        .trim_matches(BLANK);
+
+    // Anything after the interpreter is a single argument, as on Linux.
+    let (interp, arg) = match line.split_once(BLANK) {
+        Some((interp, arg)) => (interp, Some(arg.trim_matches(BLANK))),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(OsError::UnknownFormat);
+    }
+    Ok(Some((
+        interp.into(),
+        arg.filter(|arg| !arg.is_empty()).map(String::from),
+    )))
+}
+
+/// Follows the `#!` lines of scripts, from `file` at `path` run with `argv`.
+///
+/// A script runs as its interpreter, with the interpreter path, its optional
+/// argument, then the script path in place of `argv[0]` and the rest of `argv`
+/// as arguments. Returns the executable to load and its arguments.
+pub(super) fn resolve_script(
+    mut file: File,
+    mut path: String,
+    mut argv: Vec<String>,
+) -> Result<(File, Vec<String>)> {
+    for _ in 0..=MAX_SCRIPT_DEPTH {
+        let (interp, arg) = match read_shebang(&mut file)? {
+            Some(shebang) => shebang,
+            None => return Ok((file, argv)),
+        };
+
+        file = DISKFS.open(interp.as_str().into())?;
+        argv = core::iter::once(interp.clone())
+            .chain(arg)
+            .chain(core::iter::once(path))
+            .chain(argv.drain(..).skip(1))
+            .collect();
+        path = interp;
+    }
+    Err(OsError::TooManyLevels)
 }
 
 /// Loads an executable file
@@ -23,32 +141,38 @@ pub(super) struct ExecInfo {
 /// - `pagetable`: User's pagetable. We install the mapping to executable codes into it.
 ///
 /// ## Return
-/// On success, returns `Ok(usize, usize)`:
-/// - arg0: the entry point of user program
-/// - arg1: the initial sp of user program
+/// On success, returns `Ok(ExecInfo, frames)`, `frames` being the `(pa, va)` of
+/// each frame already loaded and mapped, including the stack.
 pub(super) fn load_executable(
     file: &mut File,
     pagetable: &mut PageTable,
     sup_pagetable: &mut SupPageTable,
     argv: &Vec<String>,
-) -> Result<(ExecInfo, usize, usize)> {
-    let mut exec_info = load_elf(file, pagetable, sup_pagetable)?;
+    envp: &[String],
+) -> Result<(ExecInfo, Vec<(usize, usize)>)> {
+    let mut frames = Vec::new();
+    let mut exec_info = load_elf(file, pagetable, sup_pagetable, &mut frames)?;
 
     // Initialize user stack.
-    let (init_sp, stack_pa, stack_va) = init_user_stack(pagetable, exec_info.init_sp, argv)?;
+    let (init_sp, stack_pa, stack_va) = init_user_stack(pagetable, &exec_info, argv, envp)?;
     exec_info.init_sp = init_sp;
+    frames.push((stack_pa, stack_va));
 
     // Forbid modifying executable file when running
     file.deny_write();
 
-    Ok((exec_info, stack_pa, stack_va))
+    Ok((exec_info, frames))
 }
 
 /// Parses the specified executable file and loads segments (lazy load)
+///
+/// Position-independent executables are loaded at [`PIE_BASE`] and relocated.
+/// The pages relocations write to are loaded right away, into `frames`.
 fn load_elf(
     file: &mut File,
     pagetable: &mut PageTable,
     sup_pagetable: &mut SupPageTable,
+    frames: &mut Vec<(usize, usize)>,
 ) -> Result<ExecInfo> {
     // Ensure cursor is at the beginning
     file.rewind()?;
@@ -62,24 +186,194 @@ fn load_elf(
         Ok(Elf::Elf32(_)) | Err(_) => return Err(OsError::UnknownFormat),
     };
 
+    let header = elf.elf_header();
+    let base = match header.elftype() {
+        ElfType::ET_EXEC => 0,
+        ElfType::ET_DYN => PIE_BASE,
+        _ => return Err(OsError::UnknownFormat),
+    };
+
+    let phdrs: Vec<_> = elf.program_header_iter().collect();
+    let find = |ty| phdrs.iter().find(|p| p.ph_type() == ty);
+
+    // There is no dynamic linker to run.
+    if find(ProgramType::INTERP).is_some() {
+        return Err(OsError::UnknownFormat);
+    }
+
+    // Relocated values to write, by page.
+    let mut relocs: BTreeMap<usize, Vec<(usize, u64)>> = BTreeMap::new();
+    if let (ElfType::ET_DYN, Some(dynamic)) = (header.elftype(), find(ProgramType::DYNAMIC)) {
+        for (addr, value) in relocations(&buf, &phdrs, dynamic, base)? {
+            if addr % 8 != 0 {
+                return Err(OsError::UnknownFormat);
+            }
+            relocs
+                .entry(addr.floor())
+                .or_default()
+                .push((addr - addr.floor(), value));
+        }
+    }
+
+    // Read-only once relocated.
+    let relro = find(ProgramType::Unknown(PT_GNU_RELRO))
+        .map(|p| base + p.vaddr() as usize..base + (p.vaddr() + p.memsz()) as usize);
+
     // load each loadable segment into memory (lazy load)
-    elf.program_header_iter()
-        .filter(|p| p.ph_type() == ProgramType::LOAD)
-        .for_each(|p| load_segment(file, &p, pagetable, sup_pagetable));
+    for p in phdrs.iter().filter(|p| p.ph_type() == ProgramType::LOAD) {
+        let segment = Segment {
+            phdr: p,
+            base,
+            relro: relro.clone(),
+        };
+        load_segment(
+            file,
+            &buf,
+            &segment,
+            &relocs,
+            pagetable,
+            sup_pagetable,
+            frames,
+        )?;
+    }
+    // Relocations must write to some segment.
+    if relocs
+        .keys()
+        .any(|&page| !frames.iter().any(|&(_, va)| va == page))
+    {
+        return Err(OsError::UnknownFormat);
+    }
+
+    // The program headers are in memory if some segment covers them.
+    let phoff = header.program_header_offset();
+    let phdr = phdrs.iter().find_map(|p| match p.ph_type() {
+        ProgramType::PHDR => Some(base + p.vaddr() as usize),
+        ProgramType::LOAD if (p.offset()..p.offset() + p.filesz()).contains(&phoff) => {
+            Some(base + (p.vaddr() + phoff - p.offset()) as usize)
+        }
+        _ => None,
+    });
 
     Ok(ExecInfo {
-        entry_point: elf.elf_header().entry_point() as _,
+        entry_point: base + header.entry_point() as usize,
         init_sp: 0x80500000,
+        phdr,
+        phent: header.program_header_entry_size() as _,
+        phnum: header.program_header_entry_num() as _,
+        exec_stack: find(ProgramType::Unknown(PT_GNU_STACK))
+            .map_or(false, |p| p.flags().contains(ProgramHeaderFlags::EXECUTE)),
+        brk: phdrs
+            .iter()
+            .filter(|p| p.ph_type() == ProgramType::LOAD)
+            .map(|p| (base + (p.vaddr() + p.memsz()) as usize).ceil())
+            .max()
+            .unwrap_or(base),
     })
 }
 
+/// Reads the dynamic relocations of a program loaded at `base`, as the
+/// values to write at user addresses.
+fn relocations(
+    buf: &[u8],
+    phdrs: &[ProgramHeaderEntry],
+    dynamic: &ProgramHeaderEntry,
+    base: usize,
+) -> Result<Vec<(usize, u64)>> {
+    let bytes = |off: usize, len: usize| buf.get(off..off + len).ok_or(OsError::UnknownFormat);
+    let word = |off: usize| -> Result<u64> {
+        let mut word = [0; 8];
+        word.copy_from_slice(bytes(off, 8)?);
+        Ok(u64::from_le_bytes(word))
+    };
+    // File offset of the data at link-time address `vaddr`.
+    let offset = |vaddr: u64| {
+        phdrs
+            .iter()
+            .filter(|p| p.ph_type() == ProgramType::LOAD)
+            .find(|p| (p.vaddr()..p.vaddr() + p.filesz()).contains(&vaddr))
+            .map(|p| (p.offset() + vaddr - p.vaddr()) as usize)
+            .ok_or(OsError::UnknownFormat)
+    };
+
+    let (mut rela, mut relasz, mut relaent) = (0, 0, 24);
+    let (mut symtab, mut syment) = (0, 24);
+    let start = dynamic.offset() as usize;
+    for off in (start..start + dynamic.filesz() as usize).step_by(16) {
+        let value = word(off + 8)?;
+        match word(off)? {
+            DT_NULL => break,
+            DT_RELA => rela = value,
+            DT_RELASZ => relasz = value as usize,
+            DT_RELAENT => relaent = value as usize,
+            DT_SYMTAB => symtab = value,
+            DT_SYMENT => syment = value as usize,
+            // Not used on RISC-V.
+            DT_REL => return Err(OsError::UnknownFormat),
+            _ => {}
+        }
+    }
+    if relasz == 0 {
+        return Ok(Vec::new());
+    }
+    // Entries must at least hold the fields read below.
+    if relaent < 24 || syment < 24 {
+        return Err(OsError::UnknownFormat);
+    }
+
+    // Run-time address of symbol `index`.
+    let symbol = |index: usize| -> Result<u64> {
+        let sym = offset(symtab)? + index * syment;
+        let shndx = u16::from_le_bytes([bytes(sym + 6, 1)?[0], bytes(sym + 7, 1)?[0]]);
+        match shndx {
+            // Only weak symbols may stay undefined, as null.
+            SHN_UNDEF if bytes(sym + 4, 1)?[0] >> 4 == STB_WEAK => Ok(0),
+            SHN_UNDEF => Err(OsError::UnknownFormat),
+            SHN_ABS => word(sym + 8),
+            _ => Ok(base as u64 + word(sym + 8)?),
+        }
+    };
+
+    let table = offset(rela)?;
+    let mut relocs = Vec::new();
+    for off in (table..table + relasz).step_by(relaent) {
+        let (r_offset, r_info, r_addend) = (word(off)?, word(off + 8)?, word(off + 16)?);
+        let sym = (r_info >> 32) as usize;
+        let value = match r_info as u32 {
+            R_RISCV_NONE => continue,
+            R_RISCV_RELATIVE => (base as u64).wrapping_add(r_addend),
+            R_RISCV_64 => symbol(sym)?.wrapping_add(r_addend),
+            R_RISCV_JUMP_SLOT => symbol(sym)?,
+            _ => return Err(OsError::UnknownFormat),
+        };
+        relocs.push((base + r_offset as usize, value));
+    }
+    Ok(relocs)
+}
+
+/// A loadable segment, and where it goes.
+struct Segment<'a> {
+    phdr: &'a ProgramHeaderEntry<'a>,
+    /// Load base of the program.
+    base: usize,
+    /// Range to map read-only, if any.
+    relro: Option<Range<usize>>,
+}
+
 /// Loads one segment and installs pagetable mappings (lazy load)
+///
+/// Pages in `relocs` are loaded now from `buf`, the contents of `file`, and
+/// relocated. A page shared with a segment loaded before goes in the frame
+/// already in `frames`, with the permissions of both.
 fn load_segment(
     file: &File,
-    phdr: &ProgramHeaderEntry,
+    buf: &[u8],
+    segment: &Segment,
+    relocs: &BTreeMap<usize, Vec<(usize, u64)>>,
     pagetable: &mut PageTable,
     sup_pagetable: &mut SupPageTable,
-) {
+    frames: &mut Vec<(usize, usize)>,
+) -> Result<()> {
+    let phdr = segment.phdr;
     assert_eq!(phdr.ph_type(), ProgramType::LOAD);
 
     // Meaningful contents of this segment starts from `fileoff`.
@@ -97,93 +391,188 @@ fn load_segment(
     }
 
     // Install position: `ubase`.
-    let ubase = (phdr.vaddr() as usize) & !PG_MASK;
+    let ubase = (segment.base + phdr.vaddr() as usize) & !PG_MASK;
     let pageoff = (phdr.vaddr() as usize) & PG_MASK;
-    assert_eq!(fileoff & PG_MASK, pageoff);
+    if fileoff & PG_MASK != pageoff || phdr.filesz() > phdr.memsz() {
+        return Err(OsError::UnknownFormat);
+    }
 
     // How many pages need to be allocated
-    let pages = div_round_up(pageoff + phdr.memsz() as usize, PG_SIZE);
+    let memend = pageoff + phdr.memsz() as usize;
+    let pages = div_round_up(memend, PG_SIZE);
     let mut readbytes = phdr.filesz() as usize + pageoff;
 
     // Allocate & map pages
     for p in 0..pages {
-        // let buf = unsafe { UserPool::alloc_pages(1) };
-        // let page = unsafe { (buf as *mut [u8; PG_SIZE]).as_mut().unwrap() };
-
         // Read `readsz` bytes, fill remaining bytes with 0.
         let readsz = readbytes.min(PG_SIZE);
-        // page[..readsz].copy_from_slice(&filebuf[readpos..readpos + readsz]);
-        // page[readsz..].fill(0);
+
+        let uaddr = ubase + p * PG_SIZE;
+        let flag = match &segment.relro {
+            Some(relro) if relro.start <= uaddr && uaddr + PG_SIZE <= relro.end => {
+                leaf_flag & !PTEFlags::W
+            }
+            _ => leaf_flag,
+        };
 
         // The installed page will be freed when pagetable drops, which happens
         // when user process exits. No manual resource collect is required.
-        let uaddr = ubase + p * PG_SIZE;
-        pagetable.map(PhysAddr::from_pa(0), uaddr, 1, leaf_flag);
-        sup_pagetable.map_in_flie_lazy_load(uaddr, file.clone(), readpos, readsz);
+        match relocs.get(&uaddr) {
+            Some(writes) => {
+                // Only the part of the page in this segment is its to fill.
+                let start = if p == 0 { pageoff } else { 0 };
+                let end = (memend - p * PG_SIZE).min(PG_SIZE);
+                let filled = readsz.max(start);
+                let contents = buf
+                    .get(readpos + start..readpos + filled)
+                    .ok_or(OsError::UnknownFormat)?;
+
+                let loaded = frames
+                    .iter()
+                    .find(|&&(_, va)| va == uaddr)
+                    .map(|&(pa, _)| pa);
+                let (pa, flag) = match loaded {
+                    Some(pa) => {
+                        let flag = flag | pagetable.get_pte(uaddr).unwrap().flag();
+                        (PhysAddr::from_pa(pa), flag)
+                    }
+                    None => {
+                        let pa = PhysAddr::from(FrameTable::alloc_frame()?);
+                        frames.push((pa.value(), uaddr));
+                        (pa, flag)
+                    }
+                };
+                let page = unsafe { (pa.into_va() as *mut [u8; PG_SIZE]).as_mut().unwrap() };
+                if loaded.is_none() {
+                    page.fill(0);
+                }
+
+                page[start..filled].copy_from_slice(contents);
+                page[filled..end].fill(0);
+                // Again, as the part of another segment loaded before may be overwritten.
+                for &(off, value) in writes {
+                    page[off..off + 8].copy_from_slice(&value.to_le_bytes());
+                }
+                pagetable.map(pa, uaddr, PG_SIZE, flag | PTEFlags::V);
+            }
+            None => {
+                pagetable.map(PhysAddr::from_pa(0), uaddr, 1, flag);
+                sup_pagetable.map_in_flie_lazy_load(uaddr, file.clone(), readpos, readsz);
+            }
+        }
 
         readbytes -= readsz;
         readpos += readsz;
     }
 
     assert_eq!(readbytes, 0);
+    Ok(())
+}
+
+/// 16 bytes for `AT_RANDOM`, mixed from the clock. They are not cryptographically
+/// secure, but differ between runs.
+fn random_bytes() -> [u8; 16] {
+    let mut x = crate::sbi::timer::clock() as u64 | 1;
+    let mut bytes = [0; 16];
+    for chunk in bytes.chunks_mut(8) {
+        // xorshift64
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        chunk.copy_from_slice(&x.to_le_bytes());
+    }
+    bytes
 }
 
 /// Initializes the user stack.
+///
+/// From the initial sp upwards, the stack holds `argc`, the `argv` pointers,
+/// a null, the `envp` pointers, a null, and the auxiliary vector, ended by
+/// `AT_NULL`. The strings and the `AT_RANDOM` bytes are above them.
 fn init_user_stack(
     pagetable: &mut PageTable,
-    init_sp: usize,
+    exec_info: &ExecInfo,
     argv: &Vec<String>,
+    envp: &[String],
 ) -> Result<(usize, usize, usize)> {
+    let init_sp = exec_info.init_sp;
     assert!(init_sp % PG_SIZE == 0, "initial sp address misaligns");
 
     // Allocate a page from UserPool as user stack.
-    let stack_va = FrameTable::alloc_frame();
+    let stack_va = FrameTable::alloc_frame()?;
     let stack_pa = PhysAddr::from(stack_va);
 
     // Get the start address of stack page
     let stack_page_begin = PageAlign::floor(init_sp - 1);
 
     // Install mapping
-    let flags = PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::U;
-    pagetable.map(stack_pa, stack_page_begin, PG_SIZE, flags);
-
-    // Copy arguments to user stack
-
-    // Helper function to push a usize value to stack
-    fn push_stack(sp: usize, val: usize) -> usize {
-        let sp = sp - 8;
-        unsafe {
-            core::ptr::write(sp as *mut usize, val);
-        }
-        sp
+    let mut flags = PTEFlags::V | PTEFlags::R | PTEFlags::W | PTEFlags::U;
+    if exec_info.exec_stack {
+        flags |= PTEFlags::X;
     }
+    pagetable.map(stack_pa, stack_page_begin, PG_SIZE, flags);
 
-    // Push argument strings
-    let stack_top = stack_va as usize + PG_SIZE;
+    let stack_bottom = stack_va as usize;
+    let stack_top = stack_bottom + PG_SIZE;
     let mut sp = stack_top;
-    let mut pos = Vec::new();
-    for arg in argv.iter().rev() {
-        let len = arg.len();
-        sp -= len + 1;
-        if sp < stack_va as usize {
+    // User address of what is at `sp`.
+    let user = |sp: usize| init_sp - (stack_top - sp);
+
+    // Helper function to push bytes to stack, returns their user address
+    let mut push_bytes = |bytes: &[u8]| -> Result<usize> {
+        if sp - stack_bottom < bytes.len() {
             return Err(OsError::ArgumentTooLong);
         }
-        pos.push(init_sp - (stack_top as usize - sp));
+        sp -= bytes.len();
         unsafe {
-            core::ptr::copy(arg.as_ptr(), sp as *mut u8, len);
-            core::ptr::write((sp + len) as *mut u8, 0);
+            core::ptr::copy(bytes.as_ptr(), sp as *mut u8, bytes.len());
         }
-    }
+        Ok(user(sp))
+    };
 
-    // Push pointers to the strings
-    sp = round_down(sp, 8);
-    sp = push_stack(sp, 0);
-    for x in pos {
-        sp = push_stack(sp, x);
+    // Push strings
+    let mut push_strings = |strs: &[String]| -> Result<Vec<usize>> {
+        strs.iter()
+            .map(|s| {
+                push_bytes(&[0])?;
+                push_bytes(s.as_bytes())
+            })
+            .collect()
+    };
+    let envp = push_strings(envp)?;
+    let argv = push_strings(argv)?;
+    let random = push_bytes(&random_bytes())?;
+
+    let mut auxv = vec![
+        (AT_PAGESZ, PG_SIZE),
+        (AT_ENTRY, exec_info.entry_point),
+        (AT_RANDOM, random),
+    ];
+    if let Some(phdr) = exec_info.phdr {
+        auxv.extend([
+            (AT_PHDR, phdr),
+            (AT_PHENT, exec_info.phent),
+            (AT_PHNUM, exec_info.phnum),
+        ]);
+    }
+    auxv.push((AT_NULL, 0));
+
+    // Lay out the vector
+    let mut words = vec![argv.len()];
+    words.extend(argv);
+    words.push(0);
+    words.extend(envp);
+    words.push(0);
+    words.extend(auxv.into_iter().flat_map(|(key, value)| [key, value]));
+
+    let len = words.len() * 8;
+    if sp - stack_bottom < len + 16 {
+        return Err(OsError::ArgumentTooLong);
+    }
+    sp = round_down(sp - len, 16);
+    unsafe {
+        core::ptr::copy(words.as_ptr(), sp as *mut usize, words.len());
     }
-
-    // Push a dummy return address
-    sp = push_stack(sp, 0);
 
     #[cfg(feature = "debug")]
     kprintln!(
@@ -192,9 +581,5 @@ fn init_user_stack(
         stack_page_begin
     );
 
-    Ok((
-        init_sp - (stack_top as usize - sp),
-        stack_pa.value(),
-        stack_page_begin,
-    ))
+    Ok((user(sp), stack_pa.value(), stack_page_begin))
 }
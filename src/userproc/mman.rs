@@ -0,0 +1,588 @@
+//! Memory of a user process besides its executable and stacks.
+//!
+//! The heap starts right after the last segment of the executable and moves
+//! with [`sbrk`]. Mappings, made with [`map`], go at an address the caller
+//! picks or, by default, the kernel does. The heap and anonymous mappings are
+//! demand-zero: a page gets a zeroed frame the first time it is touched. File
+//! mappings are read in page by page the same way.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::File;
+use crate::io::{Seek, SeekFrom, Write};
+use crate::mem::frametable::ksm::Ksm;
+use crate::mem::frametable::FrameTable;
+use crate::mem::suppagetable::{SupPageEntry, SupPageTable};
+use crate::mem::swaptable::SwapTable;
+use crate::mem::userbuf::write_user_doubleword;
+use crate::mem::vmstats::{Pages, VmStats};
+use crate::mem::{PTEFlags, PageAlign, PageTable, PhysAddr, PG_SIZE};
+use crate::thread;
+use crate::userproc::fileop::mmaptable::{self, Mapping};
+use crate::userproc::{fileop, uthread};
+use crate::{OsError, Result};
+
+/* Protections of a mapping */
+pub const PROT_NONE: usize = 0;
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+
+/* Flags of a mapping */
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+/// Map at exactly the given address.
+pub const MAP_FIXED: usize = 0x10;
+/// Not backed by a file.
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/* Flags of msync */
+pub const MS_ASYNC: usize = 1;
+pub const MS_INVALIDATE: usize = 2;
+pub const MS_SYNC: usize = 4;
+
+/* Whose usage getrusage reports */
+pub const RUSAGE_SELF: isize = 0;
+
+/// Largest size of the heap.
+pub const HEAP_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Addresses the kernel picks for mappings are in `MMAP_BASE..MMAP_TOP`, the
+/// highest free ones first.
+const MMAP_BASE: usize = 0x5000_0000;
+const MMAP_TOP: usize = 0x7000_0000;
+
+/// The heap of a process, `start..brk`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Heap {
+    start: usize,
+    brk: usize,
+}
+
+impl Heap {
+    /// An empty heap starting at `start`.
+    pub fn new(start: usize) -> Self {
+        Self { start, brk: start }
+    }
+}
+
+/// Whether nothing is mapped at page `va`.
+fn is_free(pt: &PageTable, spt: &SupPageTable, va: usize) -> bool {
+    pt.get_leaf(va)
+        .map_or(true, |(pte, _)| pte.flag().is_empty())
+        && spt.query(va).is_none()
+}
+
+/// Maps `start..end` as demand-zero pages with `flag`, unless some page in
+/// it is taken already.
+fn map_zeroed(start: usize, end: usize, flag: PTEFlags) -> Result<()> {
+    let current = thread::current();
+    let mut pt = current.pagetable.as_ref().unwrap().lock();
+    let spt = current.suppt.as_ref().unwrap();
+    if !(start..end)
+        .step_by(PG_SIZE)
+        .all(|va| is_free(&pt, spt, va))
+    {
+        return Err(OsError::InvalidArgument);
+    }
+    for va in (start..end).step_by(PG_SIZE) {
+        pt.map(PhysAddr::from_pa(0), va, 1, flag);
+        spt.map_zeroed(va);
+    }
+    Ok(())
+}
+
+/// Unmaps the pages in `start..end`, wherever they are.
+fn unmap_pages(start: usize, end: usize) {
+    let current = thread::current();
+    let spt = current.suppt.as_ref().unwrap();
+    for va in (start..end).step_by(PG_SIZE) {
+        if let Some(SupPageEntry::InSwap(offset)) = spt.query(va) {
+            SwapTable::dealloc(offset);
+            VmStats::sub(&current, Pages::Swapped, 1);
+        }
+        spt.remove(va);
+        FrameTable::unmap(va);
+    }
+}
+
+/// Moves the end of the heap by `increment` bytes.
+///
+/// ## Return
+/// - `Ok(brk)`: The previous end of the heap, i.e. the start of the new memory.
+/// - `Err(OutOfMemory)`: The heap would outgrow [`HEAP_LIMIT`] or run into
+///   some other mapping.
+/// - `Err(InvalidArgument)`: The heap would shrink below its start.
+pub fn sbrk(increment: isize) -> Result<isize> {
+    let proc = super::current();
+    let mut heap = proc.heap.lock();
+    let old = heap.brk;
+    let new = old
+        .checked_add_signed(increment)
+        .filter(|&new| new >= heap.start)
+        .ok_or(OsError::InvalidArgument)?;
+    if new - heap.start > HEAP_LIMIT {
+        return Err(OsError::OutOfMemory);
+    }
+
+    if new > old {
+        map_zeroed(
+            old.ceil(),
+            new.ceil(),
+            PTEFlags::R | PTEFlags::W | PTEFlags::U,
+        )
+        .map_err(|_| OsError::OutOfMemory)?;
+    } else {
+        unmap_pages(new.ceil(), old.ceil());
+    }
+    heap.brk = new;
+    Ok(old as isize)
+}
+
+/// Page table flags for `prot`. Writable pages are readable too.
+///
+/// [`PROT_NONE`] pages are mapped readable but without `U`, so user mode can't
+/// touch them. A valid entry without any of R, W and X would point to a page
+/// table instead.
+fn prot_flags(prot: usize) -> Result<PTEFlags> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    if prot == PROT_NONE {
+        return Ok(PTEFlags::R);
+    }
+    let mut flag = PTEFlags::U;
+    if prot & (PROT_READ | PROT_WRITE) != 0 {
+        flag |= PTEFlags::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        flag |= PTEFlags::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        flag |= PTEFlags::X;
+    }
+    Ok(flag)
+}
+
+/// Whether `start..start + len` is free, and below the stacks.
+fn range_free(start: usize, len: usize) -> bool {
+    let current = thread::current();
+    let pt = current.pagetable.as_ref().unwrap().lock();
+    let spt = current.suppt.as_ref().unwrap();
+    start
+        .checked_add(len)
+        .map_or(false, |end| end <= uthread::stacks_bottom())
+        && (start..start + len)
+            .step_by(PG_SIZE)
+            .all(|va| is_free(&pt, spt, va))
+}
+
+/// The highest free range of `len` bytes for a mapping.
+fn find_free(len: usize) -> Option<usize> {
+    let current = thread::current();
+    let pt = current.pagetable.as_ref().unwrap().lock();
+    let spt = current.suppt.as_ref().unwrap();
+    let mut end = MMAP_TOP;
+    while end - MMAP_BASE >= len {
+        let start = end - len;
+        match (start..end)
+            .step_by(PG_SIZE)
+            .rev()
+            .find(|&va| !is_free(&pt, spt, va))
+        {
+            Some(taken) => end = taken,
+            None => return Some(start),
+        }
+    }
+    None
+}
+
+/// Maps `len` bytes of memory with protection `prot`.
+///
+/// Anonymous mappings must be private. File mappings take `len` bytes of the
+/// file `fd` from `offset`, which must be page aligned. Private ones are
+/// copies of the file, shared ones are written back to it by [`unmap`],
+/// [`msync`] and on exit. The mapping keeps the file open on its own.
+///
+/// `addr` is only a hint, unless `flags` has [`MAP_FIXED`]. Then the mapping
+/// goes exactly there, which must be free.
+///
+/// ## Return
+/// - `Ok(addr)`: Where the memory is mapped.
+/// - `Err`: On bad arguments, or if there is no room.
+pub fn map(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: isize,
+    offset: usize,
+) -> Result<isize> {
+    let sharing = flags & (MAP_SHARED | MAP_PRIVATE);
+    if flags & !(MAP_SHARED | MAP_PRIVATE | MAP_FIXED | MAP_ANONYMOUS) != 0
+        || (sharing != MAP_SHARED && sharing != MAP_PRIVATE)
+        || len == 0
+        || !offset.is_aligned()
+    {
+        return Err(OsError::InvalidArgument);
+    }
+    let flag = prot_flags(prot)?;
+    let len = len
+        .checked_add(PG_SIZE - 1)
+        .ok_or(OsError::InvalidArgument)?
+        & !(PG_SIZE - 1);
+
+    let file = if flags & MAP_ANONYMOUS != 0 {
+        if sharing == MAP_SHARED || offset != 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        None
+    } else {
+        let (file, mode) = thread::current()
+            .fdtable
+            .as_ref()
+            .unwrap()
+            .fd_to_file(fd)
+            .ok_or(OsError::FileNotOpened)?;
+        let writes = sharing == MAP_SHARED && prot & PROT_WRITE != 0;
+        if fileop::is_writeonly(mode) || (writes && fileop::is_readonly(mode)) {
+            return Err(OsError::InvalidFileMode);
+        }
+        let file = file.lock().clone();
+        Some((file, !fileop::is_readonly(mode)))
+    };
+
+    // Choosing the address and mapping it is atomic.
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let mut anon = mmaptable.anon.lock();
+
+    let hint = Some(addr).filter(|addr| addr.is_aligned() && *addr != 0);
+    let start = if flags & MAP_FIXED != 0 {
+        hint.filter(|&addr| range_free(addr, len))
+            .ok_or(OsError::InvalidArgument)?
+    } else {
+        hint.filter(|&addr| range_free(addr, len))
+            .or_else(|| find_free(len))
+            .ok_or(OsError::OutOfMemory)?
+    };
+
+    match file {
+        None => {
+            map_zeroed(start, start + len, flag)?;
+            anon.extend((start..start + len).step_by(PG_SIZE));
+        }
+        Some((file, writable)) => {
+            let mapping = Mapping {
+                file,
+                start,
+                len,
+                offset,
+                shared: sharing == MAP_SHARED,
+                writable,
+            };
+            map_file(&mut mmaptable.files.lock(), mapping, flag)?;
+        }
+    }
+    Ok(start as isize)
+}
+
+/// Maps the whole of `file` at `start` with `flag`, shared.
+///
+/// ## Return
+/// - `Ok(mapid)`
+/// - `Err`: If the file is empty, or `start` is misaligned or not free.
+pub fn map_whole_file(start: usize, file: File, flag: PTEFlags) -> Result<isize> {
+    let len = file.len()?.ceil();
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let _anon = mmaptable.anon.lock();
+    if len == 0 || !start.is_aligned() || !range_free(start, len) {
+        return Err(OsError::InvalidArgument);
+    }
+    let mapping = Mapping {
+        file,
+        start,
+        len,
+        offset: 0,
+        shared: true,
+        writable: true,
+    };
+    let mut files = mmaptable.files.lock();
+    map_file(&mut files, mapping, flag)
+}
+
+/// Maps the pages of `mapping`, which must be free, with `flag`, and adds it
+/// to `files`.
+///
+/// Shared mappings map the pages of the page cache, private ones get copies.
+fn map_file(
+    files: &mut BTreeMap<isize, Mapping>,
+    mapping: Mapping,
+    flag: PTEFlags,
+) -> Result<isize> {
+    let current = thread::current();
+    let mut pt = current.pagetable.as_ref().unwrap().lock();
+    let spt = current.suppt.as_ref().unwrap();
+    let file_len = mapping.file.len()?;
+    for va in (mapping.start..mapping.end()).step_by(PG_SIZE) {
+        let offset = mapping.offset + (va - mapping.start);
+        if mapping.shared {
+            pt.map(PhysAddr::from_pa(0), va, 1, flag | PTEFlags::SHARED);
+            spt.map_in_file_mapped(va, mapping.file.clone(), offset);
+        } else {
+            // Past the end of the file, pages are zeroed.
+            let len = file_len.saturating_sub(offset).min(PG_SIZE);
+            pt.map(PhysAddr::from_pa(0), va, 1, flag);
+            spt.map_in_flie_lazy_load(va, mapping.file.clone(), offset, len);
+        }
+    }
+    Ok(mmaptable::alloc_mapid(files, mapping))
+}
+
+/// Writes the dirty pages of `mapping` in `start..end` back to its file, if it
+/// is shared. Only the part of a page inside the file is written.
+fn write_back(mapping: &Mapping, start: usize, end: usize) -> Result<()> {
+    if !mapping.shared {
+        return Ok(());
+    }
+
+    let current = thread::current();
+    let mut file = mapping.file.clone();
+    let file_len = file.len()?;
+    for va in (start..end).step_by(PG_SIZE) {
+        let offset = mapping.offset + (va - mapping.start);
+        let len = file_len.saturating_sub(offset).min(PG_SIZE);
+        if len == 0 {
+            break;
+        }
+        // Allocated before the page table is locked, as the allocation may
+        // evict pages.
+        let mut buf = vec![0u8; len];
+        {
+            // The page is cleaned, so writes from now on dirty it again. It
+            // is copied from its frame, which stays mapped while the page
+            // table is locked. An evicted page was written back already.
+            let mut pt = current.pagetable.as_ref().unwrap().lock();
+            let pte = match pt.get_pte(va) {
+                Some(pte) if pte.is_valid() && pte.is_dirty() => *pte,
+                _ => continue,
+            };
+            pt.map(pte.pa(), va, 1, pte.flag() - PTEFlags::D);
+            PageTable::flush_tlb();
+            buf.copy_from_slice(unsafe {
+                core::slice::from_raw_parts(pte.pa().into_va() as *const u8, len)
+            });
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        file.write(&buf)?;
+    }
+    Ok(())
+}
+
+/// Writes back and unmaps the part `start..end` of `mapping`.
+fn unmap_file(mapping: &Mapping, start: usize, end: usize) {
+    if let Err(err) = write_back(mapping, start, end) {
+        kprintln!("Failed to write back {:?}: {:?}", mapping.file, err);
+    }
+    unmap_pages(start, end);
+}
+
+/// Unmaps the pages of mappings in `addr..addr + len`. Mappings partly in
+/// it are cut.
+pub fn unmap(addr: usize, len: usize) -> Result<isize> {
+    if !addr.is_aligned() || len == 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    let end = addr.saturating_add(len.ceil());
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let mut anon = mmaptable.anon.lock();
+    for va in (addr..end).step_by(PG_SIZE) {
+        if anon.remove(&va) {
+            unmap_pages(va, va + PG_SIZE);
+        }
+    }
+
+    let mut files = mmaptable.files.lock();
+    let cut = files
+        .iter()
+        .filter(|(_, mapping)| mapping.start < end && addr < mapping.end())
+        .map(|(mapid, _)| *mapid)
+        .collect::<Vec<_>>();
+    for mapid in cut {
+        let mapping = files.remove(&mapid).unwrap();
+        let (start, stop) = (addr.max(mapping.start), end.min(mapping.end()));
+        unmap_file(&mapping, start, stop);
+
+        if mapping.start < start {
+            let len = start - mapping.start;
+            files.insert(
+                mapid,
+                Mapping {
+                    len,
+                    ..mapping.clone()
+                },
+            );
+        }
+        if stop < mapping.end() {
+            let rest = Mapping {
+                start: stop,
+                len: mapping.end() - stop,
+                offset: mapping.offset + (stop - mapping.start),
+                ..mapping
+            };
+            mmaptable::alloc_mapid(&mut files, rest);
+        }
+    }
+    Ok(0)
+}
+
+/// Unmaps the file mapping `mapid`, see [`fileop::mmap`].
+pub fn unmap_file_by_id(mapid: isize) -> Result<isize> {
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let _anon = mmaptable.anon.lock();
+    let mapping = mmaptable
+        .files
+        .lock()
+        .remove(&mapid)
+        .ok_or(OsError::BadMapid)?;
+    unmap_file(&mapping, mapping.start, mapping.end());
+    Ok(0)
+}
+
+/// Writes the dirty pages of shared mappings in `addr..addr + len` back to
+/// their files. Writes are always synchronous, so [`MS_ASYNC`] and
+/// [`MS_SYNC`] do the same. [`MS_INVALIDATE`] does nothing, as shared
+/// mappings map the page cache itself.
+pub fn msync(addr: usize, len: usize, flags: usize) -> Result<isize> {
+    if !addr.is_aligned()
+        || flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+    {
+        return Err(OsError::InvalidArgument);
+    }
+    let end = addr.checked_add(len).ok_or(OsError::OutOfMemory)?.ceil();
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let files = mmaptable.files.lock();
+    for mapping in files.values() {
+        if mapping.start < end && addr < mapping.end() {
+            write_back(mapping, addr.max(mapping.start), end.min(mapping.end()))?;
+        }
+    }
+    Ok(0)
+}
+
+/// Writes back all shared mappings, when the process exits.
+pub fn sync_all() {
+    let mmaptable = thread::current().mmaptable.clone().unwrap();
+    let files = mmaptable.files.lock();
+    for mapping in files.values() {
+        if let Err(err) = write_back(mapping, mapping.start, mapping.end()) {
+            kprintln!("Failed to write back {:?}: {:?}", mapping.file, err);
+        }
+    }
+}
+
+/// Sets the protection of the pages in `addr..addr + len` to `prot`.
+///
+/// Permissions live in the page table entries even while pages are not
+/// resident, so the supplementary page table needs no update.
+///
+/// ## Return
+/// - `Ok(0)`: On success.
+/// - `Err(OutOfMemory)`: Some page in the range is not mapped.
+/// - `Err(InvalidFileMode)`: A shared mapping of a read-only file would become
+///   writable.
+/// - `Err(InvalidArgument)`: On bad arguments.
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> Result<isize> {
+    if !addr.is_aligned() || len == 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    let flag = prot_flags(prot)?;
+    let end = addr
+        .checked_add(len)
+        .filter(|&end| end <= uthread::stacks_bottom())
+        .ok_or(OsError::OutOfMemory)?
+        .ceil();
+
+    let current = thread::current();
+    let mapped = {
+        let pt = current.pagetable.as_ref().unwrap().lock();
+        (addr..end).step_by(PG_SIZE).all(|va| {
+            pt.get_pte(va)
+                .map_or(false, |pte| !pte.flag().is_empty() && !pte.is_global())
+        })
+    };
+    if !mapped {
+        return Err(OsError::OutOfMemory);
+    }
+
+    // Shared mappings write to the file.
+    let mmaptable = current.mmaptable.clone().unwrap();
+    let files = mmaptable.files.lock();
+    if flag.contains(PTEFlags::W)
+        && files.values().any(|mapping| {
+            mapping.shared && !mapping.writable && mapping.start < end && addr < mapping.end()
+        })
+    {
+        return Err(OsError::InvalidFileMode);
+    }
+
+    for va in (addr..end).step_by(PG_SIZE) {
+        FrameTable::protect(va, flag);
+    }
+    PageTable::flush_tlb();
+    Ok(0)
+}
+
+/// Writes the memory usage of the current process to `usage`, a
+/// `struct rusage` in user space: resident, swapped and file-mapped pages,
+/// the peak of resident pages, minor and major page faults, then pages evicted
+/// and read back from swap, as 64-bit numbers. Only [`RUSAGE_SELF`] is
+/// supported as `who`.
+pub fn getrusage(who: isize, usage: usize) -> Result<isize> {
+    if who != RUSAGE_SELF {
+        return Err(OsError::InvalidArgument);
+    }
+    let proc = super::current();
+    let stats = &proc.vmstats;
+    let fields = [
+        stats.resident(),
+        stats.swapped(),
+        stats.file_mapped(),
+        stats.peak_resident(),
+        stats.minor_faults(),
+        stats.major_faults(),
+        stats.evictions(),
+        stats.swap_ins(),
+    ];
+    for (i, &field) in fields.iter().enumerate() {
+        write_user_doubleword(usage + i * 8, field as u64)?;
+    }
+    Ok(0)
+}
+
+/// Sets the ticks between scans of same-page merging to `interval`, unless it
+/// is negative, see [`Ksm`]. 0 stops merging. Then writes its statistics to
+/// `stat`, a `struct ksmstat` in user space, if not null: pages merged,
+/// passes, shared frames, frames saved and the most ever saved, as 64-bit
+/// numbers.
+pub fn ksm(interval: isize, stat: usize) -> Result<isize> {
+    if interval >= 0 {
+        Ksm::set_interval(interval as usize);
+    }
+    if stat != 0 {
+        let stats = Ksm::stats();
+        let fields = [
+            stats.merged,
+            stats.passes,
+            stats.shared,
+            stats.saved,
+            stats.peak_saved,
+        ];
+        for (i, &field) in fields.iter().enumerate() {
+            write_user_doubleword(stat + i * 8, field as u64)?;
+        }
+    }
+    Ok(0)
+}
@@ -0,0 +1,78 @@
+//! Out-of-memory killer.
+//!
+//! When no frame can be freed, neither from the pool, the page cache nor by
+//! eviction, a process is killed to make room: the one holding the most pages,
+//! resident or in swap. Its frames and swap slots are released as it exits, after
+//! which the allocation is tried again.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use crate::thread;
+use crate::{OsError, Result};
+
+use super::{processes, signal, UserProc};
+
+/// Ticks to wait for a process being killed to exit.
+const TIMEOUT: i64 = 100;
+
+/// Kills a process to free memory, unless one is exiting already, and waits
+/// for its pages to be released.
+///
+/// ## Return
+/// - `Ok(())`: A process released its pages, the allocation may be retried.
+/// - `Err(OutOfMemory)`: The current process is the one killed, nothing is
+///   left to kill, or the process did not exit in time.
+pub fn kill_victim() -> Result<()> {
+    let current = thread::current().userproc.clone();
+    let is_current = |proc: &Arc<UserProc>| current.as_ref().is_some_and(|c| Arc::ptr_eq(c, proc));
+
+    let live = processes()
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .filter(|proc| proc.vmstats.footprint() > 0)
+        .collect::<Vec<_>>();
+
+    // A process killed before, or exiting on its own, frees memory soon.
+    let exiting = live
+        .iter()
+        .find(|proc| !is_current(proc) && proc.threads.lock().exiting());
+    let victim = match exiting {
+        Some(proc) => proc.clone(),
+        None => {
+            let victim = live
+                .into_iter()
+                .filter(|proc| !proc.threads.lock().exiting())
+                .max_by_key(|proc| proc.vmstats.footprint())
+                .ok_or(OsError::OutOfMemory)?;
+            kprintln!(
+                "Out of memory: killed process {} ({} pages resident, {} in swap).",
+                victim.pid,
+                victim.vmstats.resident(),
+                victim.vmstats.swapped(),
+            );
+            kill(&victim);
+            victim
+        }
+    };
+
+    if is_current(&victim) {
+        return Err(OsError::OutOfMemory);
+    }
+    for _ in 0..TIMEOUT {
+        if victim.vmstats.footprint() == 0 {
+            return Ok(());
+        }
+        thread::sleep(1);
+    }
+    Err(OsError::OutOfMemory)
+}
+
+/// Makes all threads of `proc` exit, as if by an uncaught `SIGKILL`.
+fn kill(proc: &UserProc) {
+    let mut threads = proc.threads.lock();
+    threads.set_killed(signal::SIGKILL);
+    // Threads blocked in the kernel won't return to user mode otherwise.
+    threads.interrupt_all();
+}
@@ -0,0 +1,345 @@
+//! POSIX-style signals.
+//!
+//! Every process has a set of pending signals, a mask of blocked ones and an
+//! action for each signal. [`kill`] marks a signal pending in its target, which
+//! acts on it right before it returns to user mode, see [`deliver`]:
+//!
+//! - `SIG_IGN` discards the signal.
+//! - `SIG_DFL` terminates the process, except for signals ignored by default.
+//! - A user handler is entered with the interrupted context saved in a
+//!   [`SigFrame`] on the user stack. The handler returns to the `sa_restorer`
+//!   of its [`SigAction`], which calls `sigreturn` to resume that context.
+//!
+//! A signal that terminates its target interrupts the threads of the target
+//! blocked in the kernel, e.g. in `wait` or reading a pipe, see
+//! [`thread::interrupt`]. Their syscalls fail, and the signal is acted on
+//! right after. Other signals wait for the blocking syscall to return.
+
+use core::mem::size_of;
+
+use crate::mem::userbuf::{read_user_doubleword, write_user_doubleword};
+use crate::thread;
+use crate::trap::{stackgrowth, Frame};
+use crate::{OsError, Result};
+
+use super::UserProc;
+
+/// Number of signals, including the null signal 0.
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+
+/// Exit status of a process killed by a signal, including the signals taken
+/// on faults.
+pub const KILLED: isize = -1;
+
+/// Take the default action.
+pub const SIG_DFL: usize = 0;
+/// Ignore the signal.
+pub const SIG_IGN: usize = 1;
+
+/// `how` of [`sigprocmask`].
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// A set of signals, bit `n` standing for signal `n`.
+pub type SigSet = u64;
+
+/// Signals that can be neither caught, ignored nor blocked.
+const UNCATCHABLE: SigSet = 1 << SIGKILL;
+
+/// Signals whose default action is to do nothing.
+const IGNORED_BY_DEFAULT: SigSet = 1 << SIGCHLD;
+
+fn sigbit(sig: usize) -> SigSet {
+    1 << sig
+}
+
+/// What to do with a signal, `struct sigaction` in user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+    /// [`SIG_DFL`], [`SIG_IGN`] or the address of a handler.
+    pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself.
+    pub mask: SigSet,
+    /// Where the handler returns to.
+    pub restorer: usize,
+}
+
+impl SigAction {
+    fn read(ptr: usize) -> Result<Self> {
+        Ok(Self {
+            handler: read_user_doubleword(ptr)? as usize,
+            mask: read_user_doubleword(ptr + 8)?,
+            restorer: read_user_doubleword(ptr + 16)? as usize,
+        })
+    }
+
+    fn write(&self, ptr: usize) -> Result<()> {
+        write_user_doubleword(ptr, self.handler as u64)?;
+        write_user_doubleword(ptr + 8, self.mask)?;
+        write_user_doubleword(ptr + 16, self.restorer as u64)
+    }
+}
+
+/// Signal state of a process.
+#[derive(Clone, Default)]
+pub struct Signals {
+    pending: SigSet,
+    blocked: SigSet,
+    actions: [SigAction; NSIG],
+}
+
+impl Signals {
+    /// Whether `sig` terminates the process as soon as it is acted on.
+    fn is_fatal(&self, sig: usize) -> bool {
+        let bit = sigbit(sig);
+        UNCATCHABLE & bit != 0
+            || (self.blocked & bit == 0
+                && self.actions[sig].handler == SIG_DFL
+                && IGNORED_BY_DEFAULT & bit == 0)
+    }
+
+    /// The state of a forked child: same actions and mask, nothing pending.
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// The context interrupted by a signal handler, saved on the user stack.
+#[repr(C)]
+struct SigFrame {
+    x: [usize; 32],
+    sepc: usize,
+    blocked: SigSet,
+}
+
+impl SigFrame {
+    const WORDS: usize = size_of::<Self>() / 8;
+
+    fn read(ptr: usize) -> Result<Self> {
+        let mut words = [0; Self::WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = read_user_doubleword(ptr + i * 8)? as usize;
+        }
+        Ok(unsafe { core::mem::transmute(words) })
+    }
+
+    fn write(&self, ptr: usize) -> Result<()> {
+        let words: &[usize; Self::WORDS] = unsafe { &*(self as *const Self as *const _) };
+        for (i, word) in words.iter().enumerate() {
+            write_user_doubleword(ptr + i * 8, *word as u64)?;
+        }
+        Ok(())
+    }
+}
+
+fn check(sig: usize) -> Result<()> {
+    if sig == 0 || sig >= NSIG {
+        Err(OsError::InvalidArgument)
+    } else {
+        Ok(())
+    }
+}
+
+/// Exits the current process as killed by `sig`, with [`KILLED`] as its exit
+/// status. Its parent may tell `sig` by [`termsig`](super::termsig).
+pub fn exit_killed(sig: usize) -> ! {
+    super::current().threads.lock().set_killed(sig);
+    super::exit(KILLED)
+}
+
+/// Makes `sig` pending in `proc`, interrupting its threads if it is fatal.
+pub fn send(proc: &UserProc, sig: usize) {
+    let fatal = {
+        let mut signals = proc.signals.lock();
+        if signals.actions[sig].handler != SIG_IGN {
+            signals.pending |= sigbit(sig);
+        }
+        signals.is_fatal(sig)
+    };
+    if fatal {
+        proc.threads.lock().interrupt_all();
+    }
+}
+
+/// Sends `sig` to the process `pid`. The null signal only checks that it exists.
+pub fn kill(pid: isize, sig: usize) -> Result<isize> {
+    if sig >= NSIG {
+        return Err(OsError::InvalidArgument);
+    }
+    let proc = super::find(pid).ok_or(OsError::NoSuchProcess)?;
+    if sig != 0 {
+        send(&proc, sig);
+    }
+    Ok(0)
+}
+
+/// Makes the current process take `sig`, which it cannot block or ignore.
+///
+/// Used for faults, where resuming without running a handler would fault again.
+pub fn force(sig: usize) {
+    let proc = super::current();
+    let mut signals = proc.signals.lock();
+    if signals.blocked & sigbit(sig) != 0 || signals.actions[sig].handler == SIG_IGN {
+        signals.actions[sig].handler = SIG_DFL;
+    }
+    signals.blocked &= !sigbit(sig);
+    signals.pending |= sigbit(sig);
+}
+
+/// Examines and changes the action of `sig`, through user pointers `act` and
+/// `oldact`, either of which may be null.
+pub fn sigaction(sig: usize, act: usize, oldact: usize) -> Result<isize> {
+    check(sig)?;
+    let act = match act {
+        0 => None,
+        _ if UNCATCHABLE & sigbit(sig) != 0 => return Err(OsError::InvalidArgument),
+        ptr => Some(SigAction::read(ptr)?),
+    };
+
+    let proc = super::current();
+    let old = proc.signals.lock().actions[sig];
+    if oldact != 0 {
+        old.write(oldact)?;
+    }
+
+    if let Some(act) = act {
+        let mut signals = proc.signals.lock();
+        signals.actions[sig] = act;
+        if act.handler == SIG_IGN {
+            signals.pending &= !sigbit(sig);
+        }
+    }
+    Ok(0)
+}
+
+/// Examines and changes the blocked signals, through user pointers `set` and
+/// `oldset`, either of which may be null.
+pub fn sigprocmask(how: usize, set: usize, oldset: usize) -> Result<isize> {
+    let set = match set {
+        0 => None,
+        ptr => Some(read_user_doubleword(ptr)?),
+    };
+
+    let proc = super::current();
+    let old = proc.signals.lock().blocked;
+    if oldset != 0 {
+        write_user_doubleword(oldset, old)?;
+    }
+
+    if let Some(set) = set {
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return Err(OsError::InvalidArgument),
+        };
+        proc.signals.lock().blocked = blocked & !UNCATCHABLE;
+    }
+    Ok(0)
+}
+
+/// Resumes the context saved by [`deliver`], when a handler returns.
+///
+/// The return value is the restored `a0`, so that the syscall leaves it untouched.
+pub fn sigreturn(frame: &mut Frame) -> isize {
+    let saved = match SigFrame::read(frame.x[2]) {
+        Ok(saved) => saved,
+        Err(_) => {
+            force(SIGSEGV);
+            return -1;
+        }
+    };
+
+    frame.x[1..].copy_from_slice(&saved.x[1..]);
+    frame.sepc = saved.sepc;
+    super::current().signals.lock().blocked = saved.blocked & !UNCATCHABLE;
+    frame.x[10] as isize
+}
+
+/// Acts on the pending signals of the current process, which is about to
+/// return to user mode with context `frame`.
+///
+/// At most one handler is entered at a time. Signals still pending are acted
+/// on at the next return to user mode, which may be right after the handler
+/// calls `sigreturn`.
+pub fn deliver(frame: &mut Frame) {
+    let proc = match thread::current().userproc.clone() {
+        Some(proc) => proc,
+        None => return,
+    };
+    // Whatever interrupted the current thread is acted on below, if it still
+    // has to be.
+    thread::current().set_interrupted(false);
+
+    loop {
+        let (sig, action, blocked) = {
+            let mut signals = proc.signals.lock();
+            let ready = signals.pending & !signals.blocked;
+            if ready == 0 {
+                return;
+            }
+            let sig = ready.trailing_zeros() as usize;
+            signals.pending &= !sigbit(sig);
+            (sig, signals.actions[sig], signals.blocked)
+        };
+
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL if IGNORED_BY_DEFAULT & sigbit(sig) != 0 => continue,
+            SIG_DFL => {
+                #[cfg(feature = "debug")]
+                kprintln!(
+                    "[SIGNAL] {:?} terminated by signal {}",
+                    thread::current(),
+                    sig
+                );
+                drop(proc);
+                exit_killed(sig);
+            }
+            handler => {
+                let saved = SigFrame {
+                    x: frame.x,
+                    sepc: frame.sepc,
+                    blocked,
+                };
+                let sp = (frame.x[2] - size_of::<SigFrame>()) & !0xf;
+                // The stack may need to grow. If it cannot, writing the frame fails.
+                let _ = stackgrowth::extend_stack_to_sp(sp);
+                if saved.write(sp).is_err() {
+                    #[cfg(feature = "debug")]
+                    kprintln!("[SIGNAL] bad stack for signal {}", sig);
+                    drop(proc);
+                    exit_killed(SIGSEGV);
+                }
+
+                proc.signals.lock().blocked |= (action.mask | sigbit(sig)) & !UNCATCHABLE;
+                frame.x[1] = action.restorer;
+                frame.x[2] = sp;
+                frame.x[10] = sig;
+                frame.sepc = handler;
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Threads of a user process.
+//!
+//! A process starts with a single thread, on the main stack. More threads are
+//! created with [`create`]. Each one is a kernel thread sharing the process's
+//! address space, file descriptors and mappings (see
+//! [`Builder::process_of`](thread::Builder::process_of)), and running on its
+//! own user stack.
+//!
+//! Thread stacks are fixed-size slots right below the area reserved for the
+//! main stack, with an unmapped guard page below each. A slot is mapped lazily
+//! the first time it is used, and reused by later threads once its thread has
+//! exited.
+//!
+//! A thread ends with `thread_exit`, passing a value to [`join`]. The process
+//! ends once its last thread is gone, or as soon as any thread calls `exit`:
+//! the other threads then terminate at their next return to user mode. Those
+//! blocked in the kernel are interrupted, see [`Threads::interrupt_all`].
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
+use crate::mem::userbuf;
+use crate::mem::{PTEFlags, PhysAddr, PG_SIZE};
+use crate::sync::{Mutex, Semaphore};
+use crate::thread::{self, Thread, STACK_TOP};
+use crate::trap::stackgrowth::STACK_LIMIT;
+use crate::trap::Frame;
+use crate::{OsError, Result};
+
+use super::signal;
+
+/// Pages in the stack of a thread other than the main one.
+pub const THREAD_STACK_PAGES: usize = 16;
+
+/// Maximum number of threads besides the main one, running at the same time.
+pub const MAX_THREADS: usize = 64;
+
+/// Size of a stack slot, including its guard page.
+const SLOT_SIZE: usize = (THREAD_STACK_PAGES + 1) * PG_SIZE;
+
+/// Top of the stack in `slot`.
+fn stack_top(slot: usize) -> usize {
+    STACK_TOP - STACK_LIMIT - slot * SLOT_SIZE
+}
+
+/// Lowest address of the thread stacks. Nothing but stacks goes above it.
+pub fn stacks_bottom() -> usize {
+    stack_top(MAX_THREADS)
+}
+
+/// Whether `sp` points into the stack of a thread other than the main one.
+pub fn on_thread_stack(sp: usize) -> bool {
+    (stacks_bottom()..stack_top(0)).contains(&sp)
+}
+
+/// A thread of a user process, until it is joined.
+struct UThread {
+    thread: Weak<Thread>,
+    /// Stack slot, `None` if it runs on the main stack.
+    stack: Option<usize>,
+    /// Set by `thread_exit`.
+    value: Mutex<Option<usize>>,
+    exited: Semaphore,
+    /// Whether someone is joining it already.
+    joining: AtomicBool,
+}
+
+/// All threads of a user process.
+#[derive(Default)]
+pub struct Threads {
+    /// Threads not joined yet, running or exited, by tid.
+    all: BTreeMap<isize, Arc<UThread>>,
+    /// Number of threads still running.
+    live: usize,
+    /// Stack slots in use, one bit each.
+    used: u64,
+    /// Stack slots below this one are mapped already.
+    mapped: usize,
+    /// Exit status of the process, once some thread has called `exit`.
+    exiting: Option<isize>,
+    /// The signal that killed the process, 0 if none did.
+    killed_by: usize,
+}
+
+impl Threads {
+    /// The threads of a process forked by thread `tid`. Only that thread is
+    /// duplicated, keeping its stack.
+    pub fn fork(&self, tid: isize) -> (Self, Option<usize>) {
+        let stack = self.all.get(&tid).and_then(|t| t.stack);
+        let threads = Self {
+            used: stack.map_or(0, |slot| 1 << slot),
+            mapped: self.mapped,
+            ..Self::default()
+        };
+        (threads, stack)
+    }
+
+    /// Registers `thread`, running on stack `slot`.
+    pub fn add(&mut self, thread: &Arc<Thread>, stack: Option<usize>) {
+        self.all.insert(
+            thread.id(),
+            Arc::new(UThread {
+                thread: Arc::downgrade(thread),
+                stack,
+                value: Mutex::new(None),
+                exited: Semaphore::new(0),
+                joining: AtomicBool::new(false),
+            }),
+        );
+        self.live += 1;
+    }
+
+    /// Records that thread `tid` exited with `value`.
+    ///
+    /// Returns the exit status of the process, if this was its last thread.
+    pub fn exit(&mut self, tid: isize, value: usize) -> Option<isize> {
+        if let Some(t) = self.all.get(&tid) {
+            *t.value.lock() = Some(value);
+            t.exited.up();
+            if let Some(slot) = t.stack {
+                self.used &= !(1 << slot);
+            }
+        }
+        self.live -= 1;
+        (self.live == 0).then(|| self.exiting.unwrap_or(0))
+    }
+
+    /// Makes the process exit with `status`, unless it is exiting already.
+    pub fn set_exiting(&mut self, status: isize) {
+        self.exiting.get_or_insert(status);
+    }
+
+    /// Makes the process exit as killed by `sig`, unless it is exiting already.
+    pub fn set_killed(&mut self, sig: usize) {
+        if self.exiting.is_none() {
+            self.killed_by = sig;
+        }
+        self.set_exiting(signal::KILLED);
+    }
+
+    /// The signal that killed the process, 0 if none did.
+    pub fn killed_by(&self) -> usize {
+        self.killed_by
+    }
+
+    pub fn exiting(&self) -> bool {
+        self.exiting.is_some()
+    }
+
+    /// Interrupts the threads still running, so that those blocked in the
+    /// kernel return to user mode, see [`thread::interrupt`].
+    pub fn interrupt_all(&self) {
+        self.all
+            .values()
+            .filter_map(|t| t.thread.upgrade())
+            .for_each(|t| thread::interrupt(&t));
+    }
+
+    /// Takes a free stack slot, mapping it if it is used for the first time.
+    fn alloc_stack(&mut self) -> Result<usize> {
+        let slot = (!self.used).trailing_zeros() as usize;
+        if slot >= MAX_THREADS {
+            return Err(OsError::UserError);
+        }
+
+        if slot >= self.mapped {
+            let current = thread::current();
+            let mut pt = current.pagetable.as_ref().unwrap().lock();
+            let spt = current.suppt.as_ref().unwrap();
+            let base = stack_top(slot) - THREAD_STACK_PAGES * PG_SIZE;
+            for va in (base..stack_top(slot)).step_by(PG_SIZE) {
+                pt.map(
+                    PhysAddr::from_pa(0),
+                    va,
+                    1,
+                    PTEFlags::R | PTEFlags::W | PTEFlags::U,
+                );
+                spt.map_zeroed(va);
+            }
+            self.mapped = slot + 1;
+        }
+
+        self.used |= 1 << slot;
+        Ok(slot)
+    }
+
+    /// Gives back stack `slot`, of a thread that could not be created.
+    fn free_stack(&mut self, slot: usize) {
+        self.used &= !(1 << slot);
+    }
+}
+
+/// Creates a thread in the current process, entering `entry` with `arg` in `a0`
+/// and `ret` as its return address. `frame` is the context of the caller.
+///
+/// ## Return
+/// - `Ok(tid)`: Tid of the new thread.
+/// - `Err`: if there are too many threads, or no memory for another one.
+pub fn create(frame: &Frame, entry: usize, arg: usize, ret: usize) -> Result<isize> {
+    let current = thread::current();
+    let proc = current.userproc.clone().unwrap();
+    let slot = proc.threads.lock().alloc_stack()?;
+
+    let mut new = *frame;
+    new.x.fill(0);
+    new.x[1] = ret;
+    new.x[2] = stack_top(slot);
+    new.x[3] = frame.x[3]; // gp
+    new.x[10] = arg;
+    new.sepc = entry;
+
+    let child = super::spawn(
+        new,
+        Some(slot),
+        |builder| builder.priority(current.priority()).process_of(&current),
+        |_| {},
+    )
+    .map_err(|err| {
+        proc.threads.lock().free_stack(slot);
+        err
+    })?;
+    Ok(child.id())
+}
+
+/// Waits for thread `tid` of the current process to exit, and writes the
+/// value it passed to `thread_exit` to user pointer `value`, unless it is null.
+///
+/// ## Return
+/// - `Ok(0)`
+/// - `Err`: if `tid` is not a thread of the current process, is the current
+///   thread, or is being joined already.
+pub fn join(tid: isize, value: usize) -> Result<isize> {
+    let proc = super::current();
+    if tid == thread::current().id() {
+        return Err(OsError::UserError);
+    }
+    let t = proc
+        .threads
+        .lock()
+        .all
+        .get(&tid)
+        .cloned()
+        .ok_or(OsError::UserError)?;
+    if t.joining.swap(true, SeqCst) {
+        return Err(OsError::UserError);
+    }
+
+    if let Err(err) = t.exited.down_interruptible() {
+        t.joining.store(false, SeqCst);
+        return Err(err);
+    }
+    proc.threads.lock().all.remove(&tid);
+    let exit_value = t.value.lock().unwrap_or(0);
+    if value != 0 {
+        userbuf::write_user_doubleword(value, exit_value as u64)?;
+    }
+    Ok(0)
+}
@@ -1,7 +1,9 @@
 mod fs;
 mod malloc;
+mod pagetable;
 mod sync;
 mod thread;
+mod tty;
 mod virtio;
 
 pub fn main() {
@@ -35,9 +37,15 @@ pub fn main() {
     #[cfg(feature = "test-mem-malloc")]
     malloc::main();
 
+    #[cfg(feature = "test-mem-pagetable")]
+    pagetable::main();
+
     #[cfg(feature = "test-fs-inmem")]
     fs::inmem::main();
 
     #[cfg(feature = "test-fs-disk")]
     fs::disk::main();
+
+    #[cfg(feature = "test-tty")]
+    tty::main();
 }
@@ -0,0 +1,77 @@
+use crate::mem::palloc::UserPool;
+use crate::mem::{
+    KernelPgTable, PTEFlags, PageSize, PageTable, PhysAddr, PG_SIZE, PM_BASE, VM_OFFSET,
+};
+
+const MEGA: usize = 2 << 20;
+const GIGA: usize = 1 << 30;
+
+/// Asserts that `va` is mapped to `pa` by a leaf of `size`.
+fn assert_leaf(pt: &PageTable, va: usize, pa: usize, size: PageSize) {
+    let (pte, leaf) = pt.get_leaf(va).expect("not mapped");
+    assert!(pte.is_valid());
+    assert_eq!(leaf, size);
+    assert_eq!(pte.pa().value() + va % size.bytes(), pa);
+}
+
+fn kernel_linear_map() {
+    // The user pool is high in RAM, which ends on a 2 MiB boundary.
+    let va = UserPool::range().end - PG_SIZE;
+    let (pte, size) = KernelPgTable::get().get_leaf(va).expect("not mapped");
+    assert_ne!(size, PageSize::Page);
+    assert_eq!(pte.pa().value() + va % size.bytes(), va - VM_OFFSET);
+}
+
+fn split() {
+    let mut pt = KernelPgTable::clone().unwrap();
+    let flag = PTEFlags::V | PTEFlags::R | PTEFlags::U;
+    let (va, pa) = (GIGA, PM_BASE);
+    let size = GIGA + MEGA + PG_SIZE;
+
+    pt.map_superpages(PhysAddr::from_pa(pa), va, size, flag);
+    assert_leaf(&pt, va, pa, PageSize::Giga);
+    assert_leaf(
+        &pt,
+        va + GIGA - PG_SIZE,
+        pa + GIGA - PG_SIZE,
+        PageSize::Giga,
+    );
+    assert_leaf(&pt, va + GIGA, pa + GIGA, PageSize::Mega);
+    assert_leaf(&pt, va + GIGA + MEGA, pa + GIGA + MEGA, PageSize::Page);
+    assert!(!pt.get_pte(va + size).unwrap().is_valid());
+
+    // Unmapping a page splits the gigapage, then the megapage around it.
+    let hole = va + 3 * MEGA + 5 * PG_SIZE;
+    pt.map(PhysAddr::from_pa(0), hole, PG_SIZE, PTEFlags::empty());
+    assert!(!pt.get_pte(hole).unwrap().is_valid());
+    assert_leaf(
+        &pt,
+        hole - PG_SIZE,
+        pa + (hole - PG_SIZE - va),
+        PageSize::Page,
+    );
+    assert_leaf(
+        &pt,
+        hole + PG_SIZE,
+        pa + (hole + PG_SIZE - va),
+        PageSize::Page,
+    );
+    assert_leaf(&pt, va, pa, PageSize::Mega);
+    assert_leaf(
+        &pt,
+        va + GIGA - PG_SIZE,
+        pa + GIGA - PG_SIZE,
+        PageSize::Mega,
+    );
+    assert_leaf(&pt, va + GIGA, pa + GIGA, PageSize::Mega);
+
+    // Unmapping everything with superpages frees the page tables below them.
+    pt.map_superpages(PhysAddr::from_pa(0), va, size, PTEFlags::empty());
+    assert!(pt.get_pte(hole).is_none());
+    unsafe { pt.destroy() };
+}
+
+pub fn main() {
+    kernel_linear_map();
+    split();
+}
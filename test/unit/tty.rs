@@ -0,0 +1,68 @@
+use alloc::vec::Vec;
+
+use crate::device::tty::{Tty, TtyMode};
+
+/// Types `s` on the console.
+fn type_in(s: &[u8]) {
+    s.iter().for_each(|&c| Tty::get().input(c));
+}
+
+/// What one read of the console returns.
+fn read() -> Vec<u8> {
+    let mut buf = [0u8; 16];
+    let cnt = Tty::get().read(&mut buf).unwrap();
+    buf[..cnt].to_vec()
+}
+
+fn canonical() {
+    Tty::get().set_mode(TtyMode::ICANON);
+
+    // Erase and kill edit the line, which is only read once it ends.
+    type_in(b"ab\x08c\n");
+    assert_eq!(read(), b"ac\n");
+    type_in(b"xy\x15z\r");
+    assert_eq!(read(), b"z\n");
+
+    // A read returns one line at most.
+    type_in(b"one\ntwo\n");
+    assert_eq!(read(), b"one\n");
+    assert_eq!(read(), b"two\n");
+
+    // `^D` ends the line without a newline, or is EOF on an empty line.
+    type_in(b"q\x04");
+    assert_eq!(read(), b"q");
+    type_in(b"\x04");
+    assert_eq!(read(), b"");
+}
+
+fn raw() {
+    Tty::get().set_mode(TtyMode::empty());
+    type_in(b"a\x08\x04\x15\n");
+    assert_eq!(read(), b"a\x08\x04\x15\n");
+
+    // Leaving canonical mode hands the line being edited to readers.
+    Tty::get().set_mode(TtyMode::ICANON);
+    type_in(b"part");
+    Tty::get().set_mode(TtyMode::empty());
+    assert_eq!(read(), b"part");
+}
+
+fn interrupt() {
+    // `^C` discards pending input.
+    Tty::get().set_mode(TtyMode::ICANON | TtyMode::ISIG);
+    type_in(b"lost\n");
+    type_in(b"lost\x03kept\n");
+    assert_eq!(read(), b"kept\n");
+
+    // Without `ISIG`, it is a byte like any other.
+    Tty::get().set_mode(TtyMode::empty());
+    type_in(b"\x03");
+    assert_eq!(read(), b"\x03");
+}
+
+pub fn main() {
+    canonical();
+    raw();
+    interrupt();
+    Tty::get().set_mode(TtyMode::default());
+}
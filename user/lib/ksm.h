@@ -0,0 +1,18 @@
+#ifndef __LIB_KSM_H
+#define __LIB_KSM_H
+
+#include "types.h"
+
+// ksm() interval that leaves it as it is
+#define KSM_KEEP -1
+
+// What same-page merging did, sizes in pages
+struct ksmstat {
+    uint64 ks_merged;     // Pages merged into another one
+    uint64 ks_passes;     // Passes over all frames
+    uint64 ks_shared;     // Frames pages were merged into, mapped more than once now
+    uint64 ks_saved;      // Frames saved now
+    uint64 ks_peaksaved;  // Most frames saved at once
+};
+
+#endif
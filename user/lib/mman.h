@@ -0,0 +1,24 @@
+#ifndef __LIB_MMAN_H
+#define __LIB_MMAN_H
+
+// map() protections
+#define PROT_NONE 0   // Not accessible
+#define PROT_READ 1   // Readable
+#define PROT_WRITE 2  // Writable, and readable
+#define PROT_EXEC 4   // Executable
+
+// map() flags
+#define MAP_SHARED 0x01     // Changes are written back to the file
+#define MAP_PRIVATE 0x02    // Changes are private
+#define MAP_FIXED 0x10      // Map at exactly addr
+#define MAP_ANONYMOUS 0x20  // Zero-filled memory, not backed by a file
+
+// msync() flags
+#define MS_ASYNC 1       // Schedule the write back
+#define MS_INVALIDATE 2  // Drop other cached copies
+#define MS_SYNC 4        // Write back before returning
+
+// Returned by map() and sbrk() on error
+#define MAP_ERROR ((void*)-1)
+
+#endif
@@ -0,0 +1,21 @@
+#ifndef __LIB_RESOURCE_H
+#define __LIB_RESOURCE_H
+
+#include "types.h"
+
+// getrusage() targets
+#define RUSAGE_SELF 0  // The calling process
+
+// Memory usage of a process, sizes in pages
+struct rusage {
+    uint64 ru_resident;    // Pages in memory
+    uint64 ru_swapped;     // Pages in swap
+    uint64 ru_filemapped;  // Pages of files mapped, also counted as resident
+    uint64 ru_maxrss;      // Most pages ever in memory at once
+    uint64 ru_minflt;      // Page faults served without I/O
+    uint64 ru_majflt;      // Page faults that read from disk
+    uint64 ru_nevict;      // Pages evicted, to swap or dropped
+    uint64 ru_nswapin;     // Pages read back from swap
+};
+
+#endif
@@ -0,0 +1,43 @@
+#ifndef __LIB_SIGNAL_H
+#define __LIB_SIGNAL_H
+
+#include "types.h"
+
+// signal numbers
+#define SIGHUP 1    // Hangup
+#define SIGINT 2    // ^C on the console
+#define SIGQUIT 3   // Quit
+#define SIGILL 4    // Illegal instruction
+#define SIGTRAP 5   // Breakpoint
+#define SIGABRT 6   // Abort
+#define SIGBUS 7    // Misaligned access
+#define SIGKILL 9   // Kill, cannot be caught, ignored or blocked
+#define SIGUSR1 10  // User-defined signal 1
+#define SIGSEGV 11  // Invalid memory access
+#define SIGUSR2 12  // User-defined signal 2
+#define SIGPIPE 13  // Broken pipe
+#define SIGALRM 14  // Alarm
+#define SIGTERM 15  // Termination request
+#define SIGCHLD 17  // A child exited, ignored by default
+#define NSIG 32
+
+typedef uint64 sigset_t;
+typedef void (*sighandler_t)(int);
+
+#define SIG_DFL ((sighandler_t)0)  // Take the default action
+#define SIG_IGN ((sighandler_t)1)  // Ignore the signal
+
+#define sigmask(sig) (1UL << (sig))
+
+// sigprocmask() operations
+#define SIG_BLOCK 0    // Block the signals in set
+#define SIG_UNBLOCK 1  // Unblock the signals in set
+#define SIG_SETMASK 2  // Block exactly the signals in set
+
+struct sigaction {
+    sighandler_t sa_handler;
+    sigset_t sa_mask;             // Also blocked while the handler runs
+    void (*sa_restorer)(void);    // Set by sigaction()
+};
+
+#endif
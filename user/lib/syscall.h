@@ -18,3 +18,41 @@
 /* Project 4 only. */
 #define SYS_CHDIR 15 /**< Change the current directory. */
 #define SYS_MKDIR 16 /**< Create a directory. */
+
+/* Terminal control. */
+#define SYS_IOCTL 17 /**< Control a terminal device. */
+
+/* Processes. */
+#define SYS_FORK 18 /**< Duplicate this process. */
+#define SYS_GETPID 19 /**< Get the process id. */
+#define SYS_GETPPID 20 /**< Get the parent's process id. */
+
+/* Signals. */
+#define SYS_KILL 21        /**< Send a signal to a process. */
+#define SYS_SIGACTION 22   /**< Change the action of a signal. */
+#define SYS_SIGPROCMASK 23 /**< Change the blocked signals. */
+#define SYS_SIGRETURN 24   /**< Return from a signal handler. */
+#define SYS_TERMSIG 39     /**< Get the signal that killed a child process. */
+
+/* Pipes and file descriptors. */
+#define SYS_PIPE 25 /**< Create a pipe. */
+#define SYS_DUP 26  /**< Duplicate a file descriptor. */
+#define SYS_DUP2 27 /**< Duplicate a file descriptor to a given one. */
+
+/* Threads. */
+#define SYS_THREAD_CREATE 28 /**< Start a thread in this process. */
+#define SYS_THREAD_EXIT 29   /**< Terminate this thread. */
+#define SYS_THREAD_JOIN 30   /**< Wait for a thread to terminate. */
+#define SYS_FUTEX 31         /**< Wait on or wake a futex. */
+
+/* Environment. */
+#define SYS_EXECVE 32 /**< Start another process with an environment. */
+
+/* Memory. */
+#define SYS_SBRK 33      /**< Grow or shrink the heap. */
+#define SYS_MAP 34       /**< Map memory. */
+#define SYS_UNMAP 35     /**< Unmap memory. */
+#define SYS_MPROTECT 36  /**< Change the protection of memory. */
+#define SYS_MSYNC 37     /**< Write mapped memory back to its file. */
+#define SYS_GETRUSAGE 38 /**< Report memory usage. */
+#define SYS_KSM 40       /**< Tune and report same-page merging. */
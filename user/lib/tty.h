@@ -0,0 +1,13 @@
+#ifndef __LIB_TTY_H
+#define __LIB_TTY_H
+
+// ioctl requests
+#define TCGETMODE 1  // Get the tty mode into *(uint64*)arg
+#define TCSETMODE 2  // Set the tty mode to arg
+
+// tty mode flags
+#define TTY_ICANON 0x1  // Line-buffered input with erase and kill editing
+#define TTY_ECHO 0x2    // Echo typed characters
+#define TTY_ISIG 0x4    // ^C interrupts the foreground process
+
+#endif
@@ -7,6 +7,8 @@ typedef unsigned short uint16;
 typedef unsigned int uint32;
 typedef unsigned long uint64;
 typedef unsigned long size_t;
+typedef long intptr_t;
+typedef long off_t;
 
 /* Process identifier. */
 typedef int pid_t;
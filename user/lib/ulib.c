@@ -2,13 +2,35 @@
 
 #include "user.h"
 
+char** environ;
+
 // wrapper so that it's OK if main() does not call exit().
-void _main() {
+void _main(int argc, char* argv[], char* envp[]) {
     extern void main();
-    main();
+    environ = envp;
+    main(argc, argv, envp);
     exit(NORMAL_EXIT);
 }
 
+// The value of environment variable name, NULL if it is not set.
+char* getenv(const char* name) {
+    for (char** env = environ; env && *env; env++) {
+        const char *p = name, *q = *env;
+        while (*p && *p == *q) p++, q++;
+        if (!*p && *q == '=') return (char*)q + 1;
+    }
+    return NULL;
+}
+
+// The value of key type in the auxiliary vector, 0 if there is none.
+uint64 getauxval(uint64 type) {
+    char** env = environ;
+    while (*env) env++;
+    for (uint64* aux = (uint64*)(env + 1); aux[0] != AT_NULL; aux += 2)
+        if (aux[0] == type) return aux[1];
+    return 0;
+}
+
 int strcmp(const char* p, const char* q) {
     while (*p && *p == *q) p++, q++;
     return (uchar)*p - (uchar)*q;
@@ -113,6 +135,26 @@ int atoi(const char* s) {
     return sign * n;
 }
 
+// Handlers return into sigreturn(), which resumes the interrupted code.
+int sigaction(int sig, const struct sigaction* act, struct sigaction* oldact) {
+    extern int __sigaction(int, const struct sigaction*, struct sigaction*);
+    struct sigaction sa;
+
+    if (act) {
+        sa = *act;
+        sa.sa_restorer = sigreturn;
+        act = &sa;
+    }
+    return __sigaction(sig, act, oldact);
+}
+
+// A thread returning from fn passes the return value to thread_exit().
+int thread_create(void* (*fn)(void*), void* arg) {
+    extern int __thread_create(void* (*)(void*), void*, void (*)(void*));
+
+    return __thread_create(fn, arg, thread_exit);
+}
+
 static void compare_bytes(const void* read_data_, const void* expected_data_, size_t size,
                           size_t ofs, const char* file_name) {
     const uint8* read_data = read_data_;
@@ -0,0 +1,78 @@
+/* Memory allocator by Kernighan and Ritchie, The C programming Language,
+   2nd ed. Section 8.7. Memory comes from the heap, grown with sbrk(). */
+
+#include "user.h"
+
+typedef long Align;
+
+union header {
+    struct {
+        union header* ptr;
+        size_t size;
+    } s;
+    Align x;
+};
+
+typedef union header Header;
+
+static Header base;
+static Header* freep;
+
+void free(void* ap) {
+    Header *bp, *p;
+
+    if (ap == NULL) return;
+    bp = (Header*)ap - 1;
+    for (p = freep; !(bp > p && bp < p->s.ptr); p = p->s.ptr)
+        if (p >= p->s.ptr && (bp > p || bp < p->s.ptr)) break;
+    if (bp + bp->s.size == p->s.ptr) {
+        bp->s.size += p->s.ptr->s.size;
+        bp->s.ptr = p->s.ptr->s.ptr;
+    } else
+        bp->s.ptr = p->s.ptr;
+    if (p + p->s.size == bp) {
+        p->s.size += bp->s.size;
+        p->s.ptr = bp->s.ptr;
+    } else
+        p->s.ptr = bp;
+    freep = p;
+}
+
+static Header* morecore(size_t nu) {
+    char* p;
+    Header* hp;
+
+    if (nu < 4096) nu = 4096;
+    p = sbrk(nu * sizeof(Header));
+    if (p == MAP_ERROR) return NULL;
+    hp = (Header*)p;
+    hp->s.size = nu;
+    free((void*)(hp + 1));
+    return freep;
+}
+
+void* malloc(size_t nbytes) {
+    Header *p, *prevp;
+    size_t nunits;
+
+    nunits = (nbytes + sizeof(Header) - 1) / sizeof(Header) + 1;
+    if ((prevp = freep) == NULL) {
+        base.s.ptr = freep = prevp = &base;
+        base.s.size = 0;
+    }
+    for (p = prevp->s.ptr;; prevp = p, p = p->s.ptr) {
+        if (p->s.size >= nunits) {
+            if (p->s.size == nunits)
+                prevp->s.ptr = p->s.ptr;
+            else {
+                p->s.size -= nunits;
+                p += p->s.size;
+                p->s.size = nunits;
+            }
+            freep = prevp;
+            return (void*)(p + 1);
+        }
+        if (p == freep)
+            if ((p = morecore(nunits)) == NULL) return NULL;
+    }
+}
@@ -5,14 +5,32 @@
 
 #include "fcntl.h"
 #include "fstat.h"
+#include "ksm.h"
+#include "mman.h"
+#include "resource.h"
+#include "signal.h"
+#include "tty.h"
 #include "types.h"
 
 #define NULL ((void*)0)
 #define ROUND_UP(p, align) (((uint64)p + (align)-1) / (align) * (align))
 #define ROUND_DOWN(p, align) ((uint64)p / (align) * (align))
 #define PANIC_EXIT 12345
+
+// futex() operations
+#define FUTEX_WAIT 0  // Sleep while *addr == val
+#define FUTEX_WAKE 1  // Wake up at most val sleepers on addr
 #define NORMAL_EXIT 0
 
+// getauxval() keys
+#define AT_NULL 0     // End of the vector
+#define AT_PHDR 3     // Address of the program headers
+#define AT_PHENT 4    // Size of a program header
+#define AT_PHNUM 5    // Number of program headers
+#define AT_PAGESZ 6   // Page size
+#define AT_ENTRY 9    // Entry point
+#define AT_RANDOM 25  // Address of 16 random bytes
+
 #define panic(fmt, args...)                                                       \
     do {                                                                          \
         fprintf(2, "panicked at '" fmt "', %s:%d\n", ##args, __FILE__, __LINE__); \
@@ -29,6 +47,7 @@
 void halt(void);
 void exit(int status);
 int exec(const char* pathname, const char* argv[]);
+int execve(const char* pathname, const char* argv[], const char* envp[]);
 int wait(int pid);
 int remove(const char* pathname);
 int open(const char* pathname, int flags);
@@ -42,6 +61,27 @@ int mmap(int fd, void* addr);
 void munmap(int mapid);
 int chdir(const char* dir);
 int mkdir(const char* dir);
+int ioctl(int fd, int request, uint64 arg);
+int fork(void);
+int getpid(void);
+int getppid(void);
+int kill(int pid, int sig);
+int termsig(int pid);
+int sigprocmask(int how, const sigset_t* set, sigset_t* oldset);
+void sigreturn(void);
+int pipe(int fds[2]);
+int dup(int fd);
+int dup2(int oldfd, int newfd);
+void thread_exit(void* value);
+int thread_join(int tid, void** value);
+int futex(int* addr, int op, int val);
+void* sbrk(intptr_t increment);
+void* map(void* addr, size_t len, int prot, int flags, int fd, off_t offset);
+int unmap(void* addr, size_t len);
+int mprotect(void* addr, size_t len, int prot);
+int msync(void* addr, size_t len, int flags);
+int getrusage(int who, struct rusage* usage);
+int ksm(int interval, struct ksmstat* stat);
 
 // ulib.c
 void fprintf(int fd, const char* fmt, ...);
@@ -50,6 +90,11 @@ int strcmp(const char*, const char*);
 char* strcpy(char*, const char*);
 int strlen(const char*);
 void itoa(char*, int);
+int sigaction(int sig, const struct sigaction* act, struct sigaction* oldact);
+int thread_create(void* (*fn)(void*), void* arg);
+extern char** environ;
+char* getenv(const char* name);
+uint64 getauxval(uint64 type);
 int atoi(const char*);
 void* memset(void*, int, uint);
 int memcmp(const void*, const void*, uint64);
@@ -58,4 +103,8 @@ void check_file(const char*, const void* buf, size_t);
 void check_file_handle(int fd, const char* file_name, const void* buf_, size_t size);
 uint64 r_sp();
 
+// umalloc.c
+void* malloc(size_t size);
+void free(void* ptr);
+
 #endif
@@ -4,9 +4,10 @@ print "# generated by usys.pl - do not edit\n";
 
 print "#include \"syscall.h\"\n";
 
+# entry(name[, syscall]): the syscall defaults to the upper-cased name.
 sub entry {
     my $name = shift;
-    my $uname = uc($name);
+    my $uname = uc(shift // $name);
     print ".global $name\n";
     print "$name:\n";
     print " li a7, SYS_$uname\n";
@@ -30,3 +31,27 @@ entry("mmap");
 entry("munmap");
 entry("chdir");
 entry("mkdir");
+entry("ioctl");
+entry("fork");
+entry("getpid");
+entry("getppid");
+entry("kill");
+entry("__sigaction", "sigaction");
+entry("sigprocmask");
+entry("sigreturn");
+entry("pipe");
+entry("dup");
+entry("dup2");
+entry("__thread_create", "thread_create");
+entry("thread_exit");
+entry("thread_join");
+entry("futex");
+entry("execve");
+entry("sbrk");
+entry("map");
+entry("unmap");
+entry("mprotect");
+entry("msync");
+entry("getrusage");
+entry("termsig");
+entry("ksm");
@@ -0,0 +1,26 @@
+/* Runs as a static position-independent executable. Pointers in data
+   need relocating to wherever the kernel loaded the program. */
+
+#include "user.h"
+
+static int twice(int x) { return 2 * x; }
+static int square(int x) { return x * x; }
+
+static int (*const ops[])(int) = {twice, square};
+static const char* words[] = {"zero", "one", "two"};
+static int counter;
+static int* counter_ptr = &counter;
+
+void main() {
+    extern void _main();
+
+    assert(getauxval(AT_ENTRY) == (uint64)_main);
+    assert(getauxval(AT_PHDR) != 0);
+
+    assert(ops[0](3) == 6);
+    assert(ops[1](3) == 9);
+    assert(strcmp(words[2], "two") == 0);
+
+    *counter_ptr = 42;
+    assert(counter == 42);
+}
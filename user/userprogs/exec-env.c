@@ -0,0 +1,36 @@
+/* Runs itself with an environment, and checks that the child sees it
+   along with its auxiliary vector. */
+
+#include "user.h"
+
+static void child(char* envp[]) {
+    extern void _main();
+    uint8* random = (uint8*)getauxval(AT_RANDOM);
+    int nonzero = 0;
+
+    assert(envp == environ);
+    assert(strcmp(getenv("GREETING"), "hello") == 0);
+    assert(strcmp(getenv("EMPTY"), "") == 0);
+    assert(getenv("GREET") == NULL);
+    assert(getenv("MISSING") == NULL);
+
+    assert(getauxval(AT_PAGESZ) == 4096);
+    assert(getauxval(AT_ENTRY) == (uint64)_main);
+    assert(random != NULL);
+    for (int i = 0; i < 16; i++) nonzero |= random[i];
+    assert(nonzero);
+    exit(7);
+}
+
+void main(int argc, char* argv[], char* envp[]) {
+    int pid;
+    const char* args[] = {"exec-env", "child", 0};
+    const char* env[] = {"GREETING=hello", "EMPTY=", 0};
+
+    if (argc == 2) child(envp);
+
+    assert(envp[0] == NULL);
+    assert(getenv("GREETING") == NULL);
+    assert((pid = execve(args[0], args, env)) >= 0);
+    assert(wait(pid) == 7);
+}
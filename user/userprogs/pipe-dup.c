@@ -0,0 +1,38 @@
+/* Sends data from a child to its parent through a pipe, with the
+   child's stdout redirected to the write end. The parent reads until
+   end of file, which comes once every write end is closed. */
+
+#include "user.h"
+
+static const char msg[] = "through the pipe\n";
+
+void main() {
+    int fds[2], pid, n, total = 0;
+    char buf[64];
+
+    assert(pipe(fds) == 0);
+    assert(fds[0] > 2 && fds[1] > 2 && fds[0] != fds[1]);
+    assert(write(fds[0], msg, 1) == -1);
+
+    if ((pid = fork()) == 0) {
+        close(fds[0]);
+        assert(dup2(fds[1], 1) == 1);
+        close(fds[1]);
+        printf("%s", msg);
+        exit(0);
+    }
+
+    close(fds[1]);
+    while ((n = read(fds[0], buf + total, sizeof buf - total)) > 0) total += n;
+    assert(n == 0);
+    assert(total == sizeof msg - 1);
+    assert(memcmp(buf, msg, total) == 0);
+    assert(wait(pid) == 0);
+
+    /* dup() shares the read end; with no writers left it stays at EOF. */
+    n = dup(fds[0]);
+    assert(n > 2);
+    assert(read(n, buf, sizeof buf) == 0);
+    assert(close(fds[0]) == 0 && close(n) == 0);
+    assert(close(n) == -1);
+}
@@ -0,0 +1,13 @@
+/* Interpreter of the scripts run by script-run. */
+
+#include "user.h"
+
+void main(int argc, char* argv[]) {
+    assert(argc == 5);
+    assert(strcmp(argv[0], "script-interp") == 0);
+    assert(strcmp(argv[1], "-v") == 0);
+    assert(strcmp(argv[2], "script-hello") == 0);
+    assert(strcmp(argv[3], "a") == 0);
+    assert(strcmp(argv[4], "b") == 0);
+    exit(81);
+}
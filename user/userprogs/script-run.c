@@ -0,0 +1,20 @@
+/* Runs scripts: one with an interpreter taking an argument, and one
+   that is its own interpreter, which exec must reject. The interpreter
+   gets the path of the script, whatever argv[0] is. */
+
+#include "user.h"
+
+void main() {
+    int pid;
+    const char* hello[] = {"script-hello", "a", "b", 0};
+    const char* renamed[] = {"hello", "a", "b", 0};
+    const char* loop[] = {"script-loop", 0};
+
+    assert((pid = exec(hello[0], hello)) >= 0);
+    assert(wait(pid) == 81);
+
+    assert((pid = exec("script-hello", renamed)) >= 0);
+    assert(wait(pid) == 81);
+
+    assert(exec(loop[0], loop) == -1);
+}
@@ -0,0 +1,48 @@
+/* A process exits, or is killed, while some of its threads are blocked in
+   the kernel: reading a pipe nobody writes to, and joining such a reader.
+   They are interrupted instead of keeping the process alive forever. */
+
+#include "user.h"
+
+static int fds[2];
+
+static void* reader(void* arg) {
+    char c;
+    read(fds[0], &c, 1);
+    panic("unreachable");
+    return arg;
+}
+
+static void* exiter(void* arg) {
+    /* Give the others time to block. */
+    for (volatile int i = 0; i < 1000000; i++)
+        ;
+    exit(7);
+    return arg;
+}
+
+void main() {
+    int pid;
+    char c;
+
+    assert(pipe(fds) == 0);
+
+    if ((pid = fork()) == 0) {
+        int tid = thread_create(reader, NULL);
+        assert(tid > 0);
+        assert(thread_create(exiter, NULL) > 0);
+        thread_join(tid, NULL);
+        panic("unreachable");
+    }
+    assert(wait(pid) == 7);
+
+    /* SIGKILL reaches a process blocked in a read. */
+    if ((pid = fork()) == 0) {
+        read(fds[0], &c, 1);
+        panic("unreachable");
+    }
+    for (volatile int i = 0; i < 1000000; i++)
+        ;
+    assert(kill(pid, SIGKILL) == 0);
+    assert(wait(pid) == -1);
+}
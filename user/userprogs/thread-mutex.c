@@ -0,0 +1,58 @@
+/* Runs several threads incrementing a shared counter under a
+   futex-based mutex, then joins them and checks their results. */
+
+#include "user.h"
+
+#define NTHREADS 4
+#define ITERS 2000
+
+/* 0: unlocked, 1: locked, 2: locked with waiters. */
+static int lock;
+static volatile int counter;
+
+static void mutex_lock(int* m) {
+    int c = __sync_val_compare_and_swap(m, 0, 1);
+    if (c == 0) return;
+    if (c != 2) c = __atomic_exchange_n(m, 2, __ATOMIC_ACQUIRE);
+    while (c != 0) {
+        futex(m, FUTEX_WAIT, 2);
+        c = __atomic_exchange_n(m, 2, __ATOMIC_ACQUIRE);
+    }
+}
+
+static void mutex_unlock(int* m) {
+    if (__atomic_exchange_n(m, 0, __ATOMIC_RELEASE) == 2) futex(m, FUTEX_WAKE, 1);
+}
+
+static void* worker(void* arg) {
+    int i, local[64];
+
+    /* Touch the own stack, which is separate from the others. */
+    memset(local, (int)(uint64)arg, sizeof local);
+    for (i = 0; i < ITERS; i++) {
+        mutex_lock(&lock);
+        counter++;
+        mutex_unlock(&lock);
+    }
+    assert(local[63] == (int)(uint64)arg * 0x01010101);
+    return (char*)arg + 100;
+}
+
+void main() {
+    int tids[NTHREADS], i;
+    void* value;
+
+    for (i = 0; i < NTHREADS; i++) {
+        tids[i] = thread_create(worker, (void*)(uint64)(i + 1));
+        assert(tids[i] > 0);
+    }
+    for (i = 0; i < NTHREADS; i++) {
+        assert(thread_join(tids[i], &value) == 0);
+        assert(value == (void*)(uint64)(i + 101));
+    }
+    assert(thread_join(tids[0], &value) == -1);
+    assert(counter == NTHREADS * ITERS);
+
+    /* A futex whose value changed does not block. */
+    assert(futex(&lock, FUTEX_WAIT, 1) == -1);
+}
@@ -0,0 +1,35 @@
+/* Gets and sets the console mode through ioctl(), which fails for
+   unknown requests, unknown mode bits and file descriptors that are not
+   the console. */
+
+#include "user.h"
+
+void main() {
+    uint64 mode, saved;
+    int fds[2];
+
+    assert(ioctl(0, TCGETMODE, (uint64)&saved) == 0);
+    assert(saved == (TTY_ICANON | TTY_ECHO | TTY_ISIG));
+
+    assert(ioctl(0, TCSETMODE, TTY_ECHO) == 0);
+    assert(ioctl(1, TCGETMODE, (uint64)&mode) == 0);
+    assert(mode == TTY_ECHO);
+    assert(ioctl(0, TCSETMODE, 0) == 0);
+    assert(ioctl(0, TCGETMODE, (uint64)&mode) == 0);
+    assert(mode == 0);
+
+    assert(ioctl(0, TCSETMODE, 0x8) == -1);
+    assert(ioctl(0, 3, 0) == -1);
+    assert(ioctl(0, TCGETMODE, (uint64)&mode) == 0);
+    assert(mode == 0);
+
+    assert(pipe(fds) == 0);
+    assert(ioctl(fds[0], TCGETMODE, (uint64)&mode) == -1);
+    assert(ioctl(fds[1], TCSETMODE, 0) == -1);
+    assert(close(fds[0]) == 0 && close(fds[1]) == 0);
+    assert(ioctl(64, TCGETMODE, (uint64)&mode) == -1);
+
+    assert(ioctl(0, TCSETMODE, saved) == 0);
+    assert(ioctl(0, TCGETMODE, (uint64)&mode) == 0);
+    assert(mode == saved);
+}
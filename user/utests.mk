@@ -39,6 +39,29 @@ $(TARGETS):%:%.o $(INCS)
 	$(OBJDUMP) -S $@ > $*.asm
 	$(OBJDUMP) -t $@ | sed '1,/SYMBOL TABLE/d; s/ .* / /; /^$$/d' > $*.sym
 
+# Programs in PIE_DIR are static position-independent executables, linked
+# against a position-independent build of the library.
+PIE_DIR := user/pie
+PIE_SRCS := $(shell find $(PIE_DIR) -name '*.c')
+PIE_OBJS := $(PIE_SRCS:%.c=$(BUILD_DIR)/%.o)
+PIE_TARGETS := $(PIE_OBJS:%.o=%)
+PIE_INCS := $(patsubst $(BUILD_DIR)/%,$(BUILD_DIR)/pie/%,$(filter-out %/usys.o,$(INCS)))
+
+$(PIE_INCS):$(BUILD_DIR)/pie/%.o:%.c
+	@ mkdir -p $(dir $@)
+	$(CC) $(CFLAGS) -fPIE -c $< -o $@
+
+$(PIE_OBJS):$(BUILD_DIR)/%.o:%.c
+	@ mkdir -p $(dir $@)
+	$(CC) $(CPPFLAGS) $(CFLAGS) -fPIE -c $< -o $@
+
+$(PIE_TARGETS):%:%.o $(PIE_INCS) $(filter %/usys.o,$(INCS))
+	$(LD) $(LDFLAGS) -pie --no-dynamic-linker -z relro -z noexecstack -o $@ $^
+	$(OBJDUMP) -S $@ > $*.asm
+	$(OBJDUMP) -t $@ | sed '1,/SYMBOL TABLE/d; s/ .* / /; /^$$/d' > $*.sym
+
+TARGETS += $(PIE_TARGETS)
+
 .PHONY: $(BUILD_DIR)
 $(BUILD_DIR):
 	@ mkdir -p $(BUILD_DIR)
@@ -47,6 +70,14 @@ $(TEST_DIR)/sample.txt:
 	#! Ensure sample.txt is stored with LF in disk.img
 	tr -d '\r' < user/userprogs/sample.txt > $(TEST_DIR)/sample.txt
 
+# Scripts run by their #! interpreter, shipped next to the programs.
+SCRIPTS := $(patsubst user/scripts/%,$(TEST_DIR)/%,$(wildcard user/scripts/*))
+TARGETS += $(SCRIPTS)
+
+$(SCRIPTS):$(TEST_DIR)/%:user/scripts/%
+	@ mkdir -p $(dir $@)
+	tr -d '\r' < $< > $@
+
 $(TEST_DIR)/zeros:
 	dd if=/dev/zero of=$(TEST_DIR)/zeros bs=8192 count=1
 
@@ -0,0 +1,42 @@
+/* Illegal instructions and breakpoints kill only the faulting child,
+   unless it handles the signal. Its parent tells that from exit(-1) by
+   termsig(). */
+
+#include "user.h"
+
+static void on_trap(int sig) {
+    assert(sig == SIGTRAP);
+    exit(42);
+}
+
+void main() {
+    int pid;
+
+    if ((pid = fork()) == 0) {
+        asm volatile(".word 0");
+        panic("unreachable");
+    }
+    assert(termsig(pid) == SIGILL);
+    assert(wait(pid) == -1);
+
+    if ((pid = fork()) == 0) {
+        asm volatile("ebreak");
+        panic("unreachable");
+    }
+    assert(termsig(pid) == SIGTRAP);
+    assert(wait(pid) == -1);
+
+    if ((pid = fork()) == 0) exit(-1);
+    assert(termsig(pid) == 0);
+    assert(wait(pid) == -1);
+    assert(termsig(pid) == -1);
+
+    if ((pid = fork()) == 0) {
+        struct sigaction sa = {.sa_handler = on_trap};
+        assert(sigaction(SIGTRAP, &sa, NULL) == 0);
+        asm volatile("ebreak");
+        panic("unreachable");
+    }
+    assert(termsig(pid) == 0);
+    assert(wait(pid) == 42);
+}
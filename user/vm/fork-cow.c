@@ -0,0 +1,38 @@
+/* Forks a process sharing a 64 kB buffer copy-on-write. Parent and
+   child each modify their own copy, and neither may observe the
+   other's writes. */
+
+#include "user.h"
+
+#define SIZE (64 * 1024)
+
+static char buf[SIZE];
+
+void main() {
+    int pid;
+    size_t i;
+
+    memset(buf, 0x5a, sizeof buf);
+
+    if ((pid = fork()) == 0) {
+        /* Child. */
+        for (i = 0; i < SIZE; i++)
+            if (buf[i] != 0x5a) panic("child: byte %d != 0x5a", i);
+        memset(buf, 0xa5, sizeof buf);
+        for (i = 0; i < SIZE; i++)
+            if (buf[i] != (char)0xa5) panic("child: byte %d != 0xa5", i);
+        exit(81);
+    }
+
+    assert(pid > 0);
+    assert(wait(pid) == 81);
+
+    /* The child's writes must not show up here. */
+    for (i = 0; i < SIZE; i++)
+        if (buf[i] != 0x5a) panic("parent: byte %d != 0x5a", i);
+
+    /* And the parent can still write its own copy. */
+    memset(buf, 0x33, sizeof buf);
+    for (i = 0; i < SIZE; i++)
+        if (buf[i] != 0x33) panic("parent: byte %d != 0x33", i);
+}
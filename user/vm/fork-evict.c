@@ -0,0 +1,30 @@
+/* Pages a child shares with its parent after fork() go to swap together, and
+   come back intact in both, however many times they are evicted. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define SHARED (1 << 20)
+#define LARGE (2 << 20)
+
+static char shared[SHARED];
+static char large[LARGE];
+
+static void check(void) {
+    for (int round = 0; round < 2; round++) {
+        for (int i = 0; i < LARGE; i += PAGE) large[i] = (char)(i / PAGE);
+        for (int i = 0; i < SHARED; i++) assert(shared[i] == (char)(i * 7));
+    }
+}
+
+void main() {
+    int pid;
+
+    for (int i = 0; i < SHARED; i++) shared[i] = (char)(i * 7);
+    if ((pid = fork()) == 0) {
+        check();
+        exit(0);
+    }
+    check();
+    assert(wait(pid) == 0);
+}
@@ -0,0 +1,30 @@
+/* Checks pids across a small process tree: a child sees its parent's
+   pid, and a grandchild orphaned by the child's exit is adopted by
+   init. A child can be reaped only once, and only by its parent. */
+
+#include "user.h"
+
+void main() {
+    int self = getpid(), pid;
+
+    assert(self > 1);
+
+    if ((pid = fork()) == 0) {
+        int parent = getpid();
+
+        assert(getppid() == self);
+        if (fork() == 0) {
+            /* Grandchild: wait until the child is gone. */
+            while (getppid() == parent)
+                ;
+            assert(getppid() == 1);
+            exit(0);
+        }
+        exit(getpid() - self);
+    }
+
+    assert(pid > self);
+    assert(wait(pid) == pid - self);
+    assert(wait(pid) == -1);
+    assert(wait(self) == -1);
+}
@@ -0,0 +1,62 @@
+/* Pages with the same contents are merged in the background, once enabled.
+   Each page still keeps its own contents once written, in the parent and in
+   a child forked from it. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define PAGES 64
+#define ROUNDS 200
+#define TRIES 50
+
+static char buf[PAGES * PAGE] __attribute__((aligned(PAGE)));
+
+/* Reads all pages for a while, giving them time to be merged. */
+static void linger(void) {
+    volatile char sum = 0;
+    for (int round = 0; round < ROUNDS; round++)
+        for (int i = 0; i < PAGES * PAGE; i += 64) sum += buf[i];
+}
+
+static void check(int base) {
+    for (int i = 0; i < PAGES; i++)
+        for (int j = 0; j < PAGE; j++)
+            assert(buf[i * PAGE + j] == (char)(j == 0 ? base + i : j % 7));
+}
+
+/* Pages merged so far. */
+static uint64 merged(void) {
+    struct ksmstat stat;
+    assert(ksm(KSM_KEEP, &stat) == 0);
+    return stat.ks_merged;
+}
+
+void main() {
+    int pid;
+    uint64 before;
+
+    before = merged();
+    assert(ksm(1, NULL) == 0);
+
+    for (int i = 0; i < PAGES * PAGE; i++) buf[i] = (char)(i % PAGE % 7);
+    for (int tries = 0; tries < TRIES && merged() == before; tries++) linger();
+    assert(merged() > before);
+
+    /* Make every page different again. */
+    for (int i = 0; i < PAGES; i++) buf[i * PAGE] = (char)i;
+    check(0);
+
+    if ((pid = fork()) == 0) {
+        /* Page i is the same as page i + 1 of the parent now. */
+        for (int i = 0; i < PAGES; i++) buf[i * PAGE] = (char)(i + 1);
+        linger();
+        check(1);
+        for (int i = 0; i < PAGES; i++) buf[i * PAGE] = (char)(i + 100);
+        check(100);
+        exit(0);
+    }
+    linger();
+    check(0);
+    assert(wait(pid) == 0);
+    assert(ksm(0, NULL) == 0);
+}
@@ -0,0 +1,26 @@
+/* Allocates and frees many blocks with malloc, checking that live blocks
+   keep their contents. */
+
+#include "user.h"
+
+#define N 200
+
+void main() {
+    static char* blocks[N];
+
+    for (int round = 0; round < 3; round++) {
+        for (int i = 0; i < N; i++) {
+            size_t size = 1 + (i * 37 + round * 101) % 3000;
+            assert((blocks[i] = malloc(size)) != NULL);
+            memset(blocks[i], i, size);
+        }
+        /* Free every other block, and allocate them again. */
+        for (int i = 0; i < N; i += 2) free(blocks[i]);
+        for (int i = 0; i < N; i += 2) {
+            assert((blocks[i] = malloc(64)) != NULL);
+            memset(blocks[i], i, 64);
+        }
+        for (int i = 0; i < N; i++) assert(blocks[i][0] == (char)i);
+        for (int i = 0; i < N; i++) free(blocks[i]);
+    }
+}
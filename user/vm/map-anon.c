@@ -0,0 +1,45 @@
+/* Anonymous mappings: at a kernel-chosen address, at a fixed one, and
+   unmapped again. Their memory starts out zeroed. */
+
+#include "user.h"
+
+#define LEN (3 * 4096)
+
+void main() {
+    char *p, *q;
+    int pid;
+
+    p = map(NULL, LEN, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_ERROR);
+    assert((uint64)p % 4096 == 0);
+    for (int i = 0; i < LEN; i++) assert(p[i] == 0);
+    memset(p, 'x', LEN);
+
+    /* A second mapping does not overlap the first. */
+    q = map(NULL, 100, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(q != MAP_ERROR);
+    assert(q + 4096 <= p || q >= p + LEN);
+    *q = 'y';
+
+    /* A fixed mapping cannot replace another one. */
+    assert(map(p, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0) == MAP_ERROR);
+    assert(map(NULL, 0, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) == MAP_ERROR);
+    assert(map(NULL, 4096, PROT_READ, MAP_SHARED | MAP_ANONYMOUS, -1, 0) == MAP_ERROR);
+
+    /* Unmapping the middle page leaves the others. */
+    assert(unmap(p + 4096, 4096) == 0);
+    assert(p[0] == 'x' && p[2 * 4096] == 'x');
+    if ((pid = fork()) == 0) {
+        p[4096] = 1;
+        exit(0);
+    }
+    assert(wait(pid) == -1);
+
+    /* The hole can be mapped again, fresh. */
+    assert(map(p + 4096, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED,
+               -1, 0) == p + 4096);
+    assert(p[4096] == 0);
+
+    assert(unmap(p, LEN) == 0);
+    assert(unmap(q, 100) == 0);
+}
@@ -0,0 +1,62 @@
+/* Shared mappings and file I/O see the same page: writes through one mapping
+   show in other processes' mappings and in read(), and write() shows in the
+   mappings, without msync. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define FILE "map-coherent.dat"
+
+static void notify(int fd) {
+    assert(write(fd, "x", 1) == 1);
+}
+
+static void await_notify(int fd) {
+    char c;
+    assert(read(fd, &c, 1) == 1);
+}
+
+void main() {
+    static char buf[2 * PAGE];
+    int to_child[2], to_parent[2];
+    char c, *p, *q;
+    int fd, pid;
+
+    memset(buf, 'a', sizeof buf);
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    assert(write(fd, buf, sizeof buf) == sizeof buf);
+    p = map(NULL, 2 * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_ERROR);
+    assert(pipe(to_child) == 0 && pipe(to_parent) == 0);
+
+    if ((pid = fork()) == 0) {
+        /* A separate mapping of the same file. */
+        int fd2;
+        assert((fd2 = open(FILE, O_RDWR)) > 2);
+        q = map(NULL, 2 * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd2, 0);
+        assert(q != MAP_ERROR);
+        close(fd2);
+
+        q[PAGE + 1] = 'b';
+        notify(to_parent[1]);
+
+        await_notify(to_child[0]);
+        if (q[2] != 'c') exit(1);
+        exit(0);
+    }
+
+    await_notify(to_parent[0]);
+    assert(p[PAGE + 1] == 'b');
+    seek(fd, PAGE + 1);
+    assert(read(fd, &c, 1) == 1 && c == 'b');
+
+    seek(fd, 2);
+    assert(write(fd, "c", 1) == 1);
+    assert(p[2] == 'c');
+    notify(to_child[1]);
+    assert(wait(pid) == 0);
+
+    assert(unmap(p, 2 * PAGE) == 0);
+    close(fd);
+    assert(remove(FILE) == 0);
+}
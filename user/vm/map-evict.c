@@ -0,0 +1,66 @@
+/* Mapped pages of files survive eviction: clean private pages are loaded
+   from the file again, dirty private ones keep their changes but never reach
+   the file, and dirty shared ones are written back to it. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define PAGES 64
+#define FILE "map-evict.dat"
+
+static char pressure[2 * 1024 * 1024];
+
+static char file_byte(int offset) {
+    char c;
+    int fd;
+
+    assert((fd = open(FILE, O_RDONLY)) > 2);
+    seek(fd, offset);
+    assert(read(fd, &c, 1) == 1);
+    close(fd);
+    return c;
+}
+
+void main() {
+    static char page[PAGE];
+    char *private, *shared;
+    int fd;
+
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    for (int i = 0; i < PAGES; i++) {
+        memset(page, 'a' + i % 26, PAGE);
+        assert(write(fd, page, PAGE) == PAGE);
+    }
+    private = map(NULL, PAGES * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(private != MAP_ERROR);
+    shared = map(NULL, PAGES * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(shared != MAP_ERROR);
+    close(fd);
+
+    /* Every other private page is dirtied, every shared page is. */
+    for (int i = 0; i < PAGES; i++) {
+        if (i % 2 == 0)
+            private[i * PAGE] = 'P';
+        else
+            assert(private[i * PAGE] == 'a' + i % 26);
+    }
+    for (int i = 0; i < PAGES; i++) shared[i * PAGE + 1] = 'S';
+
+    /* Touching far more memory than there is evicts them. */
+    memset(pressure, 0x5a, sizeof pressure);
+    for (size_t i = 0; i < sizeof pressure; i += PAGE) assert(pressure[i] == 0x5a);
+
+    for (int i = 0; i < PAGES; i++) {
+        assert(private[i * PAGE] == (i % 2 == 0 ? 'P' : 'a' + i % 26));
+        assert(shared[i * PAGE] == 'a' + i % 26);
+        assert(shared[i * PAGE + 1] == 'S');
+    }
+    for (int i = 0; i < PAGES; i += 7) {
+        assert(file_byte(i * PAGE) == 'a' + i % 26);
+        assert(file_byte(i * PAGE + 1) == 'S');
+    }
+
+    assert(unmap(private, PAGES * PAGE) == 0);
+    assert(unmap(shared, PAGES * PAGE) == 0);
+    assert(remove(FILE) == 0);
+}
@@ -0,0 +1,80 @@
+/* File mappings with an offset: shared ones are written back by msync,
+   unmap and exit, even after the file is closed. Private ones never are. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define FILE "map-file.dat"
+
+static char file_byte(int offset) {
+    char c;
+    int fd;
+
+    assert((fd = open(FILE, O_RDONLY)) > 2);
+    seek(fd, offset);
+    assert(read(fd, &c, 1) == 1);
+    close(fd);
+    return c;
+}
+
+void main() {
+    static char buf[3 * PAGE];
+    char *p, *q;
+    int fd, pid;
+
+    /* Page i of the file is filled with 'a' + i. */
+    for (int i = 0; i < 3; i++) memset(buf + i * PAGE, 'a' + i, PAGE);
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    assert(write(fd, buf, sizeof buf) == sizeof buf);
+
+    p = map(NULL, 2 * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, PAGE);
+    assert(p != MAP_ERROR);
+    q = map(NULL, PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(q != MAP_ERROR);
+    assert(map(NULL, PAGE, PROT_READ, MAP_SHARED, fd, 1) == MAP_ERROR);
+    close(fd);
+    assert(p[0] == 'b' && p[PAGE] == 'c' && q[0] == 'a');
+
+    /* Written back by msync. */
+    p[0] = 'B';
+    assert(msync(p, PAGE, MS_SYNC) == 0);
+    assert(file_byte(PAGE) == 'B');
+
+    /* Private changes stay private. */
+    q[0] = 'A';
+    assert(unmap(q, PAGE) == 0);
+    assert(file_byte(0) == 'a');
+
+    /* Unmapping the second page writes it back and leaves the first. */
+    p[PAGE] = 'C';
+    assert(unmap(p + PAGE, PAGE) == 0);
+    assert(file_byte(2 * PAGE) == 'C');
+    assert(p[0] == 'B');
+    if ((pid = fork()) == 0) {
+        exit(p[PAGE]);
+    }
+    assert(wait(pid) == -1);
+    assert(unmap(p, PAGE) == 0);
+
+    /* Written back on exit. */
+    if ((pid = fork()) == 0) {
+        assert((fd = open(FILE, O_RDWR)) > 2);
+        p = map(NULL, PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+        assert(p != MAP_ERROR);
+        p[1] = 'X';
+        exit(0);
+    }
+    assert(wait(pid) == 0);
+    assert(file_byte(0) == 'a' && file_byte(1) == 'X');
+
+    /* A read-only file can't be written through a shared mapping. */
+    assert((fd = open(FILE, O_RDONLY)) > 2);
+    assert(map(NULL, PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) == MAP_ERROR);
+    assert((q = map(NULL, PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0)) != MAP_ERROR);
+    q[0] = 'Z';
+    assert(unmap(q, PAGE) == 0);
+    close(fd);
+    assert(file_byte(0) == 'a');
+
+    assert(remove(FILE) == 0);
+}
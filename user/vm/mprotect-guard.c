@@ -0,0 +1,76 @@
+/* mprotect: read-only pages, PROT_NONE guard pages, and code written at
+   run time. Bad accesses kill only the faulting child. */
+
+#include "user.h"
+
+#define RW (PROT_READ | PROT_WRITE)
+#define ANON (MAP_PRIVATE | MAP_ANONYMOUS)
+
+void main() {
+    char *p, *guard;
+    unsigned int* code;
+    int pid;
+
+    p = map(NULL, 2 * 4096, RW, ANON, -1, 0);
+    assert(p != MAP_ERROR);
+    memset(p, 'x', 2 * 4096);
+
+    /* Read-only pages can be read, but not written. */
+    assert(mprotect(p, 4096, PROT_READ) == 0);
+    assert(p[0] == 'x');
+    if ((pid = fork()) == 0) {
+        p[0] = 'y';
+        exit(0);
+    }
+    assert(wait(pid) == -1);
+    assert(read(0, p, 1) == -1);
+    p[4096] = 'y';
+
+    /* Writable again, and still private to each process after fork. */
+    assert(mprotect(p, 4096, RW) == 0);
+    p[0] = 'a';
+    if ((pid = fork()) == 0) {
+        assert(mprotect(p, 4096, RW) == 0);
+        p[0] = 'b';
+        exit(p[0]);
+    }
+    assert(wait(pid) == 'b');
+    assert(p[0] == 'a');
+
+    /* A guard page can't be touched by the process or the kernel. */
+    guard = p + 4096;
+    assert(mprotect(guard, 4096, PROT_NONE) == 0);
+    if ((pid = fork()) == 0) {
+        exit(*(volatile char*)guard);
+    }
+    assert(wait(pid) == -1);
+    if ((pid = fork()) == 0) {
+        *guard = 0;
+        exit(0);
+    }
+    assert(wait(pid) == -1);
+    assert(write(1, guard, 1) == -1);
+    assert(mprotect(guard, 4096, PROT_READ) == 0);
+    assert(*guard == 'y');
+
+    /* Only mapped pages can be protected. */
+    assert(mprotect(p + 1, 4096, PROT_READ) == -1);
+    assert(mprotect(p, 3 * 4096, PROT_READ) == -1);
+    assert(mprotect(p, 4096, 8) == -1);
+
+    /* Code can be written, then run once executable. */
+    code = map(NULL, 4096, RW, ANON, -1, 0);
+    assert(code != MAP_ERROR);
+    code[0] = 0x00a00513; /* li a0, 10 */
+    code[1] = 0x00008067; /* ret */
+    asm volatile("fence.i");
+    if ((pid = fork()) == 0) {
+        exit(((int (*)(void))code)());
+    }
+    assert(wait(pid) == -1);
+    assert(mprotect(code, 4096, PROT_READ | PROT_EXEC) == 0);
+    assert(((int (*)(void))code)() == 10);
+
+    assert(unmap(p, 2 * 4096) == 0);
+    assert(unmap(code, 4096) == 0);
+}
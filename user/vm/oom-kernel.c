@@ -0,0 +1,68 @@
+/* Forks processes, then creates threads, until there is no kernel memory
+   left for another one. fork() and thread_create() fail instead of bringing
+   the kernel down, and everything works again once they are gone.
+
+   Children and threads wait on a pipe reading into a shared mapping, so that
+   they hold kernel memory only, not copies of user pages. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define FILE "oom-kernel.dat"
+
+static int fds[2];
+static char* shared;
+
+static void* waiter(void* arg) {
+    assert(read(fds[0], shared, 1) == 0);
+    return arg;
+}
+
+static void* nothing(void* arg) {
+    return arg;
+}
+
+void main() {
+    static int tids[64];
+    int fd, pid, first, last, nthreads;
+    void* value;
+
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    assert(write(fd, "x", 1) == 1);
+    shared = map(NULL, PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(shared != MAP_ERROR);
+    close(fd);
+    assert(pipe(fds) == 0);
+
+    /* Children live on until the pipe is closed. */
+    first = last = -1;
+    while ((pid = fork()) != -1) {
+        if (pid == 0) {
+            close(fds[1]);
+            read(fds[0], shared, 1);
+            exit(0);
+        }
+        if (first == -1) first = pid;
+        last = pid;
+    }
+    assert(first != -1);
+
+    /* So do threads. There is room for 64 of them, but not the memory. */
+    for (nthreads = 0; nthreads < 64; nthreads++)
+        if ((tids[nthreads] = thread_create(waiter, NULL)) == -1) break;
+    assert(nthreads < 64);
+
+    assert(close(fds[1]) == 0);
+    for (int i = 0; i < nthreads; i++) assert(thread_join(tids[i], &value) == 0);
+    for (pid = first; pid <= last; pid++) assert(wait(pid) == 0);
+    assert(close(fds[0]) == 0);
+
+    /* Memory is back. */
+    if ((pid = fork()) == 0) exit(42);
+    assert(pid > last && wait(pid) == 42);
+    assert((pid = thread_create(nothing, shared)) > 0);
+    assert(thread_join(pid, &value) == 0 && value == shared);
+
+    assert(unmap(shared, PAGE) == 0);
+    assert(remove(FILE) == 0);
+}
@@ -0,0 +1,33 @@
+/* A child using more memory than there is, in memory and in swap, is killed,
+   and everything it held is free for others to use afterwards. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define HUGE (8 << 20)
+#define LARGE (3 << 20)
+
+static char* touch(size_t len) {
+    char* p = map(NULL, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_ERROR);
+    for (size_t i = 0; i < len; i += PAGE) p[i] = (char)(i / PAGE);
+    return p;
+}
+
+void main() {
+    char* p;
+    int pid;
+
+    for (int round = 0; round < 2; round++) {
+        if ((pid = fork()) == 0) {
+            touch(HUGE);
+            panic("unreachable");
+        }
+        assert(wait(pid) == -1);
+    }
+
+    /* Needs swap too, which the children released. */
+    p = touch(LARGE);
+    for (size_t i = 0; i < LARGE; i += PAGE) assert(p[i] == (char)(i / PAGE));
+    assert(unmap(p, LARGE) == 0);
+}
@@ -0,0 +1,68 @@
+/* Runs a small hot set of pages against sweeps over more memory than there
+   is, mixing dirty anonymous pages with clean pages of a mapped file, and
+   checks that every page survives eviction. Registered once per replacement
+   policy, selected with the `replace=` kernel parameter. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define HOT 16
+#define COLD (2 * 1024 * 1024)
+#define FILE_PAGES 64
+#define ROUNDS 3
+#define FILE "page-replace.dat"
+
+static char hot[HOT * PAGE];
+static char cold[COLD];
+
+/* The byte at `i` of the pattern seeded with `seed`. */
+static char pattern(int seed, int i) {
+    return (char)((i * 7 + seed * 13 + (i >> 12)) ^ (i >> 5));
+}
+
+void main() {
+    static char page[PAGE];
+    struct rusage before, after;
+    char* mapped;
+    int fd;
+
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    for (int i = 0; i < FILE_PAGES; i++) {
+        for (int j = 0; j < PAGE; j++) page[j] = pattern(2, i * PAGE + j);
+        assert(write(fd, page, PAGE) == PAGE);
+    }
+    mapped = map(NULL, FILE_PAGES * PAGE, PROT_READ, MAP_PRIVATE, fd, 0);
+    assert(mapped != MAP_ERROR);
+    close(fd);
+
+    for (int i = 0; i < HOT * PAGE; i++) hot[i] = pattern(0, i);
+    assert(getrusage(RUSAGE_SELF, &before) == 0);
+
+    for (int round = 0; round < ROUNDS; round++) {
+        for (int i = 0; i < COLD; i++) {
+            if (round == 0)
+                cold[i] = pattern(1, i);
+            else if (cold[i] != pattern(1, i))
+                panic("cold byte %d is wrong in round %d", i, round);
+
+            /* Touch the hot set and the file every few pages. */
+            if (i % (8 * PAGE) == 0) {
+                for (int j = 0; j < HOT; j++)
+                    if (hot[j * PAGE + i % PAGE] != pattern(0, j * PAGE + i % PAGE))
+                        panic("hot page %d is wrong in round %d", j, round);
+                int off = (i / (8 * PAGE)) % FILE_PAGES * PAGE;
+                if (mapped[off] != pattern(2, off))
+                    panic("file page %d is wrong in round %d", off / PAGE, round);
+            }
+        }
+    }
+
+    for (int i = 0; i < HOT * PAGE; i++) assert(hot[i] == pattern(0, i));
+    for (int i = 0; i < FILE_PAGES * PAGE; i++) assert(mapped[i] == pattern(2, i));
+    assert(unmap(mapped, FILE_PAGES * PAGE) == 0);
+    assert(remove(FILE) == 0);
+
+    assert(getrusage(RUSAGE_SELF, &after) == 0);
+    assert(after.ru_nevict > before.ru_nevict);
+    assert(after.ru_nswapin > before.ru_nswapin);
+}
@@ -0,0 +1,72 @@
+/* getrusage() follows the memory of the process: pages it touches, maps from
+   files, and loses to swap and gets back, and the faults it takes. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define PAGES 32
+#define BIG (3 << 20)
+#define FILE "rusage.dat"
+
+static char big[BIG];
+
+void main() {
+    struct rusage before, after;
+    char *p, *q;
+    unsigned int x = 1;
+    int fd;
+
+    assert(getrusage(RUSAGE_SELF, &before) == 0);
+    assert(before.ru_resident > 0 && before.ru_maxrss >= before.ru_resident);
+    assert(getrusage(1, &before) == -1);
+
+    /* Anonymous pages are resident once touched, and gone once unmapped. */
+    assert(getrusage(RUSAGE_SELF, &before) == 0);
+    p = map(NULL, PAGES * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_ERROR);
+    for (int i = 0; i < PAGES; i++) p[i * PAGE] = 1;
+    assert(getrusage(RUSAGE_SELF, &after) == 0);
+    assert(after.ru_resident >= before.ru_resident + PAGES);
+    assert(after.ru_minflt >= before.ru_minflt + PAGES);
+    assert(after.ru_maxrss >= after.ru_resident);
+    assert(unmap(p, PAGES * PAGE) == 0);
+    assert(getrusage(RUSAGE_SELF, &before) == 0);
+    assert(before.ru_resident + PAGES <= after.ru_resident);
+    assert(before.ru_maxrss == after.ru_maxrss);
+
+    /* Shared mappings of a file map its cached pages. */
+    assert((fd = open(FILE, O_CREATE | O_TRUNC | O_RDWR)) > 2);
+    for (int i = 0; i < PAGES; i++) assert(write(fd, big, PAGE) == PAGE);
+    q = map(NULL, PAGES * PAGE, PROT_READ, MAP_SHARED, fd, 0);
+    assert(q != MAP_ERROR);
+    close(fd);
+    for (int i = 0; i < PAGES; i++) assert(q[i * PAGE] == 0);
+    assert(getrusage(RUSAGE_SELF, &after) == 0);
+    assert(after.ru_filemapped >= before.ru_filemapped + PAGES);
+    assert(unmap(q, PAGES * PAGE) == 0);
+    assert(getrusage(RUSAGE_SELF, &after) == 0);
+    assert(after.ru_filemapped == before.ru_filemapped);
+    assert(remove(FILE) == 0);
+
+    /* More memory than there is goes to swap. Noise does not compress, so
+       reading it back takes disk I/O. */
+    for (int i = 0; i < BIG; i++) {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        big[i] = (char)x;
+    }
+    assert(getrusage(RUSAGE_SELF, &before) == 0);
+    assert(before.ru_swapped > 0 && before.ru_nevict >= before.ru_swapped);
+    x = 1;
+    for (int i = 0; i < BIG; i++) {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        assert(big[i] == (char)x);
+    }
+    assert(getrusage(RUSAGE_SELF, &after) == 0);
+    assert(after.ru_majflt > before.ru_majflt);
+    assert(after.ru_nswapin > before.ru_nswapin);
+    assert(after.ru_nevict >= before.ru_nevict);
+}
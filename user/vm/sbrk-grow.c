@@ -0,0 +1,42 @@
+/* Grows the heap with sbrk, checks that new memory reads as zero, then
+   shrinks it back. Touching memory past the break kills the process. */
+
+#include "user.h"
+
+#define SIZE (5 * 4096 + 123)
+
+void main() {
+    char* start = sbrk(0);
+    char* p;
+    int pid;
+
+    assert(start != MAP_ERROR);
+    assert(sbrk(SIZE) == start);
+    assert(sbrk(0) == start + SIZE);
+
+    for (int i = 0; i < SIZE; i++) assert(start[i] == 0);
+    for (int i = 0; i < SIZE; i++) start[i] = i;
+    for (int i = 0; i < SIZE; i++) assert(start[i] == (char)i);
+
+    /* A forked child gets its own copy of the heap. */
+    if ((pid = fork()) == 0) {
+        start[0] = 42;
+        assert(sbrk(4096) == start + SIZE);
+        exit(start[0]);
+    }
+    assert(wait(pid) == 42);
+    assert(start[0] == 0);
+    assert(sbrk(0) == start + SIZE);
+
+    assert(sbrk(-SIZE) == start + SIZE);
+    assert(sbrk(0) == start);
+    assert(sbrk(-4096) == MAP_ERROR);
+
+    /* The pages are gone. */
+    if ((pid = fork()) == 0) {
+        p = start;
+        *p = 1;
+        exit(0);
+    }
+    assert(wait(pid) == -1);
+}
@@ -0,0 +1,67 @@
+/* Installs handlers, sends signals to itself and to a child, and
+   checks that blocked signals wait until they are unblocked. A
+   fault without a handler kills the process. */
+
+#include "user.h"
+
+static volatile int got_usr1, got_usr2;
+
+static void on_usr1(int sig) {
+    assert(sig == SIGUSR1);
+    got_usr1++;
+}
+
+static void on_usr2(int sig) {
+    assert(sig == SIGUSR2);
+    /* SIGUSR1 is in sa_mask, so it must wait until we return. */
+    kill(getpid(), SIGUSR1);
+    assert(got_usr1 == 1);
+    got_usr2++;
+}
+
+void main() {
+    struct sigaction sa = {0}, old;
+    sigset_t set = sigmask(SIGUSR1);
+    int pid;
+
+    sa.sa_handler = on_usr1;
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    sa.sa_handler = on_usr2;
+    sa.sa_mask = sigmask(SIGUSR1);
+    assert(sigaction(SIGUSR2, &sa, &old) == 0);
+    assert(old.sa_handler == SIG_DFL);
+    assert(sigaction(SIGKILL, &sa, NULL) == -1);
+
+    kill(getpid(), SIGUSR1);
+    assert(got_usr1 == 1);
+    kill(getpid(), SIGUSR2);
+    assert(got_usr2 == 1 && got_usr1 == 2);
+
+    /* Blocked signals stay pending. */
+    assert(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+    kill(getpid(), SIGUSR1);
+    assert(got_usr1 == 2);
+    assert(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+    assert(got_usr1 == 3);
+
+    /* Ignored signals are discarded. */
+    sa.sa_handler = SIG_IGN;
+    assert(sigaction(SIGTERM, &sa, NULL) == 0);
+    kill(getpid(), SIGTERM);
+
+    /* Default action: termination. */
+    if ((pid = fork()) == 0) {
+        while (1)
+            ;
+    }
+    assert(kill(pid, SIGKILL) == 0);
+    assert(wait(pid) == -1);
+    assert(kill(pid, SIGKILL) == -1);
+
+    /* A fault without a handler. */
+    if ((pid = fork()) == 0) {
+        *(volatile int*)NULL = 1;
+        exit(0);
+    }
+    assert(wait(pid) == -1);
+}
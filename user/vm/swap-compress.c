@@ -0,0 +1,39 @@
+/* Swapped pages keep their contents whether they compress well or not, and
+   whether they end up in the compressed pool or on disk. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define PAGES 768
+
+static char buf[PAGES * PAGE];
+
+static unsigned int next(unsigned int* x) {
+    *x ^= *x << 13;
+    *x ^= *x >> 17;
+    *x ^= *x << 5;
+    return *x;
+}
+
+/* Page `i`: a repeated byte, a short pattern, or noise. */
+static void fill(int i, char* page, int check) {
+    unsigned int x = i + 1;
+    for (int j = 0; j < PAGE; j++) {
+        char c;
+        switch (i % 3) {
+            case 0: c = (char)i; break;
+            case 1: c = (char)(j % 13 + i); break;
+            default: c = (char)next(&x); break;
+        }
+        if (check)
+            assert(page[j] == c);
+        else
+            page[j] = c;
+    }
+}
+
+void main() {
+    for (int i = 0; i < PAGES; i++) fill(i, buf + i * PAGE, 0);
+    for (int round = 0; round < 2; round++)
+        for (int i = 0; i < PAGES; i++) fill(i, buf + i * PAGE, 1);
+}
@@ -0,0 +1,41 @@
+/* Processes running the same executable share its code pages, yet one that
+   makes its code writable and patches it only changes its own copy. */
+
+#include "user.h"
+
+#define PAGE 4096
+#define CHILD_CNT 4
+
+__attribute__((noinline, aligned(16))) int answer(void) {
+    return 1;
+}
+
+static int call(int (*volatile f)(void)) {
+    return f();
+}
+
+void main(int argc, char* argv[]) {
+    const char* patch[] = {"text-share", "patch", 0};
+    const char* plain[] = {"text-share", "plain", 0};
+    int children[CHILD_CNT];
+    unsigned int* code;
+    void* page;
+
+    if (argc > 1 && strcmp(argv[1], "patch") == 0) {
+        code = (unsigned int*)answer;
+        page = (void*)((uint64)code & ~(PAGE - 1));
+        assert(mprotect(page, PAGE, PROT_READ | PROT_WRITE | PROT_EXEC) == 0);
+        code[0] = 0x02a00513; /* li a0, 42 */
+        code[1] = 0x00008067; /* ret */
+        asm volatile("fence.i");
+        exit(call(answer));
+    }
+    if (argc > 1) exit(call(answer));
+
+    for (int i = 0; i < CHILD_CNT; i++) assert((children[i] = exec(patch[0], patch)) != -1);
+    for (int i = 0; i < CHILD_CNT; i++) assert(wait(children[i]) == 42);
+
+    assert(call(answer) == 1);
+    assert((children[0] = exec(plain[0], plain)) != -1);
+    assert(wait(children[0]) == 1);
+}